@@ -0,0 +1,319 @@
+use crate::retry;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.cloudflare.com/client/v4/";
+
+/// Cloudflare allows up to 50 zones and 5000 DNS records per page, zones need the smaller one.
+const PAGE_SIZE: u64 = 50;
+
+pub type Result<T> = std::result::Result<T, CloudflareError>;
+
+/// A single entry of the `errors` array Cloudflare returns with every failed request.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiError {
+    pub code: u64,
+    pub message: String,
+}
+
+#[derive(Debug, Error)]
+pub enum CloudflareError {
+    #[error("Cloudflare API token is invalid or lacks a permission: {}", messages(.0))]
+    Unauthorized(Vec<ApiError>),
+    #[error("Cloudflare API request failed ({status}): {}", messages(.errors))]
+    Api { status: StatusCode, errors: Vec<ApiError> },
+    #[error("Cloudflare API request failed: {0}")]
+    Request(#[from] reqwest::Error),
+}
+
+fn messages(errors: &[ApiError]) -> String {
+    if errors.is_empty() {
+        return "no details given".to_string();
+    }
+    errors
+        .iter()
+        .map(|error| format!("{} ({})", error.message, error.code))
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+impl CloudflareError {
+    /// The credentials are wrong, retrying will not help until the token is replaced.
+    pub fn is_auth_failure(&self) -> bool {
+        matches!(self, CloudflareError::Unauthorized(_))
+    }
+
+    /// The failure is transient, and the same request may succeed later.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            CloudflareError::Api { status, .. } => *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+            CloudflareError::Request(error) => retry::is_transient_error(error),
+            CloudflareError::Unauthorized(_) => false,
+        }
+    }
+}
+
+/// Every Cloudflare API response comes in this envelope, successful or not.
+#[derive(Deserialize, Debug)]
+struct CloudflareResponse<T> {
+    success: bool,
+    #[serde(default)]
+    errors: Vec<ApiError>,
+    result: Option<T>,
+    result_info: Option<ResultInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResultInfo {
+    page: u64,
+    total_pages: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TokenStatus {
+    pub id: String,
+    /// `active`, `disabled` or `expired`
+    pub status: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Zone {
+    pub id: String,
+    pub name: String,
+    pub status: String,
+    #[serde(default)]
+    pub name_servers: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DnsRecordResponse {
+    pub id: String,
+    /// Fully qualified, e.g. `node-1.k8s.example.com`
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub content: String,
+    pub ttl: u64,
+    #[serde(default)]
+    pub proxied: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DnsRecordRequest {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub content: String,
+    pub ttl: u64,
+    /// The node records must resolve to the nodes themselves, never to the Cloudflare proxies.
+    pub proxied: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct DeletedRecord {
+    #[allow(dead_code)]
+    id: String,
+}
+
+/// Minimal Cloudflare API client, just what is needed for this app.
+#[derive(Clone)]
+pub struct Client {
+    client: reqwest::Client,
+    base_url: String,
+    token: String,
+    max_retries: u32,
+    zones: Arc<Mutex<HashMap<String, Zone>>>,
+}
+
+impl Client {
+    pub fn new(token: &str) -> Client {
+        Client {
+            client: reqwest::Client::new(),
+            base_url: String::from(DEFAULT_BASE_URL),
+            token: String::from(token),
+            max_retries: retry::DEFAULT_MAX_RETRIES,
+            zones: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// How many times to retry rate-limited requests, or idempotent requests that failed with a server error.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Client {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Talk to a different API endpoint, e.g. a proxy or a mock server. Endpoint paths are appended to it.
+    pub fn with_base_url(mut self, base_url: &str) -> Client {
+        self.base_url = if base_url.ends_with('/') {
+            String::from(base_url)
+        } else {
+            format!("{}/", base_url)
+        };
+        self
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}{}", self.base_url, path);
+        self.client.request(method, &url).bearer_auth(&self.token)
+    }
+
+    /// Send the request with the same retries as the Linode client, see [`retry`]
+    async fn execute(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let request = request.build()?;
+        let idempotent = retry::is_idempotent(&request);
+        let mut attempt = 0;
+        loop {
+            let attempt_request = request
+                .try_clone()
+                .expect("Cloudflare API requests have cloneable bodies");
+            let delay = match self.client.execute(attempt_request).await {
+                Ok(response) => {
+                    let status = response.status();
+                    if status.is_success()
+                        || !retry::is_retryable_status(status, idempotent)
+                        || attempt >= self.max_retries
+                    {
+                        return Ok(response);
+                    }
+                    let delay = retry::retry_after(response.headers()).unwrap_or_else(|| retry::backoff(attempt));
+                    warn!(
+                        status = status.as_u16(),
+                        url = request.url().as_str(),
+                        attempt,
+                        delay_ms = delay.as_millis() as u64,
+                        "Cloudflare API request failed, retrying"
+                    );
+                    delay
+                }
+                Err(error) if retry::is_retryable_error(&error, idempotent) && attempt < self.max_retries => {
+                    let delay = retry::backoff(attempt);
+                    warn!(
+                        error = format!("{}", error).as_str(),
+                        url = request.url().as_str(),
+                        attempt,
+                        delay_ms = delay.as_millis() as u64,
+                        "Cloudflare API request failed, retrying"
+                    );
+                    delay
+                }
+                Err(error) => return Err(error.into()),
+            };
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+            debug!(url = request.url().as_str(), attempt, "Retrying Cloudflare API request");
+        }
+    }
+
+    /// Send the request and unwrap the response envelope
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<(T, Option<ResultInfo>)> {
+        let response = self.execute(request).await?;
+        let status = response.status();
+        // Error responses are wrapped in the envelope too, but fall back to the status code if they are not.
+        let body = response.json::<CloudflareResponse<T>>().await;
+        match body {
+            Ok(CloudflareResponse {
+                success: true,
+                result: Some(result),
+                result_info,
+                ..
+            }) if status.is_success() => Ok((result, result_info)),
+            Ok(body) => Err(Self::error(status, body.errors)),
+            Err(_) if status.is_success() => Err(Self::error(StatusCode::BAD_GATEWAY, vec![])),
+            Err(_) => Err(Self::error(status, vec![])),
+        }
+    }
+
+    fn error(status: StatusCode, errors: Vec<ApiError>) -> CloudflareError {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => CloudflareError::Unauthorized(errors),
+            status => CloudflareError::Api { status, errors },
+        }
+    }
+
+    /// All items of a list endpoint, walking through the pages
+    async fn get_list<T: DeserializeOwned>(&self, endpoint: &str, query: &[(&str, &str)]) -> Result<Vec<T>> {
+        let mut items = vec![];
+        let mut page = 1;
+        loop {
+            let request = self
+                .request(reqwest::Method::GET, endpoint)
+                .query(query)
+                .query(&[("page", page), ("per_page", PAGE_SIZE)]);
+            let (result, result_info) = self.send::<Vec<T>>(request).await?;
+            items.extend(result);
+            match result_info {
+                Some(info) if info.page < info.total_pages => page = info.page + 1,
+                _ => return Ok(items),
+            }
+        }
+    }
+
+    /// The zone with the given name, looked up once and remembered.
+    pub async fn zone(&self, name: &str) -> Result<Option<Zone>> {
+        if let Some(zone) = self.zones.lock().unwrap().get(name) {
+            return Ok(Some(zone.clone()));
+        }
+        let zone = self
+            .get_list::<Zone>("zones", &[("name", name)])
+            .await?
+            .into_iter()
+            .find(|zone| zone.name == name);
+        if let Some(zone) = &zone {
+            self.zones.lock().unwrap().insert(name.to_string(), zone.clone());
+        }
+        Ok(zone)
+    }
+
+    /// Whether the token itself is valid, regardless of its permissions
+    pub async fn verify_token(&self) -> Result<TokenStatus> {
+        let request = self.request(reqwest::Method::GET, "user/tokens/verify");
+        Ok(self.send(request).await?.0)
+    }
+
+    /// Records with the given fully qualified name, of any type
+    pub async fn get_dns_records(&self, zone_id: &str, name: &str) -> Result<Vec<DnsRecordResponse>> {
+        self.get_list(&format!("zones/{}/dns_records", zone_id), &[("name", name)])
+            .await
+    }
+
+    /// All records of the zone
+    pub async fn get_all_dns_records(&self, zone_id: &str) -> Result<Vec<DnsRecordResponse>> {
+        self.get_list(&format!("zones/{}/dns_records", zone_id), &[]).await
+    }
+
+    pub async fn create_dns_record(&self, zone_id: &str, record: &DnsRecordRequest) -> Result<DnsRecordResponse> {
+        let request = self
+            .request(reqwest::Method::POST, &format!("zones/{}/dns_records", zone_id))
+            .json(record);
+        Ok(self.send(request).await?.0)
+    }
+
+    pub async fn update_dns_record(
+        &self,
+        zone_id: &str,
+        record_id: &str,
+        record: &DnsRecordRequest,
+    ) -> Result<DnsRecordResponse> {
+        let request = self
+            .request(
+                reqwest::Method::PUT,
+                &format!("zones/{}/dns_records/{}", zone_id, record_id),
+            )
+            .json(record);
+        Ok(self.send(request).await?.0)
+    }
+
+    pub async fn delete_dns_record(&self, zone_id: &str, record_id: &str) -> Result<()> {
+        let request = self.request(
+            reqwest::Method::DELETE,
+            &format!("zones/{}/dns_records/{}", zone_id, record_id),
+        );
+        self.send::<DeletedRecord>(request).await?;
+        Ok(())
+    }
+}
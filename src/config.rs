@@ -0,0 +1,505 @@
+//! Configuration of the controller: an optional YAML file, overridden by the environment variables, overridden by
+//! the command line flags. Every setting has an environment variable, e.g. `DNS_RECORD_TTL`, and the matching flag,
+//! e.g. `--dns-record-ttl`. The whole configuration is validated at startup, before anything is reconciled.
+
+use crate::naming::{self, NameTemplate};
+use crate::selection::NodeSelector;
+use crate::{cloudflare, health, leader, linode, provider, registry, server};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Environment variable with the path of the configuration file, also given by `--config`
+pub const CONFIG_FILE_VAR: &str = "CONFIG_FILE";
+
+/// How often to check the records that are not visible on the authoritative name servers yet
+pub const DEFAULT_PROPAGATION_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long the records may take to become visible before the reconcile is considered failed. Linode usually
+/// takes a few minutes to publish changes.
+pub const DEFAULT_PROPAGATION_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// How often to compare the whole zone with the nodes, to catch what the node events missed
+pub const DEFAULT_RESYNC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How soon to retry after a transient failure (network, rate limits, API server errors)
+pub const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Failures like a missing domain or a rejected record will not fix themselves quickly, but may be fixed by
+/// an operator without restarting the controller, so do check back occasionally.
+pub const DEFAULT_PERMANENT_FAILURE_RETRY_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Deletes the records of a node before the node itself is deleted
+pub const DEFAULT_FINALIZER: &str = "k8s.haim.dev/linode-dns-finalizer";
+
+/// Environment variable of every setting, with its description for `--help`. The flags are the same names in
+/// lower case, with dashes.
+const SETTINGS: [(&str, &str); 32] = [
+    (
+        "NODE_DOMAIN",
+        "Domain of the node records, e.g. k8s.example.com (required)",
+    ),
+    (
+        "INTERNAL_DOMAIN",
+        "Domain of the internal address records, relative to NODE_DOMAIN or fully qualified",
+    ),
+    ("DNS_NAME_TEMPLATE", "Template of the node names, e.g. {hostname}"),
+    (
+        "NODE_SELECTOR",
+        "Label selector of the nodes to publish, all of them by default",
+    ),
+    (
+        "DNS_OWNER_ID",
+        "Owner of the records, to tell apart the clusters sharing a zone",
+    ),
+    ("DNS_RECORD_TTL", "TTL of the records, in seconds"),
+    (
+        "DNS_PROPAGATION_POLL_INTERVAL",
+        "Seconds between the checks of the name servers",
+    ),
+    (
+        "DNS_PROPAGATION_TIMEOUT",
+        "Seconds before records still not visible are reported as failed",
+    ),
+    (
+        "DNS_RESYNC_INTERVAL",
+        "Seconds between the resyncs of the zone, 0 to disable them",
+    ),
+    ("RETRY_INTERVAL", "Seconds before a failed reconcile is retried"),
+    (
+        "PERMANENT_FAILURE_RETRY_INTERVAL",
+        "Seconds before a reconcile failed permanently is retried",
+    ),
+    ("FINALIZER", "Finalizer set on the nodes with records"),
+    (
+        "DNS_PROVIDER",
+        "Provider of the forward records: linode, cloudflare or rfc2136",
+    ),
+    (
+        "RDNS_PROVIDER",
+        "Provider of the reverse DNS: linode, rfc2136 or none, DNS_PROVIDER by default",
+    ),
+    ("LINODE_API_TOKEN", "Linode API token"),
+    (
+        "LINODE_API_TOKEN_FILE",
+        "File with the Linode API token, re-read when it changes",
+    ),
+    ("LINODE_API_URL", "Linode API base URL"),
+    (
+        "LINODE_NAME_SERVERS",
+        "Comma-separated Linode name servers, to verify the records",
+    ),
+    ("CLOUDFLARE_API_TOKEN", "Cloudflare API token"),
+    ("CLOUDFLARE_API_URL", "Cloudflare API base URL"),
+    (
+        "RFC2136_SERVER",
+        "Primary DNS server accepting the updates, host[:port]",
+    ),
+    ("RFC2136_TSIG_KEY_NAME", "Name of the TSIG key signing the updates"),
+    ("RFC2136_TSIG_SECRET", "Base64 secret of the TSIG key"),
+    ("RFC2136_TSIG_ALGORITHM", "hmac-sha256 or hmac-sha512"),
+    (
+        "RFC2136_REVERSE_ZONES",
+        "Comma-separated reverse zones where PTR records can be updated",
+    ),
+    (
+        "HTTP_LISTEN_ADDRESS",
+        "Address and port of the metrics and probes server",
+    ),
+    (
+        "LIVENESS_TIMEOUT",
+        "Seconds a reconcile may run, or the controller wait for its first one, before it is considered stuck",
+    ),
+    ("LEADER_ELECTION", "true to elect a leader among the replicas"),
+    ("LEADER_ELECTION_LEASE", "Name of the leader election Lease"),
+    (
+        "POD_NAMESPACE",
+        "Namespace of the leader election Lease and of the events",
+    ),
+    ("POD_NAME", "Identity of the replica, in the Lease and the events"),
+    (
+        "PREFLIGHT",
+        "false to skip the startup checks of the token scopes, the zones and their delegation",
+    ),
+];
+
+/// Replaces the secrets in the printed configuration
+const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Cannot read the configuration file {0}: {1}")]
+    ReadFile(String, #[source] std::io::Error),
+    #[error("Invalid configuration file {0}: {1}")]
+    ParseFile(String, #[source] serde_yaml::Error),
+    #[error("Unknown option {0}, see --help")]
+    UnknownOption(String),
+    #[error("Option {0} needs a value")]
+    MissingValue(String),
+    #[error("Invalid value {1:?} of {0}: {2}")]
+    InvalidValue(String, String, String),
+    #[error("Invalid configuration: {}", .0.join("; "))]
+    Invalid(Vec<String>),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct Config {
+    pub node_domain: String,
+    pub internal_domain: Option<String>,
+    pub name_template: String,
+    /// Empty for all the nodes
+    pub node_selector: String,
+    pub owner_id: String,
+    /// Seconds
+    pub record_ttl: u64,
+    #[serde(with = "seconds")]
+    pub propagation_poll_interval: Duration,
+    #[serde(with = "seconds")]
+    pub propagation_timeout: Duration,
+    /// Zero disables the periodic resync
+    #[serde(with = "seconds")]
+    pub resync_interval: Duration,
+    #[serde(with = "seconds")]
+    pub retry_interval: Duration,
+    #[serde(with = "seconds")]
+    pub permanent_failure_retry_interval: Duration,
+    pub finalizer: String,
+    pub dns_provider: String,
+    /// The same as `dns_provider` when not set
+    pub rdns_provider: Option<String>,
+    pub linode: LinodeConfig,
+    pub cloudflare: CloudflareConfig,
+    pub rfc2136: Rfc2136Config,
+    pub http_listen_address: SocketAddr,
+    #[serde(with = "seconds")]
+    pub liveness_timeout: Duration,
+    pub leader_election: bool,
+    pub leader_election_lease: String,
+    pub pod_namespace: String,
+    pub pod_name: Option<String>,
+    /// Check the credentials, the zones and their delegation at startup, see [`crate::preflight`]
+    pub preflight: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct LinodeConfig {
+    pub api_token: Option<String>,
+    /// Instead of `api_token`, e.g. a mounted Secret, so that the token can be rotated without a restart
+    pub api_token_file: Option<String>,
+    pub api_url: String,
+    pub name_servers: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct CloudflareConfig {
+    pub api_token: Option<String>,
+    pub api_url: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct Rfc2136Config {
+    pub server: Option<String>,
+    pub tsig_key_name: Option<String>,
+    pub tsig_secret: Option<String>,
+    /// `hmac-sha256` when not set
+    pub tsig_algorithm: Option<String>,
+    pub reverse_zones: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            node_domain: String::new(),
+            internal_domain: None,
+            name_template: naming::DEFAULT_TEMPLATE.to_string(),
+            node_selector: String::new(),
+            owner_id: registry::DEFAULT_OWNER_ID.to_string(),
+            record_ttl: registry::DEFAULT_RECORD_TTL,
+            propagation_poll_interval: DEFAULT_PROPAGATION_POLL_INTERVAL,
+            propagation_timeout: DEFAULT_PROPAGATION_TIMEOUT,
+            resync_interval: DEFAULT_RESYNC_INTERVAL,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            permanent_failure_retry_interval: DEFAULT_PERMANENT_FAILURE_RETRY_INTERVAL,
+            finalizer: DEFAULT_FINALIZER.to_string(),
+            dns_provider: "linode".to_string(),
+            rdns_provider: None,
+            linode: LinodeConfig::default(),
+            cloudflare: CloudflareConfig::default(),
+            rfc2136: Rfc2136Config::default(),
+            http_listen_address: server::DEFAULT_ADDRESS.parse().expect("Default address is valid"),
+            liveness_timeout: health::DEFAULT_LIVENESS_TIMEOUT,
+            leader_election: false,
+            leader_election_lease: leader::DEFAULT_LEASE_NAME.to_string(),
+            pod_namespace: "default".to_string(),
+            pod_name: None,
+            preflight: true,
+        }
+    }
+}
+
+impl Default for LinodeConfig {
+    fn default() -> Self {
+        LinodeConfig {
+            api_token: None,
+            api_token_file: None,
+            api_url: linode::DEFAULT_BASE_URL.to_string(),
+            name_servers: linode::NAME_SERVERS.iter().map(|ns| ns.to_string()).collect(),
+        }
+    }
+}
+
+impl Default for CloudflareConfig {
+    fn default() -> Self {
+        CloudflareConfig {
+            api_token: None,
+            api_url: cloudflare::DEFAULT_BASE_URL.to_string(),
+        }
+    }
+}
+
+/// What the command line asks for
+#[derive(Debug)]
+pub enum Command {
+    Run(Config),
+    PrintConfig(Config),
+    Help,
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, ConfigError>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|error: T::Err| ConfigError::InvalidValue(name.to_string(), value.to_string(), error.to_string()))
+}
+
+fn parse_seconds(name: &str, value: &str) -> Result<Duration, ConfigError> {
+    parse(name, value).map(Duration::from_secs)
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// `NODE_DOMAIN` from `--node-domain`
+fn setting_of_flag(flag: &str) -> Option<&'static str> {
+    let name = flag.strip_prefix("--")?.to_ascii_uppercase().replace('-', "_");
+    SETTINGS
+        .iter()
+        .map(|(setting, _)| *setting)
+        .find(|setting| *setting == name)
+}
+
+impl Config {
+    /// The configuration from the command line arguments (without the program name), the environment variables
+    /// and the configuration file, validated.
+    pub fn load<E>(args: &[String], env: E) -> Result<Command, ConfigError>
+    where
+        E: Fn(&str) -> Option<String>,
+    {
+        let mut path = env(CONFIG_FILE_VAR);
+        let mut print_config = false;
+        let mut flags = vec![];
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            match flag {
+                "-h" | "--help" => return Ok(Command::Help),
+                "--print-config" => print_config = true,
+                _ => {
+                    let name = match flag {
+                        "-c" | "--config" => CONFIG_FILE_VAR,
+                        _ => setting_of_flag(flag).ok_or_else(|| ConfigError::UnknownOption(flag.to_string()))?,
+                    };
+                    let value = inline_value
+                        .or_else(|| args.next().cloned())
+                        .ok_or_else(|| ConfigError::MissingValue(flag.to_string()))?;
+                    if name == CONFIG_FILE_VAR {
+                        path = Some(value);
+                    } else {
+                        flags.push((name, value));
+                    }
+                }
+            }
+        }
+
+        let mut config = match path {
+            Some(path) => Config::from_file(&path)?,
+            None => Config::default(),
+        };
+        for (name, _) in SETTINGS.iter() {
+            if let Some(value) = env(name) {
+                config.set(name, &value)?;
+            }
+        }
+        for (name, value) in flags {
+            config.set(name, &value)?;
+        }
+        config.validate()?;
+        Ok(if print_config {
+            Command::PrintConfig(config)
+        } else {
+            Command::Run(config)
+        })
+    }
+
+    pub fn from_file(path: &str) -> Result<Config, ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|error| ConfigError::ReadFile(path.to_string(), error))?;
+        serde_yaml::from_str(&content).map_err(|error| ConfigError::ParseFile(path.to_string(), error))
+    }
+
+    /// Override a setting, by the name of its environment variable
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), ConfigError> {
+        let optional = |value: &str| Some(value.to_string()).filter(|value| !value.is_empty());
+        match name {
+            "NODE_DOMAIN" => self.node_domain = value.to_string(),
+            "INTERNAL_DOMAIN" => self.internal_domain = optional(value),
+            "DNS_NAME_TEMPLATE" => self.name_template = value.to_string(),
+            "NODE_SELECTOR" => self.node_selector = value.to_string(),
+            "DNS_OWNER_ID" => self.owner_id = value.to_string(),
+            "DNS_RECORD_TTL" => self.record_ttl = parse(name, value)?,
+            "DNS_PROPAGATION_POLL_INTERVAL" => self.propagation_poll_interval = parse_seconds(name, value)?,
+            "DNS_PROPAGATION_TIMEOUT" => self.propagation_timeout = parse_seconds(name, value)?,
+            "DNS_RESYNC_INTERVAL" => self.resync_interval = parse_seconds(name, value)?,
+            "RETRY_INTERVAL" => self.retry_interval = parse_seconds(name, value)?,
+            "PERMANENT_FAILURE_RETRY_INTERVAL" => self.permanent_failure_retry_interval = parse_seconds(name, value)?,
+            "FINALIZER" => self.finalizer = value.to_string(),
+            "DNS_PROVIDER" => self.dns_provider = value.to_string(),
+            "RDNS_PROVIDER" => self.rdns_provider = optional(value),
+            "LINODE_API_TOKEN" => self.linode.api_token = optional(value),
+            "LINODE_API_TOKEN_FILE" => self.linode.api_token_file = optional(value),
+            "LINODE_API_URL" => self.linode.api_url = value.to_string(),
+            "LINODE_NAME_SERVERS" => self.linode.name_servers = parse_list(value),
+            "CLOUDFLARE_API_TOKEN" => self.cloudflare.api_token = optional(value),
+            "CLOUDFLARE_API_URL" => self.cloudflare.api_url = value.to_string(),
+            "RFC2136_SERVER" => self.rfc2136.server = optional(value),
+            "RFC2136_TSIG_KEY_NAME" => self.rfc2136.tsig_key_name = optional(value),
+            "RFC2136_TSIG_SECRET" => self.rfc2136.tsig_secret = optional(value),
+            "RFC2136_TSIG_ALGORITHM" => self.rfc2136.tsig_algorithm = optional(value),
+            "RFC2136_REVERSE_ZONES" => self.rfc2136.reverse_zones = parse_list(value),
+            "HTTP_LISTEN_ADDRESS" => self.http_listen_address = parse(name, value)?,
+            "LIVENESS_TIMEOUT" => self.liveness_timeout = parse_seconds(name, value)?,
+            "LEADER_ELECTION" => self.leader_election = parse(name, value)?,
+            "LEADER_ELECTION_LEASE" => self.leader_election_lease = value.to_string(),
+            "POD_NAMESPACE" => self.pod_namespace = value.to_string(),
+            "POD_NAME" => self.pod_name = optional(value),
+            "PREFLIGHT" => self.preflight = parse(name, value)?,
+            _ => return Err(ConfigError::UnknownOption(name.to_string())),
+        }
+        Ok(())
+    }
+
+    /// The reverse DNS provider, `none` when reverse DNS is not managed
+    pub fn reverse_provider(&self) -> &str {
+        self.rdns_provider.as_deref().unwrap_or(&self.dns_provider)
+    }
+
+    /// All the problems at once, so that they can be fixed in one go
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = vec![];
+        if self.node_domain.trim().is_empty() {
+            problems.push("NODE_DOMAIN is required".to_string());
+        }
+        if let Err(error) = self.name_template.parse::<NameTemplate>() {
+            problems.push(format!("DNS_NAME_TEMPLATE: {}", error));
+        }
+        if let Err(error) = self.node_selector.parse::<NodeSelector>() {
+            problems.push(format!("NODE_SELECTOR: {}", error));
+        }
+        if let Err(error) = provider::from_config(self) {
+            problems.push(error.to_string());
+        }
+        // Zone transfers are not implemented, so resync would fail every time
+        if self.dns_provider == "rfc2136" && !self.resync_interval.is_zero() {
+            problems
+                .push("DNS_RESYNC_INTERVAL must be 0 with DNS_PROVIDER=rfc2136, which cannot list a zone".to_string());
+        }
+        if self.record_ttl == 0 {
+            problems.push("DNS_RECORD_TTL must be at least 1 second".to_string());
+        }
+        if self.propagation_poll_interval.is_zero() {
+            problems.push("DNS_PROPAGATION_POLL_INTERVAL must be at least 1 second".to_string());
+        }
+        if self.propagation_timeout < self.propagation_poll_interval {
+            problems.push("DNS_PROPAGATION_TIMEOUT must be longer than DNS_PROPAGATION_POLL_INTERVAL".to_string());
+        }
+        if self.retry_interval.is_zero() || self.permanent_failure_retry_interval.is_zero() {
+            problems.push("RETRY_INTERVAL and PERMANENT_FAILURE_RETRY_INTERVAL must be at least 1 second".to_string());
+        }
+        if !self.finalizer.contains('/') {
+            problems.push(format!(
+                "FINALIZER {:?} must be qualified by a domain, e.g. {}",
+                self.finalizer, DEFAULT_FINALIZER
+            ));
+        }
+        if self.liveness_timeout.is_zero() {
+            problems.push("LIVENESS_TIMEOUT must be at least 1 second".to_string());
+        }
+        if self.leader_election && self.leader_election_lease.is_empty() {
+            problems.push("LEADER_ELECTION_LEASE is required with LEADER_ELECTION".to_string());
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    /// YAML of the configuration, the secrets redacted, in the format of the configuration file
+    pub fn to_yaml(&self) -> String {
+        let redact = |secret: &Option<String>| secret.as_ref().map(|_| REDACTED.to_string());
+        let mut config = self.clone();
+        config.linode.api_token = redact(&self.linode.api_token);
+        config.cloudflare.api_token = redact(&self.cloudflare.api_token);
+        config.rfc2136.tsig_secret = redact(&self.rfc2136.tsig_secret);
+        serde_yaml::to_string(&config).expect("Configuration is always serializable")
+    }
+}
+
+/// Text of `--help`
+pub fn usage() -> String {
+    let mut usage = format!(
+        "Usage: node-dns [OPTIONS]\n\n\
+         Options:\n  \
+         -c, --config <PATH>  YAML configuration file [env: {}]\n      \
+         --print-config   Print the configuration and exit\n  \
+         -h, --help           Print this help and exit\n\n\
+         Settings, overriding the configuration file and the environment variables:\n",
+        CONFIG_FILE_VAR
+    );
+    for (name, description) in SETTINGS.iter() {
+        let flag = format!("--{} <VALUE>", name.to_ascii_lowercase().replace('_', "-"));
+        usage.push_str(&format!("      {:<44} {} [env: {}]\n", flag, description, name));
+    }
+    usage
+}
+
+/// Durations as a number of seconds, like the environment variables
+mod seconds {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_secs)
+    }
+}
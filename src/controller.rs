@@ -1,40 +1,165 @@
-use crate::dns;
+use crate::config::Config;
+use crate::dns::{self, Propagation, ResyncReport};
 use crate::errors::Error;
+use crate::health::Health;
+use crate::leader::LeaderElection;
+use crate::metrics;
+use crate::naming::{self, NameError, NameTemplate};
+use crate::preflight;
+use crate::provider::{self, Providers};
+use crate::registry::Registry;
+use crate::reporting::{self, EventType, Reporter, Status};
+use crate::selection::NodeSelector;
+use crate::server;
+use crate::state::{self, AppliedState};
 use anyhow::{Context, Result};
 use futures::StreamExt;
-use k8s_openapi::api::core::v1::Node;
+use k8s_openapi::api::core::v1::{Node, NodeAddress};
+use k8s_openapi::chrono::Utc;
 use kube::{
-    api::{Api, ListParams},
+    api::{Api, ListParams, Patch, PatchParams, ResourceExt},
     runtime::controller::{Context as ControllerContext, Controller, ReconcilerAction},
     runtime::finalizer::{finalizer, Event},
+    runtime::reflector::Store,
 };
-use lazy_static::lazy_static;
+use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::net::IpAddr;
-use std::sync::Mutex;
-use tracing::{trace, warn};
-
-lazy_static! {
-    /// Keep a cache of the things we reconciled successfully, to prevent excessive DNS / API traffic.
-    static ref CACHE: Mutex<HashMap<String, IpAddr>> = Mutex::new(HashMap::new());
-}
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, error, info, trace, warn};
+use trust_dns_resolver::TokioAsyncResolver;
 
 /// Data we want access to in error/reconcile calls
 struct ContextData {
     client: kube::Client,
     node_domain: String,
-    linode_api_token: String,
+    /// Where to publish the internal addresses, if anywhere
+    internal_domain: Option<dns::InternalDomain>,
+    /// `DNS_PROVIDER` and the reverse DNS provider, saved in the applied state
+    provider: String,
+    reverse_provider: String,
+    name_template: NameTemplate,
+    /// The nodes seen by the controller, for the names that depend on the other nodes, or must differ from theirs
+    store: Store<Node>,
+    /// Which nodes get records
+    selector: NodeSelector,
+    providers: Providers,
+    registry: Registry,
+    resolvers: dns::Resolvers,
+    reporter: Reporter,
+    health: Arc<Health>,
+    propagation_poll_interval: Duration,
+    propagation_timeout: Duration,
+    finalizer: String,
+    /// After a transient failure
+    retry_interval: Duration,
+    /// After a failure that needs a fix on the provider side
+    permanent_failure_retry_interval: Duration,
+    /// Since when the records of each host have been waiting to propagate
+    pending: Mutex<HashMap<String, Instant>>,
+    /// Address records of each node with up to date records, by node name, behind the gauges
+    managed: Mutex<HashMap<String, usize>>,
+}
+
+impl ContextData {
+    /// The state the records of the node are in once applied
+    fn applied_state(&self, node_addresses: &NodeAddresses) -> AppliedState {
+        AppliedState {
+            fqdn: format!("{}.{}", node_addresses.host_name, self.node_domain),
+            host_name: node_addresses.host_name.clone(),
+            internal_fqdn: self
+                .internal_domain
+                .as_ref()
+                .map(|internal_domain| internal_domain.fqdn(&node_addresses.host_name)),
+            provider: self.provider.clone(),
+            reverse_provider: self.reverse_provider.clone(),
+            ip_addresses: node_addresses.ip_addresses.clone(),
+            internal_ip_addresses: node_addresses.internal_ip_addresses.clone(),
+            applied_at: Utc::now(),
+        }
+    }
+
+    /// Whether the state was applied for these very names, addresses and providers
+    fn is_applied(&self, node_addresses: &NodeAddresses, applied: &AppliedState) -> bool {
+        applied.matches(&self.applied_state(node_addresses))
+    }
+
+    /// Address records published for the applied state: the forward and SPF glue records, and the internal ones
+    fn record_count(&self, applied: &AppliedState) -> usize {
+        let internal = match self.internal_domain {
+            Some(_) => dns::published_addresses(&applied.internal_ip_addresses).len(),
+            None => 0,
+        };
+        2 * dns::published_addresses(&applied.ip_addresses).len() + internal
+    }
+
+    /// Count the records of the node as up to date, or not when `applied` is `None`
+    fn set_managed(&self, node_name: &str, applied: Option<&AppliedState>) {
+        let mut managed = self.managed.lock().unwrap();
+        match applied {
+            Some(applied) => managed.insert(node_name.to_string(), self.record_count(applied)),
+            None => managed.remove(node_name),
+        };
+        update_gauges(&managed);
+    }
 }
 
+fn update_gauges(managed: &HashMap<String, usize>) {
+    metrics::MANAGED_NODES.set(managed.len());
+    metrics::MANAGED_RECORDS.set(managed.values().sum());
+}
+
+#[derive(Clone, Debug, PartialEq)]
 struct NodeAddresses {
+    /// `metadata.name`
+    node_name: String,
+    /// Name of the records, relative to the domain
     host_name: String,
-    ip_address: IpAddr,
+    /// All the external addresses, IPv4 and IPv6
+    ip_addresses: Vec<IpAddr>,
+    /// All the internal addresses, e.g. the private IP addresses, possibly none
+    internal_ip_addresses: Vec<IpAddr>,
 }
 
-impl TryFrom<Node> for NodeAddresses {
-    type Error = Error;
+fn parse_addresses(addresses: &[NodeAddress], type_: &str) -> Result<Vec<IpAddr>> {
+    addresses
+        .iter()
+        .filter(|address| address.type_ == type_)
+        .map(|address| {
+            address
+                .address
+                .parse()
+                .context(format!("{} {} is not a valid IP address", type_, address.address))
+        })
+        .collect()
+}
+
+/// The external addresses of the node, without the ones that cannot be parsed
+fn external_addresses(node: &Node) -> Vec<IpAddr> {
+    node.status
+        .as_ref()
+        .and_then(|status| status.addresses.as_ref())
+        .map(|addresses| {
+            addresses
+                .iter()
+                .filter(|address| address.type_ == "ExternalIP")
+                .filter_map(|address| address.address.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
 
-    fn try_from(node: Node) -> std::result::Result<Self, Self::Error> {
+impl NodeAddresses {
+    /// `nodes` are the nodes of the cluster, see [`NameTemplate::render_unique`]
+    fn new(
+        node: &Node,
+        name_template: &NameTemplate,
+        selector: &NodeSelector,
+        nodes: &[Node],
+    ) -> std::result::Result<Self, Error> {
+        let node_name = node.metadata.name.as_ref().ok_or(Error::UnnamedObject)?;
         let addresses = node
             .status
             .as_ref()
@@ -42,58 +167,203 @@ impl TryFrom<Node> for NodeAddresses {
             .addresses
             .as_ref()
             .ok_or(Error::MissingObjectKey(".status.addresses"))?;
-        let host_name = addresses
-            .iter()
-            .find(|address| address.type_ == "Hostname".to_string())
-            .as_ref()
-            .ok_or(Error::MissingObjectKey("status.addresses.Hostname"))?
-            .address
-            .as_str();
-        let ip_address: IpAddr = addresses
-            .iter()
-            .find(|address| address.type_ == "ExternalIP".to_string())
-            .as_ref()
-            .ok_or(Error::MissingObjectKey("status.addresses.ExternalIP"))?
-            .address
-            .as_str()
-            .parse()
-            .context("ExternalIP is not a valid IPv4 address")?;
+        let hostname = naming::hostname_of(node).ok_or(Error::MissingObjectKey("status.addresses.Hostname"))?;
+        let ip_addresses = parse_addresses(addresses, "ExternalIP")?;
+        if ip_addresses.is_empty() {
+            return Err(Error::MissingObjectKey("status.addresses.ExternalIP"));
+        }
         Ok(NodeAddresses {
-            host_name: host_name.to_string(),
-            ip_address,
+            node_name: node_name.clone(),
+            host_name: name_template.render_unique(node, hostname, nodes, |other| selector.matches(other))?,
+            ip_addresses,
+            internal_ip_addresses: parse_addresses(addresses, "InternalIP")?,
         })
     }
 }
 
 async fn apply(node: Node, ctx: ControllerContext<ContextData>) -> Result<ReconcilerAction, Error> {
-    let node_addresses = NodeAddresses::try_from(node)?;
-    if CACHE.lock().unwrap().get(node_addresses.host_name.as_str()) == Some(&node_addresses.ip_address) {
+    let data = ctx.get_ref();
+    let node_addresses = NodeAddresses::new(&node, &data.name_template, &data.selector, &data.store.state())?;
+    // Skip the DNS and API traffic for the nodes reconciled successfully, even by a previous instance
+    let applied = AppliedState::from_node(&node);
+    if let Some(applied) = applied
+        .as_ref()
+        .filter(|applied| data.is_applied(&node_addresses, applied))
+    {
+        data.set_managed(&node_addresses.node_name, Some(applied));
         return Ok(ReconcilerAction { requeue_after: None });
     }
-    dns::update(
-        ctx.get_ref().linode_api_token.as_str(),
-        ctx.get_ref().node_domain.as_str(),
+    data.set_managed(&node_addresses.node_name, None);
+    let mut outcome = dns::update(
+        &data.providers,
+        &data.registry,
+        &data.resolvers,
+        data.node_domain.as_str(),
         node_addresses.host_name.as_str(),
-        node_addresses.ip_address,
+        &node_addresses.ip_addresses,
     )
     .await?;
-    CACHE
-        .lock()
-        .unwrap()
-        .insert(node_addresses.host_name, node_addresses.ip_address);
+    if let Some(internal_domain) = &data.internal_domain {
+        let internal = dns::update_internal(
+            data.providers.forward.as_ref(),
+            &data.registry,
+            &data.resolvers.forward,
+            internal_domain,
+            node_addresses.host_name.as_str(),
+            &node_addresses.internal_ip_addresses,
+        )
+        .await?;
+        outcome.merge(internal);
+    }
+    for change in outcome.changes.iter() {
+        data.reporter.changed(&node, change).await;
+    }
+    let fqdn = format!("{}.{}", node_addresses.host_name, data.node_domain);
+    let published_addresses = dns::published_addresses(&node_addresses.ip_addresses);
+    if outcome.propagation == Propagation::Pending {
+        data.reporter
+            .annotate(&node, &fqdn, &published_addresses, Status::Pending)
+            .await;
+        let since = *data
+            .pending
+            .lock()
+            .unwrap()
+            .entry(node_addresses.host_name.clone())
+            .or_insert_with(Instant::now);
+        if since.elapsed() < data.propagation_timeout {
+            debug!(
+                host_name = node_addresses.host_name.as_str(),
+                "DNS records are not visible yet, checking again later"
+            );
+            return Ok(ReconcilerAction {
+                requeue_after: Some(data.propagation_poll_interval),
+            });
+        }
+        // Start over, in case the records were changed by someone else in the meantime
+        data.pending.lock().unwrap().remove(node_addresses.host_name.as_str());
+        return Err(Error::PropagationTimeout(
+            node_addresses.host_name,
+            data.propagation_timeout,
+        ));
+    }
+    data.pending.lock().unwrap().remove(node_addresses.host_name.as_str());
+    // Only once the new records are visible, so that the node stays reachable through the old ones meanwhile
+    if let Some(applied) = applied
+        .as_ref()
+        .filter(|applied| applied.host_name != node_addresses.host_name)
+    {
+        info!(
+            old_host_name = applied.host_name.as_str(),
+            new_host_name = node_addresses.host_name.as_str(),
+            "Node DNS name changed"
+        );
+        // The SPF glue records are per address, not per name, the ones still in use stay
+        dns::delete_name(
+            data.providers.forward.as_ref(),
+            &data.registry,
+            data.node_domain.as_str(),
+            applied.host_name.as_str(),
+        )
+        .await?;
+        if let Some(internal_domain) = &data.internal_domain {
+            dns::delete_internal(
+                data.providers.forward.as_ref(),
+                &data.registry,
+                internal_domain,
+                applied.host_name.as_str(),
+            )
+            .await?;
+        }
+    }
+    if let Some(applied) = applied.filter(|applied| applied.ip_addresses != node_addresses.ip_addresses) {
+        let old_ip_addresses = applied.ip_addresses;
+        info!(
+            host_name = node_addresses.host_name.as_str(),
+            ?old_ip_addresses,
+            new_ip_addresses = ?node_addresses.ip_addresses,
+            "Node IP addresses changed"
+        );
+        // An address can move to another node, which then needs its SPF glue record
+        let in_use: Vec<IpAddr> = data
+            .store
+            .state()
+            .iter()
+            .filter(|other| other.name() != node.name())
+            .flat_map(external_addresses)
+            .collect();
+        dns::remove_old_addresses(
+            &data.providers,
+            &data.registry,
+            data.node_domain.as_str(),
+            node_addresses.host_name.as_str(),
+            &old_ip_addresses,
+            &node_addresses.ip_addresses,
+            &in_use,
+        )
+        .await?;
+    }
+    data.reporter
+        .annotate(&node, &fqdn, &published_addresses, Status::Verified)
+        .await;
+    // Not best effort like the other annotations: the next reconcile needs it to clean up after a change
+    let applied = data.applied_state(&node_addresses);
+    let nodes: Api<Node> = Api::all(data.client.clone());
+    nodes
+        .patch(&node.name(), &PatchParams::default(), &Patch::Merge(&applied.patch()))
+        .await?;
+    data.set_managed(&node_addresses.node_name, Some(&applied));
     Ok(ReconcilerAction { requeue_after: None })
 }
 
+/// Delete the records the node was last published with. The names and addresses computed now may differ, after a
+/// change of the template or of the annotation, and may not even be valid: they are only used for the records not
+/// verified yet, or for a node without an applied state.
 async fn cleanup(node: Node, ctx: ControllerContext<ContextData>) -> Result<ReconcilerAction, Error> {
-    let node_addresses = NodeAddresses::try_from(node)?;
-    dns::delete(
-        ctx.get_ref().linode_api_token.as_str(),
-        ctx.get_ref().node_domain.as_str(),
-        node_addresses.host_name.as_str(),
-        node_addresses.ip_address,
-    )
-    .await?;
-    CACHE.lock().unwrap().remove(node_addresses.host_name.as_str());
+    let data = ctx.get_ref();
+    let mut published: Vec<(String, Vec<IpAddr>)> = vec![];
+    let applied = AppliedState::from_node(&node);
+    if let Some(applied) = &applied {
+        published.push((applied.host_name.clone(), applied.ip_addresses.clone()));
+    }
+    match NodeAddresses::new(&node, &data.name_template, &data.selector, &data.store.state()) {
+        Ok(node_addresses)
+            if applied
+                .as_ref()
+                .map(|applied| data.is_applied(&node_addresses, applied))
+                != Some(true) =>
+        {
+            published.push((node_addresses.host_name, node_addresses.ip_addresses))
+        }
+        Ok(_) => {}
+        // The records under that name are the ones of the older node
+        Err(Error::InvalidName(NameError::Taken(..))) if applied.is_none() => {}
+        Err(error) if applied.is_none() => return Err(error),
+        Err(error) => debug!(
+            error = format!("{}", error).as_str(),
+            "Only deleting the records of the applied state"
+        ),
+    }
+    for (host_name, ip_addresses) in published.iter() {
+        dns::delete(
+            data.providers.forward.as_ref(),
+            &data.registry,
+            data.node_domain.as_str(),
+            host_name.as_str(),
+            ip_addresses,
+        )
+        .await?;
+        if let Some(internal_domain) = &data.internal_domain {
+            dns::delete_internal(
+                data.providers.forward.as_ref(),
+                &data.registry,
+                internal_domain,
+                host_name.as_str(),
+            )
+            .await?;
+        }
+        data.pending.lock().unwrap().remove(host_name.as_str());
+    }
+    data.set_managed(&node.name(), None);
     Ok(ReconcilerAction { requeue_after: None })
 }
 
@@ -107,44 +377,376 @@ async fn finalizer_reconcile(
     }
 }
 
+/// Merge patch removing the finalizer and the annotations of a node that is not selected anymore. The resource
+/// version makes the patch fail if the finalizers changed in the meantime, so both go in the same patch: patching the
+/// annotations first would change the resource version too.
+pub fn release_patch(node: &Node, finalizer: &str) -> Value {
+    let remaining: Vec<&String> = node.finalizers().iter().filter(|other| *other != finalizer).collect();
+    let mut patch = reporting::clear_annotations_patch(node).unwrap_or_else(|| json!({ "metadata": {} }));
+    patch["metadata"]["finalizers"] = json!(remaining);
+    patch["metadata"]["resourceVersion"] = json!(node.resource_version());
+    patch
+}
+
+/// A node that is not, or no longer, selected: delete its records and remove the finalizer, if it still has it.
+async fn release(
+    nodes: &Api<Node>,
+    node: Node,
+    ctx: ControllerContext<ContextData>,
+) -> Result<ReconcilerAction, Error> {
+    let finalizers = node.finalizers();
+    let own_finalizer = &ctx.get_ref().finalizer;
+    if !finalizers.iter().any(|finalizer| finalizer == own_finalizer) {
+        return Ok(ReconcilerAction { requeue_after: None });
+    }
+    info!(
+        node = node.name().as_str(),
+        "Node is not selected anymore, deleting its DNS records"
+    );
+    let patch = release_patch(&node, own_finalizer);
+    let name = node.name();
+    cleanup(node, ctx.clone()).await?;
+    nodes
+        .patch(&name, &PatchParams::default(), &Patch::Merge(&patch))
+        .await?;
+    Ok(ReconcilerAction { requeue_after: None })
+}
+
 /// Controller triggers this whenever any of the nodes have changed in any way
 async fn reconcile(node: Node, ctx: ControllerContext<ContextData>) -> Result<ReconcilerAction, Error> {
+    let start = Instant::now();
+    let health = ctx.get_ref().health.clone();
+    let in_flight = health.reconcile_started();
+    let result = reconcile_node(node, ctx).await;
+    drop(in_flight);
+    let outcome = match &result {
+        Ok(ReconcilerAction { requeue_after: None }) => "success",
+        // Only pending records are checked again
+        Ok(ReconcilerAction { requeue_after: Some(_) }) => "pending",
+        Err(error) => {
+            metrics::RECONCILE_ERRORS.inc(&[error.kind()]);
+            "error"
+        }
+    };
+    metrics::RECONCILES.inc(&[outcome]);
+    metrics::RECONCILE_DURATION.observe(&[outcome], start.elapsed());
+    result
+}
+
+async fn reconcile_node(node: Node, ctx: ControllerContext<ContextData>) -> Result<ReconcilerAction, Error> {
     let client = ctx.get_ref().client.clone();
     let nodes: Api<Node> = Api::all(client);
-    finalizer(&nodes, "k8s.haim.dev/linode-dns-finalizer", node, |event| {
-        finalizer_reconcile(event, ctx)
+    if !ctx.get_ref().selector.matches(&node) {
+        return release(&nodes, node, ctx).await;
+    }
+    let reported_node = node.clone();
+    let result = finalizer(&nodes, &ctx.get_ref().finalizer, node, |event| {
+        finalizer_reconcile(event, ctx.clone())
     })
-    .await?;
+    .await
+    .map_err(Error::from);
+    if let Err(error) = &result {
+        report_failure(&ctx.get_ref().reporter, &reported_node, error).await;
+    }
+    result
+}
 
-    Ok(ReconcilerAction { requeue_after: None })
+/// Warning event and `Failed` status on the node. Not when the Kubernetes API itself fails, that would fail too.
+async fn report_failure(reporter: &Reporter, node: &Node, error: &Error) {
+    let reason = match error {
+        Error::KubeApiFailure(_) => return,
+        Error::PropagationTimeout(..) => "VerificationFailed",
+        _ => "ReconcileFailed",
+    };
+    reporter
+        .publish(node, EventType::Warning, reason, error.to_string())
+        .await;
+    reporter.failed(node).await;
 }
 
 /// The controller triggers this on reconcile errors
-fn error_policy(error: &Error, _ctx: ControllerContext<ContextData>) -> ReconcilerAction {
-    warn!(error = format!("{}", error).as_str(), "Reconcile failed");
-    ReconcilerAction {
-        requeue_after: Some(tokio::time::Duration::from_secs(30)),
+fn error_policy(error: &Error, ctx: ControllerContext<ContextData>) -> ReconcilerAction {
+    let data = ctx.get_ref();
+    match error {
+        // Retried like any other failure, for the nodes that failed while the token was being rotated
+        _ if error.is_auth_failure() && data.providers.rotates_credentials() => {
+            warn!(
+                error = format!("{}", error).as_str(),
+                "DNS provider API rejected the token, retrying in case it is rotated"
+            );
+            ReconcilerAction {
+                requeue_after: Some(data.retry_interval),
+            }
+        }
+        _ if error.is_auth_failure() => {
+            error!(
+                error = format!("{}", error).as_str(),
+                "DNS provider API rejected the token, not retrying until the configuration is fixed"
+            );
+            ReconcilerAction { requeue_after: None }
+        }
+        // Checked again once in a while, the older node may be gone by then
+        Error::InvalidName(NameError::Taken(..)) => {
+            error!(error = format!("{}", error).as_str(), "Node DNS name is already taken");
+            ReconcilerAction {
+                requeue_after: Some(data.permanent_failure_retry_interval),
+            }
+        }
+        Error::InvalidName(_) => {
+            error!(
+                error = format!("{}", error).as_str(),
+                "Node DNS name is invalid, not retrying until the node or the template is fixed"
+            );
+            ReconcilerAction { requeue_after: None }
+        }
+        Error::ForeignRecords(_) => {
+            error!(
+                error = format!("{}", error).as_str(),
+                "DNS name is taken by records this controller does not own"
+            );
+            ReconcilerAction {
+                requeue_after: Some(data.permanent_failure_retry_interval),
+            }
+        }
+        _ if !error.is_retryable() => {
+            error!(error = format!("{}", error).as_str(), "Reconcile failed permanently");
+            ReconcilerAction {
+                requeue_after: Some(data.permanent_failure_retry_interval),
+            }
+        }
+        _ => {
+            warn!(error = format!("{}", error).as_str(), "Reconcile failed");
+            ReconcilerAction {
+                requeue_after: Some(data.retry_interval),
+            }
+        }
     }
 }
 
-pub async fn run() -> Result<(), Error> {
-    let node_domain = std::env::var("NODE_DOMAIN").context("NODE_DOMAIN environment variable is not defined")?;
-    let linode_api_token =
-        std::env::var("LINODE_API_TOKEN").context("LINODE_API_TOKEN environment variable is not defined")?;
+/// Delete the records of the nodes that are gone, and forget the applied state of the nodes whose records went
+/// missing: the watcher sees the change, and the reconcile recreates them.
+async fn resync(ctx: &ControllerContext<ContextData>) -> Result<ResyncReport, Error> {
+    let data = ctx.get_ref();
+    // Records first, see `dns::resync`
+    let records = data
+        .providers
+        .forward
+        .list_zone_records(data.node_domain.as_str())
+        .await?;
+    let internal_records = match &data.internal_domain {
+        Some(internal_domain) if internal_domain.zone() != data.node_domain => {
+            data.providers.forward.list_zone_records(internal_domain.zone()).await?
+        }
+        _ => vec![],
+    };
+    let nodes: Api<Node> = Api::all(data.client.clone());
+    let mut selected: Vec<(NodeAddresses, Option<AppliedState>)> = vec![];
+    // The records of the nodes that cannot be read are not orphans: keep the names they were last applied with
+    let mut kept: Vec<String> = vec![];
+    let all = nodes.list(&ListParams::default()).await?.items;
+    for node in all.iter() {
+        if !data.selector.matches(node) {
+            continue;
+        }
+        let applied = AppliedState::from_node(node);
+        match (
+            NodeAddresses::new(node, &data.name_template, &data.selector, &all),
+            applied,
+        ) {
+            (Ok(node_addresses), applied) => selected.push((node_addresses, applied)),
+            (Err(error), Some(applied)) => {
+                debug!(
+                    node = node.name().as_str(),
+                    error = format!("{}", error).as_str(),
+                    "Keeping the records of an unreadable node"
+                );
+                kept.push(applied.host_name);
+            }
+            // Never published, the records under that name are the ones of the older node
+            (Err(Error::InvalidName(NameError::Taken(..))), None) => {}
+            // Its records, if any, cannot be told apart from the orphans
+            (Err(error), None) => {
+                warn!(
+                    node = node.name().as_str(),
+                    "Cannot read a node, not deleting any orphaned record"
+                );
+                return Err(error);
+            }
+        }
+    }
+    let addresses: HashMap<String, Vec<IpAddr>> = selected
+        .iter()
+        .map(|(node_addresses, _)| (node_addresses.host_name.clone(), node_addresses.ip_addresses.clone()))
+        .collect();
+    let internal_addresses: HashMap<String, Vec<IpAddr>> = selected
+        .iter()
+        .map(|(node_addresses, _)| {
+            (
+                node_addresses.host_name.clone(),
+                node_addresses.internal_ip_addresses.clone(),
+            )
+        })
+        .collect();
+    let internal = data
+        .internal_domain
+        .as_ref()
+        .map(|internal_domain| dns::InternalRecords {
+            domain: internal_domain,
+            records: if internal_domain.zone() == data.node_domain {
+                &records
+            } else {
+                &internal_records
+            },
+            nodes: &internal_addresses,
+        });
+    let report = dns::resync(
+        data.providers.forward.as_ref(),
+        &data.registry,
+        data.node_domain.as_str(),
+        internal,
+        &records,
+        &addresses,
+        &kept,
+    )
+    .await?;
+    // Recounted from scratch, e.g. for the nodes deleted without a cleanup
+    let mut managed = HashMap::new();
+    for (node_addresses, applied) in selected.iter() {
+        if report.out_of_sync.contains(&node_addresses.host_name) {
+            if applied.is_some() {
+                nodes
+                    .patch(
+                        &node_addresses.node_name,
+                        &PatchParams::default(),
+                        &Patch::Merge(&state::forget_patch()),
+                    )
+                    .await?;
+            }
+        } else if let Some(applied) = applied
+            .as_ref()
+            .filter(|applied| data.is_applied(node_addresses, applied))
+        {
+            managed.insert(node_addresses.node_name.clone(), data.record_count(applied));
+        }
+    }
+    let mut current = data.managed.lock().unwrap();
+    *current = managed;
+    update_gauges(&current);
+    Ok(report)
+}
+
+/// Resync every `interval`, starting right away: the nodes with an applied state are not verified on startup, so
+/// this is what notices the records lost in the meantime.
+async fn resync_loop(ctx: ControllerContext<ContextData>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        match resync(&ctx).await {
+            Ok(report) => info!(
+                deleted = ?report.deleted,
+                out_of_sync = ?report.out_of_sync,
+                managed_records = report.managed_records,
+                "Resync done"
+            ),
+            Err(error) => warn!(error = format!("{}", error).as_str(), "Resync failed"),
+        }
+    }
+}
+
+/// Run the controller with a validated configuration, see [`Config::load`]
+pub async fn run(config: Config) -> Result<(), Error> {
+    let name_template: NameTemplate = config.name_template.parse()?;
+    let selector: NodeSelector = config.node_selector.parse()?;
+    let providers = provider::from_config(&config)?;
+    let internal_domain = config
+        .internal_domain
+        .as_ref()
+        .map(|internal_domain| dns::InternalDomain::new(internal_domain, &config.node_domain));
+    let health = Arc::new(Health::new(config.liveness_timeout));
+    tokio::spawn(server::serve(config.http_listen_address, health.clone())?);
+    if config.preflight {
+        let mut zones = vec![config.node_domain.clone()];
+        if let Some(internal_domain) = internal_domain.as_ref().filter(|d| d.zone() != config.node_domain) {
+            zones.push(internal_domain.zone().to_string());
+        }
+        // Only Linode serves the zones from well-known name servers the delegation can be compared to
+        let resolver = match config.dns_provider.as_str() {
+            "linode" => Some(TokioAsyncResolver::tokio_from_system_conf().context("Cannot create the DNS resolver")?),
+            _ => None,
+        };
+        preflight::check(&providers, &zones, resolver.as_ref()).await?;
+    } else {
+        providers.validate_credentials().await?;
+    }
+    health.credentials_validated();
+    let watched = providers.clone();
+    tokio::spawn(async move { watched.watch_credentials().await });
 
     let client = kube::Client::try_default().await?;
     let nodes: Api<Node> = Api::all(client.clone());
     let lp = ListParams::default().fields("").timeout(290);
+    let leader = LeaderElection::from_config(client.clone(), &config);
+    // Only watches once run
+    let controller = Controller::new(nodes, lp);
 
     let context_data = ContextData {
+        reporter: Reporter::new(client.clone(), config.pod_name.as_deref(), &config.pod_namespace),
+        health,
         client,
-        node_domain,
-        linode_api_token,
+        resolvers: dns::Resolvers::new(&providers, &config.node_domain).await?,
+        provider: config.dns_provider.clone(),
+        reverse_provider: config.reverse_provider().to_string(),
+        node_domain: config.node_domain,
+        internal_domain,
+        name_template,
+        store: controller.store(),
+        selector,
+        providers,
+        registry: Registry::new(&config.owner_id).with_record_ttl(config.record_ttl),
+        propagation_poll_interval: config.propagation_poll_interval,
+        propagation_timeout: config.propagation_timeout,
+        finalizer: config.finalizer,
+        retry_interval: config.retry_interval,
+        permanent_failure_retry_interval: config.permanent_failure_retry_interval,
+        pending: Mutex::new(HashMap::new()),
+        managed: Mutex::new(HashMap::new()),
     };
-    Controller::new(nodes, lp)
-        .shutdown_on_signal()
-        .run(reconcile, error_policy, ControllerContext::new(context_data))
-        .for_each(|result| async move { trace!("Reconciled: {:?}", result) })
-        .await;
+    let context = ControllerContext::new(context_data);
+    if let Some(leader) = &leader {
+        let health = &context.get_ref().health;
+        health.standby();
+        leader.acquire().await;
+        health.leading();
+    }
+    if config.resync_interval.is_zero() {
+        debug!("Periodic resync is disabled");
+    } else {
+        tokio::spawn(resync_loop(context.clone(), config.resync_interval));
+    }
+    let health = context.get_ref().health.clone();
+    let results = controller.shutdown_on_signal().run(reconcile, error_policy, context);
+    // The ticker runs within the loop that drives the controller, so it stops ticking when that loop is stuck
+    let mut ticker = tokio::time::interval(config.liveness_timeout / 2);
+    let controller = async move {
+        tokio::pin!(results);
+        loop {
+            tokio::select! {
+                result = results.next() => match result {
+                    Some(result) => trace!("Reconciled: {:?}", result),
+                    None => break,
+                },
+                _ = ticker.tick() => health.tick(),
+            }
+        }
+        health.stopped();
+    };
+    match &leader {
+        Some(leader) => tokio::select! {
+            _ = controller => leader.release().await,
+            error = leader.hold() => return Err(error),
+        },
+        None => controller.await,
+    }
     Ok(())
 }
@@ -1,38 +1,48 @@
-use crate::linode;
+use crate::metrics;
+use crate::provider::{DnsProvider, Providers, Record};
+use crate::registry::{ForeignRecordsError, Ownership, Registry};
 use anyhow::{Context, Result};
-use std::net::IpAddr;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
 use std::str::FromStr;
-use tracing::{debug, info, instrument};
+use std::time::Duration;
+use tracing::{debug, info, instrument, warn};
 use trust_dns_resolver::config::*;
 use trust_dns_resolver::{Name, TokioAsyncResolver};
 
-/// How long to wait for the new A record to appear
-const DNS_PROPAGATION_DELAY: u64 = 60;
-
-/// This should be one of the values available in Linode UI, not every seconds value is supported
-const DNS_RECORD_TTL: u64 = 5 * 60;
+/// Resolvers of the forward and reverse records, each querying the authoritative name servers of its provider.
+pub struct Resolvers {
+    pub forward: TokioAsyncResolver,
+    pub reverse: TokioAsyncResolver,
+}
 
-/// In order to query the Linode servers directly, and get the authoritative answer
-const LINODE_NAME_SERVERS: [&str; 5] = [
-    "ns1.linode.com",
-    "ns2.linode.com",
-    "ns3.linode.com",
-    "ns4.linode.com",
-    "ns5.linode.com",
-];
+impl Resolvers {
+    pub async fn new(providers: &Providers, domain: &str) -> Result<Resolvers> {
+        let forward_name_servers = providers.forward.name_servers(domain).await?;
+        let reverse_name_servers = match &providers.reverse {
+            Some(reverse) => reverse.reverse_name_servers(),
+            // Not used, reverse DNS is not verified when it is not managed
+            None => forward_name_servers.clone(),
+        };
+        Ok(Resolvers {
+            forward: resolver(&forward_name_servers).await?,
+            reverse: resolver(&reverse_name_servers).await?,
+        })
+    }
+}
 
-async fn resolver() -> Result<TokioAsyncResolver> {
-    // First, a bootstrap resolver to resolve Linode's name servers addresses
+/// Resolver that queries the given authoritative name servers directly.
+pub async fn resolver(name_servers: &[String]) -> Result<TokioAsyncResolver> {
+    // First, a bootstrap resolver to resolve the name servers addresses
     let bootstrap_resolver = TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())?;
     // Using a loop instead of a map because of await inside. Simpler than streams.
     let mut ips: Vec<IpAddr> = Vec::new();
-    for name_server in LINODE_NAME_SERVERS.into_iter() {
-        let lookup_ip = bootstrap_resolver
-            .lookup_ip(Name::from_str(name_server).unwrap())
-            .await?;
+    for name_server in name_servers.iter() {
+        let lookup_ip = bootstrap_resolver.lookup_ip(Name::from_str(name_server)?).await?;
         lookup_ip.into_iter().for_each(|ip| ips.push(ip));
     }
-    // The actual resolver will go to Linode nameservers directly, to shorten the loop and time
+    // The actual resolver will go to the authoritative nameservers directly, to shorten the loop and time
     // to converge.
     let config = ResolverConfig::from_parts(
         None,
@@ -40,115 +50,181 @@ async fn resolver() -> Result<TokioAsyncResolver> {
         NameServerConfigGroup::from_ips_clear(ips.as_slice(), 53, true),
     );
 
+    // Nothing is cached, so that polling sees the new records as soon as the name servers have them.
     let opts = ResolverOpts {
         use_hosts_file: false,
+        positive_max_ttl: Some(Duration::ZERO),
+        negative_max_ttl: Some(Duration::ZERO),
         ..ResolverOpts::default()
     };
     let resolver = TokioAsyncResolver::tokio(config, opts)?;
     Ok(resolver)
 }
 
-#[instrument(skip(linode_api_token))]
-async fn add_a_record(linode_api_token: &str, domain: &str, host_name: &str, ip_address: IpAddr) -> Result<()> {
-    let client = linode::Client::new(linode_api_token);
-    let domains = client.get_domains().await?;
-    let domain = domains
-        .iter()
-        .find(|d| d.domain == domain)
-        .context(format!("Could not find domain {} at Linode", domain))?;
-    let records = client.get_domain_records(domain.id).await?;
+/// Address record types, the only ones this controller creates besides the ownership records
+fn is_address(record: &Record) -> bool {
+    record.type_ == "A" || record.type_ == "AAAA"
+}
+
+/// Compares the parsed addresses, providers do not all format IPv6 addresses the same way
+fn points_to(record: &Record, ip_address: IpAddr) -> bool {
+    record.target.parse::<IpAddr>().ok() == Some(ip_address)
+}
 
+/// The addresses published for a node: its first IPv4 and its first IPv6 address, as there is only room for one
+/// A and one AAAA record per name.
+pub fn published_addresses(ip_addresses: &[IpAddr]) -> Vec<IpAddr> {
+    let ipv4 = ip_addresses.iter().find(|ip| ip.is_ipv4());
+    let ipv6 = ip_addresses.iter().find(|ip| ip.is_ipv6());
+    ipv4.into_iter().chain(ipv6).copied().collect()
+}
+
+#[instrument(skip(provider, registry))]
+async fn add_a_record(
+    provider: &dyn DnsProvider,
+    registry: &Registry,
+    domain: &str,
+    host_name: &str,
+    ip_address: IpAddr,
+) -> Result<Option<Change>> {
     let addr_type = if ip_address.is_ipv4() { "A" } else { "AAAA" };
-    let record = records
+    let fqdn = format!("{}.{}", host_name, domain);
+    let record = provider
+        .list_records(domain, host_name)
+        .await?
         .into_iter()
-        .find(|r| r.name == host_name && r.type_ == addr_type);
-    if let Some(record) = record {
-        if record.target == ip_address.to_string() {
-            info!("Forward DNS record is already defined in Linode");
-            return Ok(());
-        }
-        let record_id = record.id;
-        let mut update_request = linode::DomainRecordRequest::from(record);
-        update_request.target = ip_address.to_string();
-        client
-            .update_domain_record(domain.id, record_id, update_request)
-            .await?;
-        info!("Forward DNS record updated");
-    } else {
-        let create_request = linode::DomainRecordRequest {
-            name: host_name.to_string(),
-            target: ip_address.to_string(),
-            type_: addr_type.to_string(),
-            priority: None,
-            port: None,
-            weight: None,
-            ttl_sec: DNS_RECORD_TTL,
-            service: None,
-            protocol: None,
-            tag: None,
-        };
-        client.create_domain_record(domain.id, create_request).await?;
-        info!("Forward DNS record created");
+        .find(|r| r.type_ == addr_type);
+    let ownership = registry.ownership(provider, domain, host_name).await?;
+    match (record, ownership) {
+        (Some(record), ownership) if points_to(&record, ip_address) => {
+            info!("Forward DNS record is already defined");
+            adopt(provider, registry, domain, host_name, ip_address, ownership).await
+        }
+        (Some(mut record), Ownership::Owned) => {
+            record.target = ip_address.to_string();
+            provider.update_record(domain, &record).await?;
+            info!("Forward DNS record updated");
+            Ok(Some(Change::Updated { fqdn, ip_address }))
+        }
+        (None, ownership @ (Ownership::Owned | Ownership::Unclaimed)) => {
+            if ownership == Ownership::Unclaimed {
+                registry.claim(provider, domain, host_name).await?;
+            }
+            let record = Record::new(host_name, addr_type, &ip_address.to_string(), registry.record_ttl());
+            provider.create_record(domain, &record).await?;
+            info!("Forward DNS record created");
+            Ok(Some(Change::Created { fqdn, ip_address }))
+        }
+        (_, ownership) => {
+            warn!(
+                ?ownership,
+                "Forward DNS record exists, but was not created by this controller"
+            );
+            Err(ForeignRecordsError {
+                name: host_name.to_string(),
+                ownership,
+            }
+            .into())
+        }
     }
-    Ok(())
 }
 
-#[instrument(skip(linode_api_token))]
-async fn delete_a_record(linode_api_token: &str, domain: &str, host_name: &str) -> Result<()> {
-    let client = linode::Client::new(linode_api_token);
-    let domains = client.get_domains().await?;
-    let domain = domains
-        .iter()
-        .find(|d| d.domain == domain)
-        .context(format!("Could not find domain {} at Linode", domain))?;
+/// Claim an unclaimed name whose record already points to the node, e.g. published by a version of this controller
+/// without ownership records, so that it is deleted with the node and updated when its address changes.
+async fn adopt(
+    provider: &dyn DnsProvider,
+    registry: &Registry,
+    domain: &str,
+    host_name: &str,
+    ip_address: IpAddr,
+    ownership: Ownership,
+) -> Result<Option<Change>> {
+    if ownership != Ownership::Unclaimed {
+        return Ok(None);
+    }
+    registry.claim(provider, domain, host_name).await?;
+    info!("Claimed the existing forward DNS record");
+    let fqdn = format!("{}.{}", host_name, domain);
+    Ok(Some(Change::Adopted { fqdn, ip_address }))
+}
 
-    // : Vec<Box<dyn Future<Output=Result<()>>>>
-    let record_ids: Vec<u64> = client
-        .get_domain_records(domain.id)
+#[instrument(skip(provider, registry))]
+async fn delete_a_record(provider: &dyn DnsProvider, registry: &Registry, domain: &str, host_name: &str) -> Result<()> {
+    let ownership = registry.ownership(provider, domain, host_name).await?;
+    if ownership != Ownership::Owned {
+        warn!(
+            ?ownership,
+            "DNS records were not created by this controller, not deleting them"
+        );
+        return Ok(());
+    }
+    let (owned, other): (Vec<Record>, Vec<Record>) = provider
+        .list_records(domain, host_name)
         .await?
         .into_iter()
-        .filter(|r| r.name == host_name)
-        .map(|record| record.id)
-        .collect();
-
-    for id in record_ids.into_iter() {
-        client.delete_domain_record(domain.id, id).await?;
+        .partition(is_address);
+    for record in owned.iter() {
+        provider.delete_record(domain, record).await?;
     }
+    registry.release(provider, domain, host_name).await?;
     info!("Forward DNS record(s) deleted");
+    if !other.is_empty() {
+        let types: Vec<&str> = other.iter().map(|r| r.type_.as_str()).collect();
+        warn!(?types, "Other DNS records of the same name were left alone");
+    }
     Ok(())
 }
 
-#[instrument(skip(linode_api_token))]
-async fn trigger_rptr_update(linode_api_token: &str, fqdn: &str, ip_address: IpAddr) -> Result<()> {
-    let client = linode::Client::new(linode_api_token);
-
-    let addresses = client.get_ip_addresses().await?;
-    if addresses
-        .into_iter()
-        .any(|a| a.address == ip_address.to_string() && a.rdns == Some(fqdn.to_string()))
-    {
-        info!("Reverse DNS record already defined in Linode");
+/// Delete the address record of the name pointing to the IP address, leaving the other records of the name and its
+/// ownership alone.
+#[instrument(skip(provider, registry))]
+async fn delete_address(
+    provider: &dyn DnsProvider,
+    registry: &Registry,
+    domain: &str,
+    host_name: &str,
+    ip_address: IpAddr,
+) -> Result<()> {
+    let ownership = registry.ownership(provider, domain, host_name).await?;
+    if ownership != Ownership::Owned {
+        warn!(
+            ?ownership,
+            "DNS records were not created by this controller, not deleting them"
+        );
         return Ok(());
     }
-    client.update_rdns(ip_address, fqdn).await?;
-    info!("Triggered RDNS update in Linode");
+    let records = provider.list_records(domain, host_name).await?;
+    for record in records.iter().filter(|r| is_address(r) && points_to(r, ip_address)) {
+        provider.delete_record(domain, record).await?;
+        info!("Forward DNS record deleted");
+    }
     Ok(())
 }
 
-async fn forward_lookup_check(resolver: &TokioAsyncResolver, fqdn: &str, ip: IpAddr) -> Result<()> {
-    let forward_lookup = resolver.lookup_ip(Name::from_str(fqdn)?).await?;
-    let ip_address = forward_lookup
-        .iter()
-        .next()
-        .context("Could not find IP address in response")?;
-    if ip_address == ip {
+/// Only the records of the same family as the IP address are looked up, the name may have both.
+async fn forward_lookup(resolver: &TokioAsyncResolver, fqdn: &str, ip: IpAddr) -> Result<()> {
+    let name = Name::from_str(fqdn)?;
+    let found = match ip {
+        IpAddr::V4(ip) => resolver.ipv4_lookup(name).await?.iter().any(|address| *address == ip),
+        IpAddr::V6(ip) => resolver.ipv6_lookup(name).await?.iter().any(|address| *address == ip),
+    };
+    if found {
         Ok(())
     } else {
         Err(anyhow::anyhow!("IP address does not match"))
     }
 }
 
-async fn reverse_lookup_check(resolver: &TokioAsyncResolver, ip: IpAddr, fqdn: &str) -> Result<()> {
+/// `forward_lookup`, counting the failures
+async fn forward_lookup_check(resolver: &TokioAsyncResolver, fqdn: &str, ip: IpAddr) -> Result<()> {
+    let result = forward_lookup(resolver, fqdn, ip).await;
+    if result.is_err() {
+        metrics::VERIFICATION_FAILURES.inc(&["forward"]);
+    }
+    result
+}
+
+async fn reverse_lookup(resolver: &TokioAsyncResolver, ip: IpAddr, fqdn: &str) -> Result<()> {
     let reverse_lookup = resolver.reverse_lookup(ip).await?;
     let name = reverse_lookup
         .into_iter()
@@ -161,42 +237,478 @@ async fn reverse_lookup_check(resolver: &TokioAsyncResolver, ip: IpAddr, fqdn: &
     }
 }
 
+/// `reverse_lookup`, counting the failures
+async fn reverse_lookup_check(resolver: &TokioAsyncResolver, ip: IpAddr, fqdn: &str) -> Result<()> {
+    let result = reverse_lookup(resolver, ip, fqdn).await;
+    if result.is_err() {
+        metrics::VERIFICATION_FAILURES.inc(&["reverse"]);
+    }
+    result
+}
+
+/// Name matching the `exists:%{i}._spf.example.com` SPF mechanism, which expands IPv6 addresses to dot-separated
+/// nibbles, e.g. `2.0.0.1.0.d.b.8.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.1._spf` for `2001:db8::1`
 fn spf_glue_record(ip_address: IpAddr) -> String {
-    format!("{}._spf", ip_address.to_string())
+    match ip_address {
+        IpAddr::V4(ip) => format!("{}._spf", ip),
+        IpAddr::V6(ip) => {
+            let nibbles: Vec<String> = ip
+                .octets()
+                .iter()
+                .flat_map(|octet| [octet >> 4, octet & 0xf])
+                .map(|nibble| format!("{:x}", nibble))
+                .collect();
+            format!("{}._spf", nibbles.join("."))
+        }
+    }
+}
+
+/// Address of the SPF glue record: the IPv4 address itself, and `127.0.0.2` for an IPv6 address, as `exists:` only
+/// ever looks up A records, even for IPv6 senders (RFC 7208, section 5.7)
+fn spf_glue_address(ip_address: IpAddr) -> IpAddr {
+    match ip_address {
+        IpAddr::V4(_) => ip_address,
+        IpAddr::V6(_) => IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)),
+    }
+}
+
+/// Where the records of the internal addresses of the nodes go: either a subdomain of the node domain, in the same
+/// zone, e.g. `node-1.internal` in `k8s.example.com`, or a zone of its own, e.g. `node-1` in `k8s.example.internal`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InternalDomain {
+    zone: String,
+    /// Subdomain of the zone the records are in, if any
+    subdomain: Option<String>,
+}
+
+impl InternalDomain {
+    pub fn new(internal_domain: &str, node_domain: &str) -> InternalDomain {
+        match internal_domain.strip_suffix(&format!(".{}", node_domain)) {
+            Some(subdomain) => InternalDomain {
+                zone: node_domain.to_string(),
+                subdomain: Some(subdomain.to_string()),
+            },
+            None => InternalDomain {
+                zone: internal_domain.to_string(),
+                subdomain: None,
+            },
+        }
+    }
+
+    /// Zone the records are in: the node domain, or a zone of its own
+    pub fn zone(&self) -> &str {
+        &self.zone
+    }
+
+    /// Fully qualified name of the record of the host
+    pub fn fqdn(&self, host_name: &str) -> String {
+        format!("{}.{}", self.record_name(host_name), self.zone)
+    }
+
+    /// Name of the record of the host, relative to the zone
+    fn record_name(&self, host_name: &str) -> String {
+        match &self.subdomain {
+            Some(subdomain) => format!("{}.{}", host_name, subdomain),
+            None => host_name.to_string(),
+        }
+    }
+}
+
+/// Whether the records are visible on the authoritative name servers yet
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Propagation {
+    Verified,
+    Pending,
 }
 
-#[instrument(skip(linode_api_token))]
-pub async fn update(linode_api_token: &str, domain: &str, host_name: &str, ip_address: IpAddr) -> Result<()> {
+impl From<Result<()>> for Propagation {
+    fn from(check: Result<()>) -> Self {
+        match check {
+            Ok(()) => Propagation::Verified,
+            Err(_) => Propagation::Pending,
+        }
+    }
+}
+
+/// A change made to the records, worth telling the operators about
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Created {
+        fqdn: String,
+        ip_address: IpAddr,
+    },
+    Updated {
+        fqdn: String,
+        ip_address: IpAddr,
+    },
+    /// An existing record pointing to the node was claimed
+    Adopted {
+        fqdn: String,
+        ip_address: IpAddr,
+    },
+    ReverseUpdated {
+        ip_address: IpAddr,
+        fqdn: String,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Created { fqdn, ip_address } => {
+                write!(f, "Created the record of {} pointing to {}", fqdn, ip_address)
+            }
+            Change::Updated { fqdn, ip_address } => {
+                write!(f, "Updated the record of {} to point to {}", fqdn, ip_address)
+            }
+            Change::Adopted { fqdn, ip_address } => {
+                write!(f, "Claimed the existing record of {} pointing to {}", fqdn, ip_address)
+            }
+            Change::ReverseUpdated { ip_address, fqdn } => {
+                write!(f, "Updated the reverse DNS of {} to point to {}", ip_address, fqdn)
+            }
+        }
+    }
+}
+
+/// What an update did, and whether its result is visible yet
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome {
+    pub propagation: Propagation,
+    pub changes: Vec<Change>,
+}
+
+impl Default for Outcome {
+    fn default() -> Self {
+        Outcome {
+            propagation: Propagation::Verified,
+            changes: vec![],
+        }
+    }
+}
+
+impl Outcome {
+    /// Pending as soon as one part is pending
+    fn add(&mut self, propagation: Propagation, change: Option<Change>) {
+        if propagation == Propagation::Pending {
+            self.propagation = Propagation::Pending;
+        }
+        self.changes.extend(change);
+    }
+
+    pub fn merge(&mut self, other: Outcome) {
+        self.add(other.propagation, None);
+        self.changes.extend(other.changes);
+    }
+}
+
+/// Make sure the name resolves to the IP address, creating or fixing the record if it does not.
+async fn ensure_forward(
+    provider: &dyn DnsProvider,
+    registry: &Registry,
+    resolver: &TokioAsyncResolver,
+    domain: &str,
+    name: &str,
+    ip_address: IpAddr,
+) -> Result<(Propagation, Option<Change>)> {
+    let fqdn = format!("{}.{}", name, domain);
+    if forward_lookup_check(resolver, &fqdn, ip_address).await.is_ok() {
+        let ownership = registry.ownership(provider, domain, name).await?;
+        let change = adopt(provider, registry, domain, name, ip_address, ownership).await?;
+        return Ok((Propagation::Verified, change));
+    }
+    info!(fqdn = fqdn.as_str(), "Forward lookup failed, adding new DNS record");
+    let change = add_a_record(provider, registry, domain, name, ip_address).await?;
+    let propagation = forward_lookup_check(resolver, &fqdn, ip_address).await.into();
+    Ok((propagation, change))
+}
+
+/// Publish the forward, SPF glue and reverse records of the node, an A record for its IPv4 address and an AAAA
+/// record for its IPv6 address, without waiting for them to propagate: the caller is expected to call again later
+/// while the result is `Pending`, every call checks the name servers once more.
+#[instrument(skip(providers, registry, resolvers))]
+pub async fn update(
+    providers: &Providers,
+    registry: &Registry,
+    resolvers: &Resolvers,
+    domain: &str,
+    host_name: &str,
+    ip_addresses: &[IpAddr],
+) -> Result<Outcome> {
     debug!("Verifying forward and reverse DNS records");
-    let resolver = resolver().await?;
+    let mut outcome = Outcome::default();
+    for ip_address in published_addresses(ip_addresses) {
+        outcome.merge(update_address(providers, registry, resolvers, domain, host_name, ip_address).await?);
+    }
+    Ok(outcome)
+}
+
+/// The records of one of the node addresses
+#[instrument(skip(providers, registry, resolvers, domain, host_name))]
+async fn update_address(
+    providers: &Providers,
+    registry: &Registry,
+    resolvers: &Resolvers,
+    domain: &str,
+    host_name: &str,
+    ip_address: IpAddr,
+) -> Result<Outcome> {
     let fqdn = format!("{}.{}", host_name, domain);
-    let spf_fqdn = format!("{}.{}", spf_glue_record(ip_address), domain);
+    let provider = providers.forward.as_ref();
+    let mut outcome = Outcome::default();
 
-    if forward_lookup_check(&resolver, &fqdn, ip_address).await.is_err() {
-        info!("Forward lookup failed, adding new DNS record");
-        add_a_record(linode_api_token, &domain, &host_name, ip_address).await?;
-        debug!(delay = DNS_PROPAGATION_DELAY, "Waiting for DNS propagation");
-        tokio::time::sleep(std::time::Duration::from_secs(DNS_PROPAGATION_DELAY)).await;
+    let (forward, change) =
+        ensure_forward(provider, registry, &resolvers.forward, domain, host_name, ip_address).await?;
+    outcome.add(forward, change);
+    let spf_glue = spf_glue_record(ip_address);
+    let (spf, change) = ensure_forward(
+        provider,
+        registry,
+        &resolvers.forward,
+        domain,
+        spf_glue.as_str(),
+        spf_glue_address(ip_address),
+    )
+    .await?;
+    if ip_address.is_ipv6() && change.is_some() {
+        // Earlier versions published the IPv6 glue as an AAAA record, which `exists:` never matches
+        delete_address(provider, registry, domain, spf_glue.as_str(), ip_address).await?;
+    }
+    outcome.add(spf, change);
+    if forward == Propagation::Pending {
+        // Linode refuses to set the reverse DNS to a name that does not resolve to the address
+        debug!("Forward record is not visible yet, postponing the reverse DNS update");
+        return Ok(outcome);
     }
 
-    if forward_lookup_check(&resolver, &spf_fqdn, ip_address).await.is_err() {
-        info!("Forward lookup failed for the SPF record, adding new DNS record");
-        add_a_record(linode_api_token, &domain, spf_glue_record(ip_address).as_str(), ip_address).await?;
+    match &providers.reverse {
+        Some(reverse)
+            if reverse_lookup_check(&resolvers.reverse, ip_address, &fqdn)
+                .await
+                .is_err() =>
+        {
+            info!("Reverse lookup failed, triggering API to update");
+            reverse.set_ptr(ip_address, &fqdn).await?;
+            let reverse = reverse_lookup_check(&resolvers.reverse, ip_address, &fqdn).await.into();
+            outcome.add(reverse, Some(Change::ReverseUpdated { ip_address, fqdn }));
+        }
+        _ => {}
+    };
+    Ok(outcome)
+}
+
+/// Publish the internal addresses of the node, e.g. its private IP addresses, under the internal domain. Only the
+/// address records: no SPF glue, and no reverse DNS, which is not available for private addresses. The internal
+/// domain is expected to be served by the same name servers as the node domain.
+#[instrument(skip(provider, registry, resolver))]
+pub async fn update_internal(
+    provider: &dyn DnsProvider,
+    registry: &Registry,
+    resolver: &TokioAsyncResolver,
+    internal_domain: &InternalDomain,
+    host_name: &str,
+    ip_addresses: &[IpAddr],
+) -> Result<Outcome> {
+    let name = internal_domain.record_name(host_name);
+    let zone = internal_domain.zone.as_str();
+    let ip_addresses = published_addresses(ip_addresses);
+    if ip_addresses.is_empty() {
+        return delete_internal(provider, registry, internal_domain, host_name)
+            .await
+            .map(|()| Outcome::default());
     }
+    let mut outcome = Outcome::default();
+    for ip_address in ip_addresses.iter() {
+        let (propagation, change) = ensure_forward(provider, registry, resolver, zone, &name, *ip_address).await?;
+        outcome.add(propagation, change);
+    }
+    // The records of an address family the node no longer has
+    for ip_address in provider
+        .list_records(zone, &name)
+        .await?
+        .iter()
+        .filter(|r| is_address(r))
+        .filter_map(|r| r.target.parse::<IpAddr>().ok())
+        .filter(|ip| !ip_addresses.contains(ip))
+    {
+        delete_address(provider, registry, zone, &name, ip_address).await?;
+    }
+    Ok(outcome)
+}
+
+/// Delete the internal address records of the node, if this controller created them.
+#[instrument(skip(provider, registry))]
+pub async fn delete_internal(
+    provider: &dyn DnsProvider,
+    registry: &Registry,
+    internal_domain: &InternalDomain,
+    host_name: &str,
+) -> Result<()> {
+    let name = internal_domain.record_name(host_name);
+    delete_a_record(provider, registry, internal_domain.zone.as_str(), &name).await
+}
 
-    if reverse_lookup_check(&resolver, ip_address, &fqdn).await.is_err() {
-        info!("Reverse lookup failed, triggering API to update");
-        trigger_rptr_update(linode_api_token, &fqdn, ip_address).await?;
-        debug!(delay = DNS_PROPAGATION_DELAY, "Waiting for DNS propagation");
-        tokio::time::sleep(std::time::Duration::from_secs(DNS_PROPAGATION_DELAY)).await;
+/// Clean up after the node changed IP addresses: the SPF glue records of the old addresses go away, unless another
+/// node now has the address (`in_use`), and their reverse DNS is reset if it still points to the node. The forward
+/// records were already updated by `update`, except when the node lost an address family altogether, in which case
+/// its record is deleted.
+#[instrument(skip(providers, registry, in_use))]
+pub async fn remove_old_addresses(
+    providers: &Providers,
+    registry: &Registry,
+    domain: &str,
+    host_name: &str,
+    old_ip_addresses: &[IpAddr],
+    ip_addresses: &[IpAddr],
+    in_use: &[IpAddr],
+) -> Result<()> {
+    let provider = providers.forward.as_ref();
+    let current = published_addresses(ip_addresses);
+    for old_ip_address in published_addresses(old_ip_addresses)
+        .into_iter()
+        .filter(|ip| !current.contains(ip))
+    {
+        info!(%old_ip_address, "Removing the DNS records of the old IP address");
+        if in_use.contains(&old_ip_address) {
+            info!(%old_ip_address, "Keeping the SPF glue record, another node has the address now");
+        } else {
+            delete_a_record(provider, registry, domain, spf_glue_record(old_ip_address).as_str()).await?;
+        }
+        if !current.iter().any(|ip| ip.is_ipv4() == old_ip_address.is_ipv4()) {
+            delete_address(provider, registry, domain, host_name, old_ip_address).await?;
+        }
+        if let Some(reverse) = &providers.reverse {
+            reverse
+                .reset_ptr(old_ip_address, &format!("{}.{}", host_name, domain))
+                .await?;
+        }
     }
     Ok(())
 }
 
-#[instrument(skip(linode_api_token))]
-pub async fn delete(linode_api_token: &str, domain: &str, host_name: &str, ip_address: IpAddr) -> Result<()> {
+/// What a resync found
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResyncReport {
+    /// Owned names that no node uses anymore, deleted
+    pub deleted: Vec<String>,
+    /// Nodes whose records are missing or wrong, to be reconciled again
+    pub out_of_sync: Vec<String>,
+    /// Address records of the owned names left in the zone
+    pub managed_records: usize,
+}
+
+/// The internal records of the nodes, for [`resync`]
+pub struct InternalRecords<'a> {
+    pub domain: &'a InternalDomain,
+    /// Records of the zone of the internal domain, the same as the node records when it is in the node zone
+    pub records: &'a [Record],
+    /// Internal addresses of the nodes, by name
+    pub nodes: &'a HashMap<String, Vec<IpAddr>>,
+}
+
+/// Whether the owned name points to the address
+fn has_owned_address(owned: &[String], records: &[Record], name: &str, ip_address: IpAddr) -> bool {
+    owned.iter().any(|owned| owned == name)
+        && records
+            .iter()
+            .any(|r| r.name == name && is_address(r) && points_to(r, ip_address))
+}
+
+/// Compare the records of the zone with the nodes: the owned records no node needs anymore are deleted, and the
+/// nodes whose records are missing are reported, for the caller to reconcile them again.
+///
+/// The records must be listed before the nodes, otherwise the records of a node created in between would look
+/// orphaned. The internal records are checked too, but only deleted when they are in the same zone.
+///
+/// `kept` are the names of the nodes whose addresses cannot be read: their records, and the SPF glue records of
+/// the addresses they point to, are kept without being checked.
+#[instrument(skip(provider, registry, internal, records, nodes))]
+pub async fn resync(
+    provider: &dyn DnsProvider,
+    registry: &Registry,
+    domain: &str,
+    internal: Option<InternalRecords<'_>>,
+    records: &[Record],
+    nodes: &HashMap<String, Vec<IpAddr>>,
+    kept: &[String],
+) -> Result<ResyncReport> {
+    let owned = registry.owned_names(records);
+    let has_address = |name: &str, ip_address: IpAddr| has_owned_address(&owned, records, name, ip_address);
+    let owned_internal = internal
+        .as_ref()
+        .map(|internal| registry.owned_names(internal.records))
+        .unwrap_or_default();
+    let internal_in_sync = |host_name: &str| match &internal {
+        Some(internal) => published_addresses(internal.nodes.get(host_name).map_or(&[][..], Vec::as_slice))
+            .into_iter()
+            .all(|ip| {
+                let name = internal.domain.record_name(host_name);
+                has_owned_address(&owned_internal, internal.records, &name, ip)
+            }),
+        None => true,
+    };
+    let mut report = ResyncReport::default();
+    let mut expected: Vec<String> = vec![];
+    for host_name in kept.iter() {
+        expected.extend(
+            records
+                .iter()
+                .filter(|r| &r.name == host_name && is_address(r))
+                .filter_map(|r| r.target.parse().ok())
+                .map(spf_glue_record),
+        );
+    }
+    for host_name in nodes.keys().chain(kept.iter()) {
+        expected.push(host_name.clone());
+        if let Some(internal_domain) = internal
+            .as_ref()
+            .map(|internal| internal.domain)
+            .filter(|internal_domain| internal_domain.zone == domain)
+        {
+            expected.push(internal_domain.record_name(host_name));
+        }
+    }
+    for (host_name, ip_addresses) in nodes.iter() {
+        let ip_addresses = published_addresses(ip_addresses);
+        let in_sync = ip_addresses
+            .iter()
+            .all(|ip| has_address(host_name, *ip) && has_address(&spf_glue_record(*ip), spf_glue_address(*ip)));
+        if !in_sync || !internal_in_sync(host_name) {
+            report.out_of_sync.push(host_name.clone());
+        }
+        expected.extend(ip_addresses.into_iter().map(spf_glue_record));
+    }
+    for name in owned.iter().filter(|name| !expected.contains(name)) {
+        delete_a_record(provider, registry, domain, name).await?;
+        report.deleted.push(name.clone());
+    }
+    report.out_of_sync.sort();
+    report.deleted.sort();
+    report.managed_records = records
+        .iter()
+        .filter(|r| is_address(r) && owned.contains(&r.name) && !report.deleted.contains(&r.name))
+        .count();
+    Ok(report)
+}
+
+/// Delete the address records of the name, if this controller created them, e.g. when a node was renamed.
+pub async fn delete_name(provider: &dyn DnsProvider, registry: &Registry, domain: &str, host_name: &str) -> Result<()> {
+    delete_a_record(provider, registry, domain, host_name).await
+}
+
+/// Delete the forward and SPF glue records of the node, if this controller created them.
+#[instrument(skip(provider, registry))]
+pub async fn delete(
+    provider: &dyn DnsProvider,
+    registry: &Registry,
+    domain: &str,
+    host_name: &str,
+    ip_addresses: &[IpAddr],
+) -> Result<()> {
     info!("Deleting DNS record");
-    delete_a_record(linode_api_token, &domain, host_name).await?;
-    delete_a_record(linode_api_token, &domain, spf_glue_record(ip_address).as_str()).await?;
+    delete_a_record(provider, registry, domain, host_name).await?;
+    for ip_address in published_addresses(ip_addresses) {
+        delete_a_record(provider, registry, domain, spf_glue_record(ip_address).as_str()).await?;
+    }
     Ok(())
 }
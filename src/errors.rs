@@ -1,3 +1,7 @@
+use crate::cloudflare::CloudflareError;
+use crate::linode::LinodeError;
+use crate::naming::NameError;
+use crate::registry::ForeignRecordsError;
 use kube::runtime::finalizer::Error as FinalizerError;
 use thiserror::Error;
 
@@ -11,8 +15,80 @@ pub enum Error {
     MissingEnvVar(#[from] std::env::VarError),
     #[error("Object has no name")]
     UnnamedObject,
+    #[error("DNS records of {0} are still not visible on the authoritative name servers after {1:?}")]
+    PropagationTimeout(String, std::time::Duration),
+    #[error("{0} lost the leadership")]
+    LeadershipLost(String),
+    #[error("Preflight checks failed: {}", .0.join("; "))]
+    PreflightFailed(Vec<String>),
     #[error(transparent)]
-    Other(#[from] anyhow::Error),
+    Linode(#[from] LinodeError),
+    #[error(transparent)]
+    Cloudflare(#[from] CloudflareError),
+    #[error(transparent)]
+    InvalidName(#[from] NameError),
+    #[error(transparent)]
+    ForeignRecords(#[from] ForeignRecordsError),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl Error {
+    /// Short name of the variant, to label the metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::MissingObjectKey(_) => "missing_object_key",
+            Error::KubeApiFailure(_) => "kube_api",
+            Error::MissingEnvVar(_) => "missing_env_var",
+            Error::UnnamedObject => "unnamed_object",
+            Error::PropagationTimeout(..) => "propagation_timeout",
+            Error::LeadershipLost(_) => "leadership_lost",
+            Error::PreflightFailed(_) => "preflight_failed",
+            Error::Linode(_) => "linode",
+            Error::Cloudflare(_) => "cloudflare",
+            Error::InvalidName(_) => "invalid_name",
+            Error::ForeignRecords(_) => "foreign_records",
+            Error::Other(_) => "other",
+        }
+    }
+
+    /// A provider rejected the credentials, retrying will not help until they are replaced.
+    pub fn is_auth_failure(&self) -> bool {
+        match self {
+            Error::Linode(error) => error.is_auth_failure(),
+            Error::Cloudflare(error) => error.is_auth_failure(),
+            _ => false,
+        }
+    }
+
+    /// The failure is transient, and the same reconcile may succeed later. Anything but a rejected provider request,
+    /// an invalid name or a name taken by others is assumed to be.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Linode(error) => error.is_retryable(),
+            Error::Cloudflare(error) => error.is_retryable(),
+            Error::InvalidName(_) | Error::ForeignRecords(_) => false,
+            _ => true,
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    /// API and ownership errors travel through `anyhow` in the DNS code, bring their type back so they can be classified.
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<LinodeError>() {
+            Ok(err) => return Self::Linode(err),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<CloudflareError>() {
+            Ok(err) => return Self::Cloudflare(err),
+            Err(err) => err,
+        };
+        match err.downcast::<ForeignRecordsError>() {
+            Ok(err) => Self::ForeignRecords(err),
+            Err(err) => Self::Other(err),
+        }
+    }
 }
 
 impl From<FinalizerError<Self>> for Error {
@@ -0,0 +1,119 @@
+//! Liveness and readiness of the controller, for the Kubernetes probes.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How long a reconcile may run, or the loop driving the controller go without running, before it is considered
+/// stuck. The propagation waits are requeues, not part of a reconcile, so even the slowest ones take seconds.
+pub const DEFAULT_LIVENESS_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+pub struct Health {
+    /// Since when this replica is the leader, or since the startup without leader election
+    started: Mutex<Instant>,
+    /// Waiting for the leadership
+    standby: AtomicBool,
+    credentials_validated: AtomicBool,
+    /// Set by the first reconcile: the controller only reconciles once its node watcher has listed the nodes
+    reconciling: AtomicBool,
+    next_id: AtomicU64,
+    /// Start of the reconciles in progress
+    in_flight: Mutex<HashMap<u64, Instant>>,
+    /// When the loop driving the controller last ticked, `None` before the first tick
+    last_tick: Mutex<Option<Instant>>,
+    /// The controller stopped, it will not reconcile anymore
+    stopped: AtomicBool,
+    liveness_timeout: Duration,
+}
+
+/// A reconcile in progress, until dropped
+pub struct InFlight<'a> {
+    health: &'a Health,
+    id: u64,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.health.in_flight.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl Health {
+    pub fn new(liveness_timeout: Duration) -> Self {
+        Health {
+            started: Mutex::new(Instant::now()),
+            standby: AtomicBool::new(false),
+            credentials_validated: AtomicBool::new(false),
+            reconciling: AtomicBool::new(false),
+            next_id: AtomicU64::new(0),
+            in_flight: Mutex::new(HashMap::new()),
+            last_tick: Mutex::new(None),
+            stopped: AtomicBool::new(false),
+            liveness_timeout,
+        }
+    }
+
+    /// A standby does not reconcile, but is healthy and ready to take over
+    pub fn standby(&self) {
+        self.standby.store(true, Ordering::Relaxed);
+    }
+
+    /// The watcher starts when the leadership is acquired, give it the full timeout from there
+    pub fn leading(&self) {
+        *self.started.lock().unwrap() = Instant::now();
+        self.standby.store(false, Ordering::Relaxed);
+    }
+
+    pub fn credentials_validated(&self) {
+        self.credentials_validated.store(true, Ordering::Relaxed);
+    }
+
+    pub fn reconcile_started(&self) -> InFlight<'_> {
+        self.reconciling.store(true, Ordering::Relaxed);
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.in_flight.lock().unwrap().insert(id, Instant::now());
+        InFlight { health: self, id }
+    }
+
+    /// The loop driving the controller is still running, told by a ticker within that loop. Unlike the reconciles,
+    /// the ticks keep coming in a quiet cluster.
+    pub fn tick(&self) {
+        *self.last_tick.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// The stream of the controller ended
+    pub fn stopped(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.credentials_validated.load(Ordering::Relaxed)
+            && (self.standby.load(Ordering::Relaxed) || self.reconciling.load(Ordering::Relaxed))
+    }
+
+    /// Not alive when the controller stopped, when its loop did not tick within the timeout, when it did not start
+    /// any reconcile within the timeout, i.e. its watcher never listed the nodes, or when a reconcile is stuck
+    pub fn is_alive(&self) -> bool {
+        if self.standby.load(Ordering::Relaxed) {
+            return true;
+        }
+        if self.stopped.load(Ordering::Relaxed) {
+            return false;
+        }
+        let started = *self.started.lock().unwrap();
+        if !self.reconciling.load(Ordering::Relaxed) && started.elapsed() >= self.liveness_timeout {
+            return false;
+        }
+        let last_tick = self.last_tick.lock().unwrap().unwrap_or(started);
+        if last_tick.elapsed() >= self.liveness_timeout {
+            return false;
+        }
+        self.in_flight
+            .lock()
+            .unwrap()
+            .values()
+            .all(|started| started.elapsed() < self.liveness_timeout)
+    }
+}
@@ -0,0 +1,228 @@
+//! Leader election with a `coordination.k8s.io` Lease, so that only one of the replicas manages the records while
+//! the others stand by.
+
+use crate::config::Config;
+use crate::errors::Error;
+use k8s_openapi::api::coordination::v1::{Lease, LeaseSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::MicroTime;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::api::{Api, ObjectMeta, PostParams};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+pub const DEFAULT_LEASE_NAME: &str = "node-dns";
+
+/// How long the lease is valid without a renewal, and so how soon a standby takes over from a crashed leader
+const LEASE_DURATION: Duration = Duration::from_secs(15);
+
+/// How long the leader keeps trying to renew the lease before giving up the leadership, shorter than the lease
+/// duration so that it stops before a standby may take over
+const RENEW_DEADLINE: Duration = Duration::from_secs(10);
+
+/// How often the leader renews the lease, and the standbys try to acquire it
+const RETRY_PERIOD: Duration = Duration::from_secs(2);
+
+/// The lease to write, for `identity` to hold it, or `None` when another holder's lease has not expired yet
+pub fn claim(
+    current: Option<&LeaseSpec>,
+    identity: &str,
+    lease_duration: Duration,
+    now: DateTime<Utc>,
+) -> Option<LeaseSpec> {
+    let lease_duration_seconds = Some(lease_duration.as_secs() as i32);
+    let current = match current {
+        Some(current) => current,
+        None => {
+            return Some(LeaseSpec {
+                holder_identity: Some(identity.to_string()),
+                lease_duration_seconds,
+                acquire_time: Some(MicroTime(now)),
+                renew_time: Some(MicroTime(now)),
+                lease_transitions: Some(0),
+            })
+        }
+    };
+    if current.holder_identity.as_deref() == Some(identity) {
+        return Some(LeaseSpec {
+            lease_duration_seconds,
+            renew_time: Some(MicroTime(now)),
+            ..current.clone()
+        });
+    }
+    let expired = match (
+        &current.holder_identity,
+        &current.renew_time,
+        current.lease_duration_seconds,
+    ) {
+        (Some(_), Some(MicroTime(renew_time)), Some(seconds)) => {
+            *renew_time + k8s_openapi::chrono::Duration::seconds(seconds.into()) <= now
+        }
+        // Released, or never held
+        _ => true,
+    };
+    if !expired {
+        return None;
+    }
+    Some(LeaseSpec {
+        holder_identity: Some(identity.to_string()),
+        lease_duration_seconds,
+        acquire_time: Some(MicroTime(now)),
+        renew_time: Some(MicroTime(now)),
+        lease_transitions: Some(current.lease_transitions.unwrap_or(0) + 1),
+    })
+}
+
+pub struct LeaderElection {
+    leases: Api<Lease>,
+    name: String,
+    /// Pod name, unique among the replicas
+    identity: String,
+}
+
+impl LeaderElection {
+    pub fn new(client: kube::Client, namespace: &str, name: &str, identity: &str) -> Self {
+        LeaderElection {
+            leases: Api::namespaced(client, namespace),
+            name: name.to_string(),
+            identity: identity.to_string(),
+        }
+    }
+
+    /// Enabled by `LEADER_ELECTION`. The lease is `LEADER_ELECTION_LEASE` in the `POD_NAMESPACE` namespace, held by
+    /// `POD_NAME`, both usually set through the downward API.
+    pub fn from_config(client: kube::Client, config: &Config) -> Option<Self> {
+        if !config.leader_election {
+            return None;
+        }
+        let identity = config
+            .pod_name
+            .clone()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .unwrap_or_else(|| format!("node-dns-{}", std::process::id()));
+        Some(LeaderElection::new(
+            client,
+            &config.pod_namespace,
+            &config.leader_election_lease,
+            &identity,
+        ))
+    }
+
+    /// The lease, or `None` when it was never created
+    async fn get(&self) -> Result<Option<Lease>, kube::Error> {
+        match self.leases.get(&self.name).await {
+            Ok(lease) => Ok(Some(lease)),
+            Err(kube::Error::Api(response)) if response.code == 404 => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Acquire or renew the lease. `false` when someone else holds it, or won the race for it.
+    async fn try_acquire_or_renew(&self) -> Result<bool, Error> {
+        let current = self.get().await?;
+        let spec = match claim(
+            current.as_ref().and_then(|lease| lease.spec.as_ref()),
+            &self.identity,
+            LEASE_DURATION,
+            Utc::now(),
+        ) {
+            Some(spec) => spec,
+            None => return Ok(false),
+        };
+        let result = match current {
+            Some(lease) => {
+                // The resource version makes the replace fail if another replica updated the lease in the meantime
+                let lease = Lease {
+                    spec: Some(spec),
+                    ..lease
+                };
+                self.leases.replace(&self.name, &PostParams::default(), &lease).await
+            }
+            None => {
+                let lease = Lease {
+                    metadata: ObjectMeta {
+                        name: Some(self.name.clone()),
+                        ..ObjectMeta::default()
+                    },
+                    spec: Some(spec),
+                };
+                self.leases.create(&PostParams::default(), &lease).await
+            }
+        };
+        match result {
+            Ok(_) => Ok(true),
+            Err(kube::Error::Api(response)) if response.code == 409 => Ok(false),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Wait until this replica holds the lease
+    pub async fn acquire(&self) {
+        info!(
+            lease = self.name.as_str(),
+            identity = self.identity.as_str(),
+            "Waiting for the leadership"
+        );
+        loop {
+            match self.try_acquire_or_renew().await {
+                Ok(true) => {
+                    info!(identity = self.identity.as_str(), "Acquired the leadership");
+                    return;
+                }
+                Ok(false) => debug!("Another replica is the leader"),
+                Err(error) => warn!(error = format!("{}", error).as_str(), "Failed to acquire the lease"),
+            }
+            tokio::time::sleep(RETRY_PERIOD).await;
+        }
+    }
+
+    /// Keep renewing the lease, and return when it could not be renewed in time: another replica may take over
+    /// any moment, so the caller must stop working right away.
+    pub async fn hold(&self) -> Error {
+        let mut renewed = Instant::now();
+        loop {
+            tokio::time::sleep(RETRY_PERIOD).await;
+            // A request hanging past the deadline must not keep the leadership either
+            let renewal = tokio::time::timeout_at(renewed + RENEW_DEADLINE, self.try_acquire_or_renew()).await;
+            match renewal {
+                Ok(Ok(true)) => renewed = Instant::now(),
+                Ok(Ok(false)) => return Error::LeadershipLost(self.identity.clone()),
+                Ok(Err(error)) => warn!(error = format!("{}", error).as_str(), "Failed to renew the lease"),
+                Err(_) => {
+                    warn!("Renewing the lease timed out");
+                    return Error::LeadershipLost(self.identity.clone());
+                }
+            }
+            if renewed.elapsed() >= RENEW_DEADLINE {
+                return Error::LeadershipLost(self.identity.clone());
+            }
+        }
+    }
+
+    /// Give up the lease on shutdown, so that a standby takes over without waiting for it to expire
+    pub async fn release(&self) {
+        let lease = match self.get().await {
+            Ok(Some(lease)) => lease,
+            Ok(None) => return,
+            Err(error) => {
+                warn!(error = format!("{}", error).as_str(), "Failed to release the lease");
+                return;
+            }
+        };
+        let spec = lease.spec.clone().unwrap_or_default();
+        if spec.holder_identity.as_deref() != Some(self.identity.as_str()) {
+            return;
+        }
+        let lease = Lease {
+            spec: Some(LeaseSpec {
+                holder_identity: None,
+                ..spec
+            }),
+            ..lease
+        };
+        match self.leases.replace(&self.name, &PostParams::default(), &lease).await {
+            Ok(_) => info!("Released the leadership"),
+            Err(error) => warn!(error = format!("{}", error).as_str(), "Failed to release the lease"),
+        }
+    }
+}
@@ -1,5 +1,20 @@
+pub mod cloudflare;
+pub mod config;
 pub mod controller;
 pub mod dns;
 pub mod errors;
+pub mod health;
+pub mod leader;
 pub mod linode;
 pub mod logging;
+pub mod metrics;
+pub mod naming;
+pub mod preflight;
+pub mod provider;
+pub mod registry;
+pub mod reporting;
+pub mod retry;
+pub mod selection;
+pub mod server;
+pub mod state;
+pub mod tsig;
@@ -1,14 +1,285 @@
-use anyhow::{Error, Result};
+use crate::metrics;
+use crate::retry;
+use futures::{future, pin_mut, stream, Stream, StreamExt, TryStreamExt};
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
 use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tracing::{debug, warn};
 
-const BASE_URL: &str = "https://api.linode.com/v4/";
+pub const DEFAULT_BASE_URL: &str = "https://api.linode.com/v4/";
+
+/// Authoritative for the Linode domains, queried directly to verify the records
+pub const NAME_SERVERS: [&str; 5] = [
+    "ns1.linode.com",
+    "ns2.linode.com",
+    "ns3.linode.com",
+    "ns4.linode.com",
+    "ns5.linode.com",
+];
+
+/// Linode accepts page sizes between 25 and 500, the default being 100.
+const MIN_PAGE_SIZE: u64 = 25;
+const MAX_PAGE_SIZE: u64 = 500;
+
+/// Domain records are re-fetched after this long, to notice the changes made outside of this controller.
+const DEFAULT_RECORD_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Warn when the remaining request quota drops below this fraction of the limit
+const RATE_LIMIT_WARNING_THRESHOLD: f64 = 0.1;
+
+pub type Result<T> = std::result::Result<T, LinodeError>;
+
+/// A single entry of the `errors` array Linode returns with every failed request.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    errors: Vec<FieldError>,
+}
+
+/// Linode API failures, classified by what the caller can do about them.
+#[derive(Debug, Error)]
+pub enum LinodeError {
+    #[error("Linode API token is invalid or expired: {}", reasons(.0))]
+    Unauthorized(Vec<FieldError>),
+    #[error("Linode API token is missing a required scope or grant: {}", reasons(.0))]
+    Forbidden(Vec<FieldError>),
+    #[error("Linode API object not found: {}", reasons(.0))]
+    NotFound(Vec<FieldError>),
+    #[error("Linode API rejected the request: {}", reasons(.0))]
+    Validation(Vec<FieldError>),
+    #[error("Linode API rate limit exceeded: {}", reasons(.0))]
+    RateLimited(Vec<FieldError>),
+    #[error("Linode API server error ({status}): {}", reasons(.errors))]
+    Server {
+        status: StatusCode,
+        errors: Vec<FieldError>,
+    },
+    #[error("Unexpected Linode API response ({status}): {}", reasons(.errors))]
+    Unexpected {
+        status: StatusCode,
+        errors: Vec<FieldError>,
+    },
+    #[error("Linode API request failed: {0}")]
+    Request(#[from] reqwest::Error),
+}
+
+fn reasons(errors: &[FieldError]) -> String {
+    if errors.is_empty() {
+        return "no details given".to_string();
+    }
+    errors
+        .iter()
+        .map(|error| match &error.field {
+            Some(field) => format!("{}: {}", field, error.reason),
+            None => error.reason.clone(),
+        })
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+impl LinodeError {
+    async fn from_response(response: reqwest::Response) -> LinodeError {
+        let status = response.status();
+        // The body is informational only, failing to parse it should not hide the status code.
+        let errors = response
+            .json::<ErrorResponse>()
+            .await
+            .map(|body| body.errors)
+            .unwrap_or_default();
+        match status {
+            StatusCode::UNAUTHORIZED => LinodeError::Unauthorized(errors),
+            StatusCode::FORBIDDEN => LinodeError::Forbidden(errors),
+            StatusCode::NOT_FOUND => LinodeError::NotFound(errors),
+            StatusCode::BAD_REQUEST => LinodeError::Validation(errors),
+            StatusCode::TOO_MANY_REQUESTS => LinodeError::RateLimited(errors),
+            status if status.is_server_error() => LinodeError::Server { status, errors },
+            status => LinodeError::Unexpected { status, errors },
+        }
+    }
+
+    /// The credentials are wrong, retrying will not help until the token is replaced.
+    pub fn is_auth_failure(&self) -> bool {
+        matches!(self, LinodeError::Unauthorized(_) | LinodeError::Forbidden(_))
+    }
+
+    /// The failure is transient, and the same request may succeed later.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            LinodeError::RateLimited(_) | LinodeError::Server { .. } => true,
+            LinodeError::Request(error) => retry::is_transient_error(error),
+            _ => false,
+        }
+    }
+
+    /// Field-level details Linode gave about the failure
+    pub fn field_errors(&self) -> &[FieldError] {
+        match self {
+            LinodeError::Unauthorized(errors)
+            | LinodeError::Forbidden(errors)
+            | LinodeError::NotFound(errors)
+            | LinodeError::Validation(errors)
+            | LinodeError::RateLimited(errors)
+            | LinodeError::Server { errors, .. }
+            | LinodeError::Unexpected { errors, .. } => errors,
+            LinodeError::Request(_) => &[],
+        }
+    }
+}
 
 /// Minimal Linode API client, just what is needed for this app.
+#[derive(Clone)]
 pub struct Client {
     client: reqwest::Client,
-    token: String,
+    base_url: String,
+    /// Shared by all clones of a client, so that replacing it affects them all
+    token: Arc<RwLock<String>>,
+    page_size: u64,
+    max_retries: u32,
+    rate_limit: Arc<Mutex<Option<RateLimit>>>,
+    record_cache_ttl: Duration,
+    cache: Arc<Mutex<Cache>>,
+}
+
+/// Lookups shared by all clones of a client. Our own changes are applied to the cache as they are made,
+/// everything else is picked up when the records expire.
+#[derive(Default)]
+struct Cache {
+    domain_ids: HashMap<String, u64>,
+    records: HashMap<(u64, Filter), CachedRecords>,
+    /// Bumped by every change to the records of a domain, so that a list fetched meanwhile is not cached
+    generations: HashMap<u64, u64>,
+}
+
+struct CachedRecords {
+    fetched_at: Instant,
+    records: Vec<DomainRecordResponse>,
+}
+
+impl Cache {
+    fn invalidate_domain(&mut self, domain_id: u64) {
+        self.domain_ids.retain(|_, id| *id != domain_id);
+        self.records.retain(|(id, _), _| *id != domain_id);
+        self.bump_generation(domain_id);
+    }
+
+    fn generation(&self, domain_id: u64) -> u64 {
+        self.generations.get(&domain_id).copied().unwrap_or_default()
+    }
+
+    fn bump_generation(&mut self, domain_id: u64) {
+        *self.generations.entry(domain_id).or_default() += 1;
+    }
+}
+
+/// Server-side filter for list endpoints, sent in the `X-Filter` header. All the conditions must match.
+#[derive(Serialize, Default, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Filter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    type_: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    public: Option<bool>,
+}
+
+impl Filter {
+    pub fn new() -> Filter {
+        Filter::default()
+    }
+
+    pub fn name(mut self, name: &str) -> Filter {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Record type for domain records (`A`, `AAAA`, ...), address type for IPs (`ipv4`, `ipv6`, ...)
+    pub fn type_(mut self, type_: &str) -> Filter {
+        self.type_ = Some(type_.to_string());
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Filter {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    pub fn target(mut self, target: &str) -> Filter {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    pub fn region(mut self, region: &str) -> Filter {
+        self.region = Some(region.to_string());
+        self
+    }
+
+    pub fn public(mut self, public: bool) -> Filter {
+        self.public = Some(public);
+        self
+    }
+
+    fn header(&self) -> Option<String> {
+        if *self == Filter::default() {
+            None
+        } else {
+            Some(serde_json::to_string(self).expect("Filter is always serializable"))
+        }
+    }
+
+    /// Whether the domain record would be returned by a list call with this filter
+    fn matches_record(&self, record: &DomainRecordResponse) -> bool {
+        fn matches(condition: &Option<String>, value: Option<&String>) -> bool {
+            condition.is_none() || condition.as_ref() == value
+        }
+        matches(&self.name, Some(&record.name))
+            && matches(&self.type_, Some(&record.type_))
+            && matches(&self.tag, record.tag.as_ref())
+            && matches(&self.target, Some(&record.target))
+    }
+}
+
+/// Request quota, as reported by Linode in the `X-RateLimit-*` headers of the last response.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub limit: u64,
+    pub remaining: u64,
+    /// When the quota is replenished, in seconds since the Unix epoch
+    pub reset: u64,
+}
+
+impl RateLimit {
+    fn from_headers(headers: &HeaderMap) -> Option<RateLimit> {
+        let header = |name: &str| headers.get(name)?.to_str().ok()?.parse::<u64>().ok();
+        Some(RateLimit {
+            limit: header("X-RateLimit-Limit")?,
+            remaining: header("X-RateLimit-Remaining")?,
+            reset: header("X-RateLimit-Reset")?,
+        })
+    }
+
+    /// Time left until the quota is replenished
+    fn reset_after(&self) -> Duration {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Duration::from_secs(self.reset).saturating_sub(now)
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -30,7 +301,7 @@ pub struct DomainResponse {
     pub type_: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct DomainRecordResponse {
     pub created: String,
     pub id: u64,
@@ -65,7 +336,8 @@ pub struct DomainRecordRequest {
 
 #[derive(Serialize, Debug)]
 pub struct RdnsUpdateRequest {
-    pub rdns: String,
+    /// `None` resets the reverse DNS to the Linode default, e.g. `192-0-2-10.ip.linodeusercontent.com`
+    pub rdns: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -81,6 +353,65 @@ pub struct RdnsUpdateResponse {
     pub type_: String,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct ProfileResponse {
+    pub username: String,
+    /// Restricted users only have the access given by their grants
+    #[serde(default)]
+    pub restricted: bool,
+}
+
+/// What a restricted user may do, see `Client::get_grants`
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct GrantsResponse {
+    pub domain: Vec<Grant>,
+    pub linode: Vec<Grant>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Grant {
+    pub id: u64,
+    pub label: String,
+    /// `read_only`, `read_write`, or `None` for no access at all
+    pub permissions: Option<String>,
+}
+
+impl Grant {
+    pub fn is_read_write(&self) -> bool {
+        self.permissions.as_deref() == Some("read_write")
+    }
+}
+
+/// OAuth scopes of a token, e.g. `domains:read_write ips:read_only`, or `*` for all of them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scopes(Vec<String>);
+
+impl Scopes {
+    pub fn parse(scopes: &str) -> Scopes {
+        Scopes(
+            scopes
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|scope| !scope.is_empty())
+                .map(String::from)
+                .collect(),
+        )
+    }
+
+    /// Whether the token can change the resources of the area, e.g. `domains`
+    pub fn can_write(&self, area: &str) -> bool {
+        self.0
+            .iter()
+            .any(|scope| scope == "*" || *scope == format!("{}:read_write", area))
+    }
+}
+
+impl fmt::Display for Scopes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.join(" "))
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct IpAddressResponse {
     pub address: String,
@@ -97,8 +428,8 @@ pub struct IpAddressResponse {
 
 #[derive(Deserialize, Debug)]
 pub struct LinodeResponse<T: DeserializeOwned> {
-    #[serde(deserialize_with = "T::deserialize")]
-    data: T,
+    #[serde(deserialize_with = "Vec::<T>::deserialize")]
+    data: Vec<T>,
     page: u64,
     pages: u64,
     #[allow(dead_code)]
@@ -126,37 +457,226 @@ impl Client {
     pub fn new(token: &str) -> Client {
         Client {
             client: reqwest::Client::new(),
-            token: String::from(token),
+            base_url: String::from(DEFAULT_BASE_URL),
+            token: Arc::new(RwLock::new(String::from(token))),
+            page_size: MAX_PAGE_SIZE,
+            max_retries: retry::DEFAULT_MAX_RETRIES,
+            rate_limit: Arc::new(Mutex::new(None)),
+            record_cache_ttl: DEFAULT_RECORD_CACHE_TTL,
+            cache: Arc::new(Mutex::new(Cache::default())),
+        }
+    }
+
+    /// How long the domain records fetched by `cached_domain_records` are considered fresh.
+    pub fn with_record_cache_ttl(mut self, record_cache_ttl: Duration) -> Client {
+        self.record_cache_ttl = record_cache_ttl;
+        self
+    }
+
+    /// How many times to retry rate-limited requests, or idempotent requests that failed with a server error.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Client {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// The same client with a different token, not shared with this one: to check a token before switching to it.
+    pub fn with_token(&self, token: &str) -> Client {
+        Client {
+            token: Arc::new(RwLock::new(String::from(token))),
+            ..self.clone()
         }
     }
 
+    /// Switch this client and all its clones to another token. The requests already sent keep the previous one.
+    pub fn set_token(&self, token: &str) {
+        *self.token.write().unwrap() = String::from(token);
+    }
+
+    /// Whether the client uses this token
+    pub fn has_token(&self, token: &str) -> bool {
+        *self.token.read().unwrap() == token
+    }
+
+    /// Request quota reported by the last response, if any
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        *self.rate_limit.lock().unwrap()
+    }
+
+    /// Talk to a different API endpoint, e.g. a proxy or a mock server. Endpoint paths are appended to it.
+    pub fn with_base_url(mut self, base_url: &str) -> Client {
+        self.base_url = if base_url.ends_with('/') {
+            String::from(base_url)
+        } else {
+            format!("{}/", base_url)
+        };
+        self
+    }
+
+    /// Number of items to request per page in list calls, clamped to what Linode supports.
+    pub fn with_page_size(mut self, page_size: u64) -> Client {
+        self.page_size = page_size.clamp(MIN_PAGE_SIZE, MAX_PAGE_SIZE);
+        self
+    }
+
     fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
-        let url = format!("{}{}", BASE_URL, path);
+        let url = format!("{}{}", self.base_url, path);
         self.client
             .request(method, &url)
-            .header("Authorization", format!("Bearer {}", self.token))
+            .header("Authorization", format!("Bearer {}", self.token.read().unwrap()))
     }
 
-    async fn get_list<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T> {
-        // TODO: handle pagination
-        let response = self
+    fn record_rate_limit(&self, headers: &HeaderMap) {
+        if let Some(rate_limit) = RateLimit::from_headers(headers) {
+            if (rate_limit.remaining as f64) < (rate_limit.limit as f64) * RATE_LIMIT_WARNING_THRESHOLD {
+                warn!(
+                    limit = rate_limit.limit,
+                    remaining = rate_limit.remaining,
+                    reset = rate_limit.reset,
+                    "Linode API request quota is running low"
+                );
+            }
+            *self.rate_limit.lock().unwrap() = Some(rate_limit);
+        }
+    }
+
+    /// How long the server asked us to wait: `Retry-After` (in seconds) if present, otherwise until the quota
+    /// reset time if the quota is exhausted. At most `retry::MAX_DELAY` either way.
+    fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+        retry::retry_after(headers).or_else(|| {
+            RateLimit::from_headers(headers)
+                .filter(|rate_limit| rate_limit.remaining == 0)
+                .map(|rate_limit| rate_limit.reset_after().min(retry::MAX_DELAY))
+        })
+    }
+
+    /// Path of the request relative to the base URL, with the ids and the addresses replaced by placeholders, e.g.
+    /// `domains/{id}/records`, to label the metrics without one series per record.
+    fn endpoint(&self, url: &reqwest::Url) -> String {
+        let path = url.as_str().strip_prefix(&self.base_url).unwrap_or_else(|| url.path());
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        path.split('/')
+            .map(|segment| {
+                if !segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit()) {
+                    "{id}"
+                } else if segment.parse::<IpAddr>().is_ok() {
+                    "{ip}"
+                } else {
+                    segment
+                }
+            })
+            .collect::<Vec<&str>>()
+            .join("/")
+    }
+
+    /// Send the request, retrying when Linode asks us to slow down (429), and on server errors or connection
+    /// failures when the request is idempotent, see [`retry`].
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let request = request.build()?;
+        let idempotent = retry::is_idempotent(&request);
+        let endpoint = self.endpoint(request.url());
+        let mut attempt = 0;
+        loop {
+            let attempt_request = request.try_clone().expect("Linode API requests have cloneable bodies");
+            let response = self.client.execute(attempt_request).await;
+            let status = match &response {
+                Ok(response) => response.status().as_u16().to_string(),
+                Err(_) => "error".to_string(),
+            };
+            metrics::LINODE_REQUESTS.inc(&[endpoint.as_str(), status.as_str()]);
+            let delay = match response {
+                Ok(response) => {
+                    self.record_rate_limit(response.headers());
+                    let status = response.status();
+                    if status.is_success() {
+                        return Ok(response);
+                    }
+                    if !retry::is_retryable_status(status, idempotent) || attempt >= self.max_retries {
+                        return Err(LinodeError::from_response(response).await);
+                    }
+                    let delay = Self::retry_after(response.headers()).unwrap_or_else(|| retry::backoff(attempt));
+                    warn!(
+                        status = status.as_u16(),
+                        url = request.url().as_str(),
+                        attempt,
+                        delay_ms = delay.as_millis() as u64,
+                        remaining = self.rate_limit().map(|rate_limit| rate_limit.remaining),
+                        "Linode API request failed, retrying"
+                    );
+                    delay
+                }
+                Err(error) if retry::is_retryable_error(&error, idempotent) && attempt < self.max_retries => {
+                    let delay = retry::backoff(attempt);
+                    warn!(
+                        error = format!("{}", error).as_str(),
+                        url = request.url().as_str(),
+                        attempt,
+                        delay_ms = delay.as_millis() as u64,
+                        "Linode API request failed, retrying"
+                    );
+                    delay
+                }
+                Err(error) => return Err(error.into()),
+            };
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+            debug!(url = request.url().as_str(), attempt, "Retrying Linode API request");
+        }
+    }
+
+    async fn get_page<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        filter: &Filter,
+        page: u64,
+    ) -> Result<LinodeResponse<T>> {
+        let mut request = self
             .request(reqwest::Method::GET, endpoint)
-            .send()
+            .query(&[("page", page), ("page_size", self.page_size)]);
+        if let Some(filter) = filter.header() {
+            request = request.header("X-Filter", filter);
+        }
+        let response = self.send(request).await?.json::<LinodeResponse<T>>().await?;
+        Ok(response)
+    }
+
+    /// Stream all items of a list endpoint, fetching the next page only when the previous one is exhausted,
+    /// so that the callers that stop early do not pay for the pages they did not need.
+    fn list<'a, T: DeserializeOwned + 'a>(
+        &'a self,
+        endpoint: String,
+        filter: Filter,
+    ) -> impl Stream<Item = Result<T>> + 'a {
+        stream::try_unfold(Some(1), move |page| {
+            let endpoint = endpoint.clone();
+            let filter = filter.clone();
+            async move {
+                match page {
+                    None => Ok(None),
+                    Some(page) => self.get_page::<T>(&endpoint, &filter, page).await.map(|response| {
+                        let next_page = (response.page < response.pages).then(|| response.page + 1);
+                        Some((stream::iter(response.data).map(Ok), next_page))
+                    }),
+                }
+            }
+        })
+        .try_flatten()
+    }
+
+    async fn get_list<T: DeserializeOwned>(&self, endpoint: &str, filter: &Filter) -> Result<Vec<T>> {
+        self.list(endpoint.to_string(), filter.clone()).try_collect().await
+    }
+
+    async fn get<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T> {
+        let response = self
+            .send(self.request(reqwest::Method::GET, endpoint))
             .await?
-            .error_for_status()?
-            .json::<LinodeResponse<T>>()
+            .json::<T>()
             .await?;
-        if (response.page != 1) || (response.pages != 1) {
-            return Err(Error::msg("Pagination is required but is not implemented"));
-        }
-        Ok(response.data)
+        Ok(response)
     }
 
     async fn delete(&self, endpoint: &str) -> Result<()> {
-        self.request(reqwest::Method::DELETE, endpoint)
-            .send()
-            .await?
-            .error_for_status()?;
+        self.send(self.request(reqwest::Method::DELETE, endpoint)).await?;
         Ok(())
     }
 
@@ -167,11 +687,8 @@ impl Client {
         body: &B,
     ) -> Result<T> {
         let response = self
-            .request(method, endpoint)
-            .json(body)
-            .send()
+            .send(self.request(method, endpoint).json(body))
             .await?
-            .error_for_status()?
             .json::<T>()
             .await?;
         Ok(response)
@@ -185,12 +702,107 @@ impl Client {
         self.request_with_body(reqwest::Method::PUT, endpoint, body).await
     }
 
+    pub fn domains(&self) -> impl Stream<Item = Result<DomainResponse>> + '_ {
+        self.list("domains".to_string(), Filter::new())
+    }
+
     pub async fn get_domains(&self) -> Result<Vec<DomainResponse>> {
-        self.get_list("domains").await
+        self.get_list("domains", &Filter::new()).await
+    }
+
+    /// The domain with this name, `None` when the account has no such domain
+    pub async fn get_domain(&self, domain: &str) -> Result<Option<DomainResponse>> {
+        let domains = self.domains().try_filter(|d| future::ready(d.domain == domain));
+        pin_mut!(domains);
+        domains.try_next().await
+    }
+
+    pub fn domain_records(
+        &self,
+        domain_id: u64,
+        filter: &Filter,
+    ) -> impl Stream<Item = Result<DomainRecordResponse>> + '_ {
+        self.list(format!("domains/{}/records", domain_id), filter.clone())
+    }
+
+    pub async fn get_domain_records(&self, domain_id: u64, filter: &Filter) -> Result<Vec<DomainRecordResponse>> {
+        self.get_list(&format!("domains/{}/records", domain_id), filter).await
     }
 
-    pub async fn get_domain_records(&self, domain_id: u64) -> Result<Vec<DomainRecordResponse>> {
-        self.get_list(&format!("domains/{}/records", domain_id)).await
+    /// Id of the domain with the given name, looked up once and remembered.
+    pub async fn domain_id(&self, domain: &str) -> Result<Option<u64>> {
+        if let Some(id) = self.cache.lock().unwrap().domain_ids.get(domain) {
+            return Ok(Some(*id));
+        }
+        let domains = self.domains().try_filter(|d| future::ready(d.domain == domain));
+        pin_mut!(domains);
+        let id = domains.try_next().await?.map(|d| d.id);
+        if let Some(id) = id {
+            self.cache.lock().unwrap().domain_ids.insert(domain.to_string(), id);
+        }
+        Ok(id)
+    }
+
+    /// Records of the domain matching the filter, served from the cache while it is fresh.
+    pub async fn cached_domain_records(&self, domain_id: u64, filter: &Filter) -> Result<Vec<DomainRecordResponse>> {
+        let key = (domain_id, filter.clone());
+        let generation = {
+            let cache = self.cache.lock().unwrap();
+            if let Some(cached) = cache.records.get(&key) {
+                if cached.fetched_at.elapsed() < self.record_cache_ttl {
+                    return Ok(cached.records.clone());
+                }
+            }
+            cache.generation(domain_id)
+        };
+        let records = self
+            .get_domain_records(domain_id, filter)
+            .await
+            .map_err(|err| self.not_found(domain_id, err))?;
+        let cached = CachedRecords {
+            fetched_at: Instant::now(),
+            records: records.clone(),
+        };
+        let mut cache = self.cache.lock().unwrap();
+        // A change made while fetching is in the cached lists but maybe not in this one: do not overwrite them
+        if cache.generation(domain_id) == generation {
+            // One list per queried name: drop the expired ones, or the names of long gone nodes pile up
+            let ttl = self.record_cache_ttl;
+            cache.records.retain(|_, cached| cached.fetched_at.elapsed() < ttl);
+            cache.records.insert(key, cached);
+        }
+        Ok(records)
+    }
+
+    /// Forget everything cached about the domain, e.g. when it was changed outside of this controller.
+    pub fn invalidate_domain(&self, domain_id: u64) {
+        self.cache.lock().unwrap().invalidate_domain(domain_id);
+    }
+
+    /// Something we thought exists does not: the cached view of the domain cannot be trusted anymore.
+    fn not_found(&self, domain_id: u64, err: LinodeError) -> LinodeError {
+        if let LinodeError::NotFound(_) = err {
+            self.invalidate_domain(domain_id);
+        }
+        err
+    }
+
+    /// Apply our own change to every cached list of the domain's records: drop the record with the given id,
+    /// and add the new version of it to the lists whose filter it matches.
+    fn update_cached_records(&self, domain_id: u64, removed: Option<u64>, added: Option<&DomainRecordResponse>) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.bump_generation(domain_id);
+        for ((id, filter), cached) in cache.records.iter_mut() {
+            if *id != domain_id {
+                continue;
+            }
+            if let Some(removed) = removed {
+                cached.records.retain(|r| r.id != removed);
+            }
+            if let Some(added) = added.filter(|added| filter.matches_record(added)) {
+                cached.records.push(added.clone());
+            }
+        }
     }
 
     pub async fn create_domain_record(
@@ -198,13 +810,20 @@ impl Client {
         domain_id: u64,
         domain_record: DomainRecordRequest,
     ) -> Result<DomainRecordResponse> {
-        self.post(&format!("domains/{}/records", domain_id), &domain_record)
+        let record: DomainRecordResponse = self
+            .post(&format!("domains/{}/records", domain_id), &domain_record)
             .await
+            .map_err(|err| self.not_found(domain_id, err))?;
+        self.update_cached_records(domain_id, None, Some(&record));
+        Ok(record)
     }
 
     pub async fn delete_domain_record(&self, domain_id: u64, domain_record_id: u64) -> Result<()> {
         self.delete(&format!("domains/{}/records/{}", domain_id, domain_record_id))
             .await
+            .map_err(|err| self.not_found(domain_id, err))?;
+        self.update_cached_records(domain_id, Some(domain_record_id), None);
+        Ok(())
     }
 
     pub async fn update_domain_record(
@@ -213,22 +832,66 @@ impl Client {
         domain_record_id: u64,
         domain_record: DomainRecordRequest,
     ) -> Result<DomainRecordResponse> {
-        self.put(
-            &format!("domains/{}/records/{}", domain_id, domain_record_id),
-            &domain_record,
-        )
-        .await
+        let record: DomainRecordResponse = self
+            .put(
+                &format!("domains/{}/records/{}", domain_id, domain_record_id),
+                &domain_record,
+            )
+            .await
+            .map_err(|err| self.not_found(domain_id, err))?;
+        self.update_cached_records(domain_id, Some(domain_record_id), Some(&record));
+        Ok(record)
     }
 
     pub async fn update_rdns(&self, ip: IpAddr, fqdn: &str) -> Result<RdnsUpdateResponse> {
         self.put(
-            &format!("networking/ips/{}", ip.to_string()),
-            &RdnsUpdateRequest { rdns: fqdn.to_string() },
+            &format!("networking/ips/{}", ip),
+            &RdnsUpdateRequest {
+                rdns: Some(fqdn.to_string()),
+            },
         )
         .await
     }
 
-    pub async fn get_ip_addresses(&self) -> Result<Vec<IpAddressResponse>> {
-        self.get_list("networking/ips").await
+    pub async fn reset_rdns(&self, ip: IpAddr) -> Result<RdnsUpdateResponse> {
+        self.put(&format!("networking/ips/{}", ip), &RdnsUpdateRequest { rdns: None })
+            .await
+    }
+
+    pub fn ip_addresses(&self, filter: &Filter) -> impl Stream<Item = Result<IpAddressResponse>> + '_ {
+        self.list("networking/ips".to_string(), filter.clone())
+    }
+
+    pub async fn get_ip_addresses(&self, filter: &Filter) -> Result<Vec<IpAddressResponse>> {
+        self.get_list("networking/ips", filter).await
+    }
+
+    pub async fn get_ip_address(&self, ip: IpAddr) -> Result<IpAddressResponse> {
+        self.get(&format!("networking/ips/{}", ip)).await
+    }
+
+    /// The user the token belongs to, also a cheap way to check that the token works
+    pub async fn get_profile(&self) -> Result<ProfileResponse> {
+        self.get("profile").await
+    }
+
+    /// Scopes of the token, from the `X-OAuth-Scopes` header Linode adds to its responses. `None` when the header
+    /// is missing.
+    pub async fn get_token_scopes(&self) -> Result<Option<Scopes>> {
+        let response = self.send(self.request(reqwest::Method::GET, "profile")).await?;
+        Ok(response
+            .headers()
+            .get("X-OAuth-Scopes")
+            .and_then(|scopes| scopes.to_str().ok())
+            .map(Scopes::parse))
+    }
+
+    /// Grants of a restricted user, `None` for an unrestricted one, who can access everything on the account
+    pub async fn get_grants(&self) -> Result<Option<GrantsResponse>> {
+        let response = self.send(self.request(reqwest::Method::GET, "profile/grants")).await?;
+        if response.status() == StatusCode::NO_CONTENT {
+            return Ok(None);
+        }
+        Ok(Some(response.json().await?))
     }
 }
@@ -1,3 +1,4 @@
+use node_dns::config::{self, Command, Config};
 use node_dns::controller;
 use node_dns::logging;
 use tracing::{error, info};
@@ -5,7 +6,26 @@ use tracing::{error, info};
 #[tokio::main]
 async fn main() {
     logging::setup();
-    std::process::exit(controller::run().await.map_or_else(
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = match Config::load(&args, |name| std::env::var(name).ok()) {
+        Ok(Command::Run(config)) => config,
+        Ok(Command::PrintConfig(config)) => {
+            print!("{}", config.to_yaml());
+            return;
+        }
+        Ok(Command::Help) => {
+            print!("{}", config::usage());
+            return;
+        }
+        Err(error) => {
+            error!(
+                error = format!("{}", error).as_str(),
+                "Invalid configuration, terminating"
+            );
+            std::process::exit(2);
+        }
+    };
+    std::process::exit(controller::run(config).await.map_or_else(
         |error| {
             error!(error = format!("{}", error).as_str(), "Fatal error, terminating");
             1
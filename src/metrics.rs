@@ -0,0 +1,235 @@
+//! Prometheus metrics of the controller, in the text exposition format.
+
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Reconciles take from milliseconds (cache hits) to seconds (API calls, DNS lookups)
+const DURATION_BUCKETS: &[f64] = &[0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Counters by label values, in the order of the label names
+pub struct CounterVec {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, u64>>,
+}
+
+impl CounterVec {
+    fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        CounterVec {
+            name,
+            help,
+            labels,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inc(&self, label_values: &[&str]) {
+        let key = label_values.iter().map(|value| value.to_string()).collect();
+        *self.values.lock().unwrap().entry(key).or_insert(0) += 1;
+    }
+
+    pub fn get(&self, label_values: &[&str]) -> u64 {
+        let key: Vec<String> = label_values.iter().map(|value| value.to_string()).collect();
+        self.values.lock().unwrap().get(&key).copied().unwrap_or(0)
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "counter");
+        for (label_values, value) in self.values.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "{}{} {}",
+                self.name,
+                labels(self.labels, label_values, None),
+                value
+            );
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    /// Cumulative, one per bucket
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// Histograms of durations by label values
+pub struct HistogramVec {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    buckets: &'static [f64],
+    values: Mutex<BTreeMap<Vec<String>, Histogram>>,
+}
+
+impl HistogramVec {
+    fn new(name: &'static str, help: &'static str, labels: &'static [&'static str], buckets: &'static [f64]) -> Self {
+        HistogramVec {
+            name,
+            help,
+            labels,
+            buckets,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn observe(&self, label_values: &[&str], duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let key = label_values.iter().map(|value| value.to_string()).collect();
+        let mut values = self.values.lock().unwrap();
+        let histogram = values.entry(key).or_insert_with(|| Histogram {
+            counts: vec![0; self.buckets.len()],
+            ..Histogram::default()
+        });
+        for (count, bound) in histogram.counts.iter_mut().zip(self.buckets.iter()) {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+        histogram.sum += seconds;
+        histogram.count += 1;
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "histogram");
+        for (label_values, histogram) in self.values.lock().unwrap().iter() {
+            for (count, bound) in histogram.counts.iter().zip(self.buckets.iter()) {
+                let le = bound.to_string();
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{} {}",
+                    self.name,
+                    labels(self.labels, label_values, Some(&le)),
+                    count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{} {}",
+                self.name,
+                labels(self.labels, label_values, Some("+Inf")),
+                histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "{}_sum{} {}",
+                self.name,
+                labels(self.labels, label_values, None),
+                histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "{}_count{} {}",
+                self.name,
+                labels(self.labels, label_values, None),
+                histogram.count
+            );
+        }
+    }
+}
+
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicI64,
+}
+
+impl Gauge {
+    fn new(name: &'static str, help: &'static str) -> Self {
+        Gauge {
+            name,
+            help,
+            value: AtomicI64::new(0),
+        }
+    }
+
+    pub fn set(&self, value: usize) {
+        self.value.store(value as i64, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "gauge");
+        let _ = writeln!(out, "{} {}", self.name, self.get());
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, type_: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, type_);
+}
+
+fn labels(names: &[&str], values: &[String], le: Option<&str>) -> String {
+    let mut pairs: Vec<String> = names
+        .iter()
+        .zip(values.iter())
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+    if pairs.is_empty() {
+        return String::new();
+    }
+    format!("{{{}}}", pairs.join(","))
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+lazy_static! {
+    /// By outcome: `success`, `pending` (waiting for the records to propagate) or `error`
+    pub static ref RECONCILES: CounterVec =
+        CounterVec::new("node_dns_reconciles_total", "Node reconciles", &["outcome"]);
+    pub static ref RECONCILE_DURATION: HistogramVec = HistogramVec::new(
+        "node_dns_reconcile_duration_seconds",
+        "Duration of the node reconciles",
+        &["outcome"],
+        DURATION_BUCKETS,
+    );
+    /// By kind of error, see `Error::kind`
+    pub static ref RECONCILE_ERRORS: CounterVec =
+        CounterVec::new("node_dns_reconcile_errors_total", "Failed node reconciles", &["error"]);
+    /// By endpoint, with the ids and addresses replaced by placeholders, and by HTTP status, or `error` when no
+    /// response was received
+    pub static ref LINODE_REQUESTS: CounterVec = CounterVec::new(
+        "node_dns_linode_requests_total",
+        "Linode API requests, including the retries",
+        &["endpoint", "status"],
+    );
+    /// By check: `forward` or `reverse`
+    pub static ref VERIFICATION_FAILURES: CounterVec = CounterVec::new(
+        "node_dns_verification_failures_total",
+        "DNS lookups that did not return the expected records",
+        &["check"],
+    );
+    /// Updated by the reconciles, and recounted on resync
+    pub static ref MANAGED_NODES: Gauge = Gauge::new("node_dns_managed_nodes", "Nodes with up to date records");
+    /// The forward, SPF glue and internal records of the nodes with up to date records
+    pub static ref MANAGED_RECORDS: Gauge =
+        Gauge::new("node_dns_managed_records", "Address records published for the nodes");
+}
+
+/// All the metrics, in the Prometheus text exposition format
+pub fn render() -> String {
+    let mut out = String::new();
+    RECONCILES.render(&mut out);
+    RECONCILE_DURATION.render(&mut out);
+    RECONCILE_ERRORS.render(&mut out);
+    LINODE_REQUESTS.render(&mut out);
+    VERIFICATION_FAILURES.render(&mut out);
+    MANAGED_NODES.render(&mut out);
+    MANAGED_RECORDS.render(&mut out);
+    out
+}
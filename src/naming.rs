@@ -0,0 +1,205 @@
+//! DNS names of the nodes: from a template, or from an annotation on the node.
+
+use k8s_openapi::api::core::v1::Node;
+use k8s_openapi::chrono::{DateTime, Utc};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Overrides the template for a single node, e.g. `k8s.haim.dev/dns-name: gateway`
+pub const NAME_ANNOTATION: &str = "k8s.haim.dev/dns-name";
+
+pub const DEFAULT_TEMPLATE: &str = "{hostname}";
+
+const REGION_LABEL: &str = "topology.kubernetes.io/region";
+
+/// Nodes with the same value, or all without it, make a pool for `{index}`
+pub const POOL_LABEL: &str = "lke.linode.com/pool-id";
+
+#[derive(Debug, Error, PartialEq)]
+pub enum NameError {
+    #[error("Unknown placeholder {{{0}}} in the name template")]
+    UnknownPlaceholder(String),
+    #[error("Unclosed placeholder in the name template")]
+    UnclosedPlaceholder,
+    #[error("Node has no {0}, needed by the name template")]
+    MissingField(String),
+    #[error("{0:?} is not a valid DNS label: {1}")]
+    InvalidLabel(String, &'static str),
+    /// The older node keeps the name: the ownership records are per cluster, not per node, so both would otherwise
+    /// keep overwriting the records of the other
+    #[error("{0:?} is already the name of the older node {1}")]
+    Taken(String, String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    /// The `Hostname` address of the node
+    Hostname,
+    /// `metadata.name`
+    Name,
+    Region,
+    /// Ordinal of the node in its pool, see [`pool_index`]
+    Index,
+    Label(String),
+}
+
+/// Template of the node names, e.g. `pool-{label:lke.linode.com/pool-id}-{hostname}`. The placeholders are
+/// `{hostname}`, `{name}`, `{region}`, `{index}` and `{label:<key>}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NameTemplate {
+    segments: Vec<Segment>,
+}
+
+impl FromStr for NameTemplate {
+    type Err = NameError;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut segments = vec![];
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..].find('}').ok_or(NameError::UnclosedPlaceholder)? + start;
+            let placeholder = &rest[start + 1..end];
+            segments.push(match placeholder {
+                "hostname" => Segment::Hostname,
+                "name" => Segment::Name,
+                "region" => Segment::Region,
+                "index" => Segment::Index,
+                _ => match placeholder.strip_prefix("label:") {
+                    Some(key) if !key.is_empty() => Segment::Label(key.to_string()),
+                    _ => return Err(NameError::UnknownPlaceholder(placeholder.to_string())),
+                },
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+        Ok(NameTemplate { segments })
+    }
+}
+
+impl NameTemplate {
+    /// Name of the node relative to the domain, from its annotation if it has one, or else from the template.
+    /// `hostname` is its `Hostname` address, `nodes` all the nodes of the cluster, only needed by `{index}`.
+    pub fn render(&self, node: &Node, hostname: &str, nodes: &[Node]) -> Result<String, NameError> {
+        let annotation = node
+            .metadata
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(NAME_ANNOTATION));
+        if let Some(name) = annotation {
+            return validate_label(name);
+        }
+        let label = |key: &str| {
+            node.metadata
+                .labels
+                .as_ref()
+                .and_then(|labels| labels.get(key))
+                .map(String::as_str)
+                .ok_or_else(|| NameError::MissingField(format!("label {}", key)))
+        };
+        let mut name = String::new();
+        for segment in self.segments.iter() {
+            let index;
+            name.push_str(match segment {
+                Segment::Literal(literal) => literal.as_str(),
+                Segment::Hostname => hostname,
+                Segment::Name => node
+                    .metadata
+                    .name
+                    .as_deref()
+                    .ok_or_else(|| NameError::MissingField("name".to_string()))?,
+                Segment::Region => label(REGION_LABEL)?,
+                Segment::Index => {
+                    index = pool_index(node, nodes).to_string();
+                    index.as_str()
+                }
+                Segment::Label(key) => label(key)?,
+            });
+        }
+        validate_label(&name)
+    }
+
+    /// Like [`NameTemplate::render`], but fails with [`NameError::Taken`] when an older node of `nodes` that gets
+    /// records, according to `published`, has the same name. The nodes whose name cannot be rendered are ignored.
+    pub fn render_unique(
+        &self,
+        node: &Node,
+        hostname: &str,
+        nodes: &[Node],
+        published: impl Fn(&Node) -> bool,
+    ) -> Result<String, NameError> {
+        let name = self.render(node, hostname, nodes)?;
+        let older = nodes.iter().filter(|other| age(other) < age(node) && published(other));
+        for other in older {
+            let other_name = match hostname_of(other) {
+                Some(hostname) => self.render(other, hostname, nodes),
+                None => continue,
+            };
+            if other_name.as_ref() == Ok(&name) {
+                let other = other.metadata.name.clone().unwrap_or_default();
+                return Err(NameError::Taken(name, other));
+            }
+        }
+        Ok(name)
+    }
+}
+
+/// The `Hostname` address of the node
+pub fn hostname_of(node: &Node) -> Option<&str> {
+    node.status
+        .as_ref()?
+        .addresses
+        .as_ref()?
+        .iter()
+        .find(|address| address.type_ == "Hostname")
+        .map(|address| address.address.as_str())
+}
+
+/// Orders the nodes from the oldest, by creation time then name
+fn age(node: &Node) -> (Option<DateTime<Utc>>, Option<String>) {
+    (
+        node.metadata.creation_timestamp.as_ref().map(|time| time.0),
+        node.metadata.name.clone(),
+    )
+}
+
+/// Ordinal of the node among the nodes of its pool, from 0, by creation time then name. The nodes being deleted
+/// still count, so that no other node takes their name before their records are deleted; once they are gone, the
+/// nodes created after them are renumbered.
+pub fn pool_index(node: &Node, nodes: &[Node]) -> usize {
+    let pool = |node: &Node| {
+        node.metadata
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get(POOL_LABEL))
+            .cloned()
+    };
+    nodes
+        .iter()
+        .filter(|other| pool(other) == pool(node) && age(other) < age(node))
+        .count()
+}
+
+/// A single DNS label (RFC 1123): letters, digits and hyphens, not starting or ending with a hyphen, at most 63
+/// characters. Returned in lower case.
+pub fn validate_label(name: &str) -> Result<String, NameError> {
+    let invalid = |reason| Err(NameError::InvalidLabel(name.to_string(), reason));
+    if name.is_empty() {
+        return invalid("it is empty");
+    }
+    if name.len() > 63 {
+        return invalid("it is longer than 63 characters");
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return invalid("only letters, digits and hyphens are allowed");
+    }
+    if name.starts_with('-') || name.ends_with('-') {
+        return invalid("it starts or ends with a hyphen");
+    }
+    Ok(name.to_ascii_lowercase())
+}
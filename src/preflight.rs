@@ -0,0 +1,70 @@
+//! Checks run once at startup, so that a misconfiguration stops the controller with a clear error instead of
+//! failing the reconcile of every node, retried forever.
+
+use crate::errors::Error;
+use crate::provider::Providers;
+use anyhow::{bail, Result};
+use std::str::FromStr;
+use tracing::info;
+use trust_dns_resolver::{Name, TokioAsyncResolver};
+
+/// Check the credentials, then that each zone can be written and, when a resolver is given, that it is delegated
+/// to the name servers of the provider. All the problems are reported together, except invalid credentials, which
+/// make every other check fail anyway.
+pub async fn check(
+    providers: &Providers,
+    zones: &[String],
+    resolver: Option<&TokioAsyncResolver>,
+) -> Result<(), Error> {
+    providers.validate_credentials().await?;
+
+    let mut problems = Vec::new();
+    for zone in zones.iter() {
+        if let Err(error) = providers.forward.check_zone_access(zone).await {
+            problems.push(format!("{:#}", error));
+            continue;
+        }
+        if let Some(resolver) = resolver {
+            let delegated = match providers.forward.name_servers(zone).await {
+                Ok(name_servers) => check_delegation(resolver, zone, &name_servers).await,
+                Err(error) => Err(error),
+            };
+            if let Err(error) = delegated {
+                problems.push(format!("{:#}", error));
+            }
+        }
+    }
+    if let Some(reverse) = &providers.reverse {
+        if let Err(error) = reverse.check_access().await {
+            problems.push(format!("{:#}", error));
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(Error::PreflightFailed(problems));
+    }
+    info!(zones = ?zones, "Preflight checks passed");
+    Ok(())
+}
+
+/// The NS records of the zone, as seen by the resolver, only point at the given name servers
+pub async fn check_delegation(resolver: &TokioAsyncResolver, zone: &str, name_servers: &[String]) -> Result<()> {
+    let expected: Vec<String> = name_servers.iter().map(|name| normalize(name)).collect();
+    let delegated: Vec<String> = match resolver.ns_lookup(Name::from_str(&format!("{}.", zone))?).await {
+        Ok(lookup) => lookup.iter().map(|name| normalize(&name.to_string())).collect(),
+        Err(error) => bail!("Could not look up the name servers of {}: {}", zone, error),
+    };
+    if delegated.is_empty() {
+        bail!("{} is not delegated to any name server", zone);
+    }
+    let foreign: Vec<&String> = delegated.iter().filter(|name| !expected.contains(name)).collect();
+    if !foreign.is_empty() {
+        bail!("{} is delegated to {:?}, expected only {:?}", zone, foreign, expected);
+    }
+    Ok(())
+}
+
+/// Name servers compare without the trailing dot of the FQDN and the case
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
@@ -0,0 +1,188 @@
+//! DNS providers: the services where the node records are published.
+
+mod cloudflare;
+mod linode;
+mod rfc2136;
+
+pub use self::cloudflare::CloudflareProvider;
+pub use self::linode::LinodeProvider;
+pub use self::rfc2136::Rfc2136Provider;
+
+use crate::config::Config;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// A DNS record, as seen by the reconciliation logic
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    /// Provider-specific identifier, absent for the records that were not created yet
+    pub id: Option<String>,
+    /// Name relative to the zone, e.g. `node-1` for `node-1.k8s.example.com`
+    pub name: String,
+    pub type_: String,
+    pub target: String,
+    pub ttl: u64,
+}
+
+impl Record {
+    pub fn new(name: &str, type_: &str, target: &str, ttl: u64) -> Record {
+        Record {
+            id: None,
+            name: name.to_string(),
+            type_: type_.to_string(),
+            target: target.to_string(),
+            ttl,
+        }
+    }
+}
+
+/// Forward records: the node names and the SPF glue.
+#[async_trait]
+pub trait DnsProvider: Send + Sync {
+    /// All records with the given name in the zone, of any type
+    async fn list_records(&self, zone: &str, name: &str) -> Result<Vec<Record>>;
+
+    /// All records of the zone, fresh from the provider, to find the ones left behind by deleted nodes
+    async fn list_zone_records(&self, zone: &str) -> Result<Vec<Record>>;
+
+    async fn create_record(&self, zone: &str, record: &Record) -> Result<Record>;
+
+    /// Update the record with the same id, or, for the providers without record ids, replace the records of the
+    /// same name and type.
+    async fn update_record(&self, zone: &str, record: &Record) -> Result<Record>;
+
+    async fn delete_record(&self, zone: &str, record: &Record) -> Result<()>;
+
+    /// Authoritative name servers of the zone, queried directly to verify the records without waiting for caches
+    /// to expire.
+    async fn name_servers(&self, zone: &str) -> Result<Vec<String>>;
+
+    /// Check that the provider accepts the credentials, before anything is reconciled
+    async fn validate_credentials(&self) -> Result<()>;
+
+    /// Pick up the rotated credentials for as long as the controller runs. Returns right away when they are
+    /// fixed, as with most providers.
+    async fn watch_credentials(&self) {}
+
+    /// Whether [`DnsProvider::watch_credentials`] may switch to new credentials, so that the operations they were
+    /// rejected for are worth retrying
+    fn rotates_credentials(&self) -> bool {
+        false
+    }
+
+    /// Check that the zone exists and that the credentials allow changing its records, so that a misconfiguration
+    /// fails at startup rather than in every reconcile. Nothing more than the credentials is checked by default.
+    async fn check_zone_access(&self, _zone: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Reverse DNS of the node IP addresses. Usually only whoever owns the addresses can set it, which is not
+/// necessarily where the zone is hosted.
+#[async_trait]
+pub trait ReverseDnsProvider: Send + Sync {
+    /// Point the reverse DNS record of the IP address to the FQDN, if not already.
+    async fn set_ptr(&self, ip_address: IpAddr, fqdn: &str) -> Result<()>;
+
+    /// Give up the reverse DNS of an IP address the node no longer has, if it still points to the FQDN: it goes
+    /// back to the provider default, or is removed when there is none.
+    async fn reset_ptr(&self, ip_address: IpAddr, fqdn: &str) -> Result<()>;
+
+    /// Authoritative name servers of the reverse zones
+    fn reverse_name_servers(&self) -> Vec<String>;
+
+    /// Check that the provider accepts the credentials, before anything is reconciled
+    async fn validate_credentials(&self) -> Result<()>;
+
+    /// See [`DnsProvider::watch_credentials`]
+    async fn watch_credentials(&self) {}
+
+    /// See [`DnsProvider::rotates_credentials`]
+    fn rotates_credentials(&self) -> bool {
+        false
+    }
+
+    /// Check that the credentials allow setting the reverse DNS, see [`DnsProvider::check_zone_access`]
+    async fn check_access(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The providers of the forward records and of the reverse DNS, possibly the same one.
+#[derive(Clone)]
+pub struct Providers {
+    pub forward: Arc<dyn DnsProvider>,
+    /// `None` when reverse DNS is not managed at all
+    pub reverse: Option<Arc<dyn ReverseDnsProvider>>,
+}
+
+impl Providers {
+    /// The credentials of the forward provider, and of the reverse one, if any
+    pub async fn validate_credentials(&self) -> Result<()> {
+        self.forward.validate_credentials().await?;
+        if let Some(reverse) = &self.reverse {
+            reverse.validate_credentials().await?;
+        }
+        Ok(())
+    }
+
+    /// Whether the credentials of either provider may be rotated
+    // `is_some_and` needs a newer Rust than the image is built with
+    #[allow(clippy::unnecessary_map_or)]
+    pub fn rotates_credentials(&self) -> bool {
+        self.forward.rotates_credentials()
+            || self
+                .reverse
+                .as_ref()
+                .map_or(false, |reverse| reverse.rotates_credentials())
+    }
+
+    /// Watch the credentials of both providers, or only once when they are the same provider
+    pub async fn watch_credentials(&self) {
+        match &self.reverse {
+            Some(reverse) if Arc::as_ptr(reverse) as *const () != Arc::as_ptr(&self.forward) as *const () => {
+                futures::join!(self.forward.watch_credentials(), reverse.watch_credentials());
+            }
+            _ => self.forward.watch_credentials().await,
+        }
+    }
+}
+
+/// Create the providers selected by `DNS_PROVIDER` and `RDNS_PROVIDER`, see [`Config`].
+pub fn from_config(config: &Config) -> Result<Providers> {
+    let forward = config.dns_provider.as_str();
+    let reverse = config.reverse_provider();
+    let uses = |name: &str| forward == name || reverse == name;
+    // A single instance when the same provider is used for both, to share its client
+    let linode = if uses("linode") {
+        Some(Arc::new(LinodeProvider::from_config(&config.linode)?))
+    } else {
+        None
+    };
+    let rfc2136 = if uses("rfc2136") {
+        Some(Arc::new(
+            Rfc2136Provider::from_config(&config.rfc2136)?.with_ptr_ttl(config.record_ttl),
+        ))
+    } else {
+        None
+    };
+
+    let forward: Arc<dyn DnsProvider> = match (forward, &linode, &rfc2136) {
+        ("linode", Some(linode), _) => linode.clone(),
+        ("rfc2136", _, Some(rfc2136)) => rfc2136.clone(),
+        ("cloudflare", _, _) => Arc::new(CloudflareProvider::from_config(&config.cloudflare)?),
+        (other, _, _) => bail!("Unknown DNS provider {}", other),
+    };
+    let reverse: Option<Arc<dyn ReverseDnsProvider>> = match (reverse, &linode, &rfc2136) {
+        ("linode", Some(linode), _) => Some(linode.clone()),
+        ("rfc2136", _, Some(rfc2136)) => Some(rfc2136.clone()),
+        ("none", _, _) => None,
+        ("cloudflare", _, _) => {
+            bail!("Cloudflare cannot manage reverse DNS, set RDNS_PROVIDER to linode, rfc2136 or none")
+        }
+        (other, _, _) => bail!("Unknown reverse DNS provider {}", other),
+    };
+    Ok(Providers { forward, reverse })
+}
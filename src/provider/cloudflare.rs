@@ -0,0 +1,113 @@
+use super::{DnsProvider, Record};
+use crate::cloudflare;
+use crate::config::CloudflareConfig;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+
+/// Cloudflare zones. Forward records only: Cloudflare cannot set the reverse DNS of addresses it does not own.
+pub struct CloudflareProvider {
+    client: cloudflare::Client,
+}
+
+impl CloudflareProvider {
+    pub fn new(client: cloudflare::Client) -> CloudflareProvider {
+        CloudflareProvider { client }
+    }
+
+    pub fn from_config(config: &CloudflareConfig) -> Result<CloudflareProvider> {
+        let token = config
+            .api_token
+            .as_deref()
+            .context("CLOUDFLARE_API_TOKEN is required by the Cloudflare provider")?;
+        Ok(CloudflareProvider::new(
+            cloudflare::Client::new(token).with_base_url(&config.api_url),
+        ))
+    }
+
+    async fn zone(&self, zone: &str) -> Result<cloudflare::Zone> {
+        self.client
+            .zone(zone)
+            .await?
+            .context(format!("Could not find zone {} at Cloudflare", zone))
+    }
+}
+
+/// Cloudflare names are fully qualified, ours are relative to the zone.
+fn record(zone: &str, record: cloudflare::DnsRecordResponse) -> Record {
+    let name = record
+        .name
+        .strip_suffix(&format!(".{}", zone))
+        .unwrap_or(&record.name)
+        .to_string();
+    Record {
+        id: Some(record.id),
+        name,
+        type_: record.type_,
+        target: record.content,
+        ttl: record.ttl,
+    }
+}
+
+fn request(zone: &str, record: &Record) -> cloudflare::DnsRecordRequest {
+    cloudflare::DnsRecordRequest {
+        name: format!("{}.{}", record.name, zone),
+        type_: record.type_.clone(),
+        content: record.target.clone(),
+        ttl: record.ttl,
+        proxied: false,
+    }
+}
+
+#[async_trait]
+impl DnsProvider for CloudflareProvider {
+    async fn list_records(&self, zone: &str, name: &str) -> Result<Vec<Record>> {
+        let zone_id = self.zone(zone).await?.id;
+        let records = self
+            .client
+            .get_dns_records(&zone_id, &format!("{}.{}", name, zone))
+            .await?;
+        Ok(records.into_iter().map(|r| record(zone, r)).collect())
+    }
+
+    async fn list_zone_records(&self, zone: &str) -> Result<Vec<Record>> {
+        let zone_id = self.zone(zone).await?.id;
+        let records = self.client.get_all_dns_records(&zone_id).await?;
+        Ok(records.into_iter().map(|r| record(zone, r)).collect())
+    }
+
+    async fn create_record(&self, zone: &str, record: &Record) -> Result<Record> {
+        let zone_id = self.zone(zone).await?.id;
+        let created = self.client.create_dns_record(&zone_id, &request(zone, record)).await?;
+        Ok(self::record(zone, created))
+    }
+
+    async fn update_record(&self, zone: &str, record: &Record) -> Result<Record> {
+        let zone_id = self.zone(zone).await?.id;
+        let record_id = record.id.as_deref().context("Record has no id")?;
+        let updated = self
+            .client
+            .update_dns_record(&zone_id, record_id, &request(zone, record))
+            .await?;
+        Ok(self::record(zone, updated))
+    }
+
+    async fn delete_record(&self, zone: &str, record: &Record) -> Result<()> {
+        let zone_id = self.zone(zone).await?.id;
+        let record_id = record.id.as_deref().context("Record has no id")?;
+        self.client.delete_dns_record(&zone_id, record_id).await?;
+        Ok(())
+    }
+
+    /// Cloudflare assigns a pair of name servers to every zone
+    async fn name_servers(&self, zone: &str) -> Result<Vec<String>> {
+        Ok(self.zone(zone).await?.name_servers)
+    }
+
+    async fn validate_credentials(&self) -> Result<()> {
+        let token = self.client.verify_token().await?;
+        if token.status != "active" {
+            return Err(anyhow::anyhow!("Cloudflare API token is {}", token.status));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,367 @@
+use super::{DnsProvider, Record, ReverseDnsProvider};
+use crate::config::LinodeConfig;
+use crate::linode;
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+
+/// How often the token file is read. A mounted Secret takes up to a minute or two to be updated anyway.
+const TOKEN_FILE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Linode domains, and reverse DNS of Linode IP addresses.
+pub struct LinodeProvider {
+    client: linode::Client,
+    /// In order to query the Linode servers directly, and get the authoritative answer
+    name_servers: Vec<String>,
+    /// Where the token comes from, when it can be rotated
+    token_file: Option<PathBuf>,
+    token_file_poll_interval: Duration,
+}
+
+impl LinodeProvider {
+    pub fn new(client: linode::Client) -> LinodeProvider {
+        LinodeProvider {
+            client,
+            name_servers: linode::NAME_SERVERS.iter().map(|ns| ns.to_string()).collect(),
+            token_file: None,
+            token_file_poll_interval: TOKEN_FILE_POLL_INTERVAL,
+        }
+    }
+
+    /// Switch to the token in the file whenever it changes, see [`DnsProvider::watch_credentials`]
+    pub fn with_token_file(mut self, token_file: &Path) -> Self {
+        self.token_file = Some(token_file.to_path_buf());
+        self
+    }
+
+    pub fn with_token_file_poll_interval(mut self, interval: Duration) -> Self {
+        self.token_file_poll_interval = interval;
+        self
+    }
+
+    pub fn with_name_servers(mut self, name_servers: &[String]) -> Self {
+        self.name_servers = name_servers.to_vec();
+        self
+    }
+
+    pub fn from_config(config: &LinodeConfig) -> Result<LinodeProvider> {
+        if config.name_servers.is_empty() {
+            bail!("LINODE_NAME_SERVERS must not be empty");
+        }
+        let token = match (&config.api_token, &config.api_token_file) {
+            (Some(token), None) => token.clone(),
+            (None, Some(token_file)) => read_token(Path::new(token_file))?,
+            (Some(_), Some(_)) => bail!("Only one of LINODE_API_TOKEN and LINODE_API_TOKEN_FILE can be set"),
+            (None, None) => bail!("LINODE_API_TOKEN or LINODE_API_TOKEN_FILE is required by the Linode provider"),
+        };
+        let provider = LinodeProvider::new(linode::Client::new(&token).with_base_url(&config.api_url))
+            .with_name_servers(&config.name_servers);
+        Ok(match &config.api_token_file {
+            Some(token_file) => provider.with_token_file(Path::new(token_file)),
+            None => provider,
+        })
+    }
+
+    /// The token must have the `read_write` scope of the area, e.g. `domains`. Tokens without scopes, e.g. when
+    /// going through a proxy that drops the header, are given the benefit of the doubt.
+    async fn check_scope(&self, area: &str) -> Result<()> {
+        match self.client.get_token_scopes().await? {
+            Some(scopes) if !scopes.can_write(area) => bail!(
+                "Linode API token has the scopes \"{}\", {}:read_write is required",
+                scopes,
+                area
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Grants of the user, `None` when unrestricted
+    async fn restricted_grants(&self) -> Result<Option<(String, linode::GrantsResponse)>> {
+        let profile = self.client.get_profile().await?;
+        if !profile.restricted {
+            return Ok(None);
+        }
+        Ok(self.client.get_grants().await?.map(|grants| (profile.username, grants)))
+    }
+
+    /// Check the token against the API, and only then switch to it. The requests in progress finish with the
+    /// previous token.
+    pub async fn switch_token(&self, token: &str) -> Result<()> {
+        let profile = self.client.with_token(token).get_profile().await?;
+        self.client.set_token(token);
+        info!(
+            username = profile.username.as_str(),
+            "Switched to the new Linode API token"
+        );
+        Ok(())
+    }
+
+    async fn domain_id(&self, zone: &str) -> Result<u64> {
+        self.client
+            .domain_id(zone)
+            .await?
+            .context(format!("Could not find domain {} at Linode", zone))
+    }
+}
+
+fn record_id(record: &Record) -> Result<u64> {
+    record
+        .id
+        .as_deref()
+        .context("Record has no id")?
+        .parse()
+        .context("Record id is not a Linode record id")
+}
+
+impl From<linode::DomainRecordResponse> for Record {
+    fn from(record: linode::DomainRecordResponse) -> Self {
+        Record {
+            id: Some(record.id.to_string()),
+            name: record.name,
+            type_: record.type_,
+            target: record.target,
+            ttl: record.ttl_sec,
+        }
+    }
+}
+
+impl From<&Record> for linode::DomainRecordRequest {
+    fn from(record: &Record) -> Self {
+        linode::DomainRecordRequest {
+            name: record.name.clone(),
+            target: record.target.clone(),
+            type_: record.type_.clone(),
+            priority: None,
+            port: None,
+            weight: None,
+            ttl_sec: record.ttl,
+            service: None,
+            protocol: None,
+            tag: None,
+        }
+    }
+}
+
+#[async_trait]
+impl DnsProvider for LinodeProvider {
+    async fn list_records(&self, zone: &str, name: &str) -> Result<Vec<Record>> {
+        let domain_id = self.domain_id(zone).await?;
+        // Filtering by name only, so that the same cached lookup serves both updates and deletes of this name.
+        let records = self
+            .client
+            .cached_domain_records(domain_id, &linode::Filter::new().name(name))
+            .await?;
+        Ok(records.into_iter().map(Record::from).collect())
+    }
+
+    async fn list_zone_records(&self, zone: &str) -> Result<Vec<Record>> {
+        let domain_id = self.domain_id(zone).await?;
+        // The zone is listed to catch up with changes made behind our back, which the cache would hide
+        self.client.invalidate_domain(domain_id);
+        let records = self
+            .client
+            .get_domain_records(domain_id, &linode::Filter::new())
+            .await?;
+        Ok(records.into_iter().map(Record::from).collect())
+    }
+
+    async fn create_record(&self, zone: &str, record: &Record) -> Result<Record> {
+        let domain_id = self.domain_id(zone).await?;
+        let created = self.client.create_domain_record(domain_id, record.into()).await?;
+        Ok(created.into())
+    }
+
+    /// Only the fields of [`Record`] are changed, the others, e.g. a tag, are kept as they are at Linode
+    async fn update_record(&self, zone: &str, record: &Record) -> Result<Record> {
+        let domain_id = self.domain_id(zone).await?;
+        let id = record_id(record)?;
+        let current = self
+            .client
+            .cached_domain_records(domain_id, &linode::Filter::new().name(&record.name))
+            .await?
+            .into_iter()
+            .find(|current| current.id == id);
+        let request = match current {
+            Some(current) => linode::DomainRecordRequest {
+                name: record.name.clone(),
+                target: record.target.clone(),
+                type_: record.type_.clone(),
+                ttl_sec: record.ttl,
+                ..current.into()
+            },
+            None => record.into(),
+        };
+        let updated = self.client.update_domain_record(domain_id, id, request).await?;
+        Ok(updated.into())
+    }
+
+    async fn delete_record(&self, zone: &str, record: &Record) -> Result<()> {
+        let domain_id = self.domain_id(zone).await?;
+        self.client.delete_domain_record(domain_id, record_id(record)?).await?;
+        Ok(())
+    }
+
+    async fn name_servers(&self, _zone: &str) -> Result<Vec<String>> {
+        Ok(self.name_servers.clone())
+    }
+
+    async fn validate_credentials(&self) -> Result<()> {
+        let profile = self.client.get_profile().await?;
+        debug!(username = profile.username.as_str(), "Linode API token is valid");
+        Ok(())
+    }
+
+    /// The token can write domains, and the zone is an active master zone the user has read-write access to
+    async fn check_zone_access(&self, zone: &str) -> Result<()> {
+        self.check_scope("domains").await?;
+        let domain = self
+            .client
+            .get_domain(zone)
+            .await?
+            .context(format!("Could not find domain {} at Linode", zone))?;
+        if domain.type_ != "master" {
+            bail!(
+                "Linode domain {} is a {} zone, records can only be added to a master zone",
+                zone,
+                domain.type_
+            );
+        }
+        if domain.status != "active" {
+            bail!(
+                "Linode domain {} is {}, its records are not served",
+                zone,
+                domain.status
+            );
+        }
+        if let Some((username, grants)) = self.restricted_grants().await? {
+            if !grants
+                .domain
+                .iter()
+                .any(|grant| grant.id == domain.id && grant.is_read_write())
+            {
+                bail!(
+                    "Linode user {} has no read_write grant on the domain {}",
+                    username,
+                    zone
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Poll the token file, if any. A token Linode rejects is not tried again until the file changes, one that
+    /// could not be checked, e.g. because of a network error, is tried again on the next poll.
+    async fn watch_credentials(&self) {
+        let token_file = match &self.token_file {
+            Some(token_file) => token_file,
+            None => return,
+        };
+        let mut interval = tokio::time::interval(self.token_file_poll_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut rejected: Option<String> = None;
+        loop {
+            interval.tick().await;
+            let token = match read_token(token_file) {
+                Ok(token) => token,
+                Err(error) => {
+                    warn!(
+                        error = format!("{:#}", error).as_str(),
+                        "Failed to read the Linode API token"
+                    );
+                    continue;
+                }
+            };
+            if self.client.has_token(&token) || rejected.as_ref() == Some(&token) {
+                continue;
+            }
+            if let Err(error) = self.switch_token(&token).await {
+                warn!(
+                    error = format!("{}", error).as_str(),
+                    "New Linode API token could not be validated, still using the previous one"
+                );
+                if matches!(error.downcast_ref::<linode::LinodeError>(), Some(error) if error.is_auth_failure()) {
+                    rejected = Some(token);
+                }
+            }
+        }
+    }
+
+    /// When reading the token from a file
+    fn rotates_credentials(&self) -> bool {
+        self.token_file.is_some()
+    }
+}
+
+#[async_trait]
+impl ReverseDnsProvider for LinodeProvider {
+    async fn set_ptr(&self, ip_address: IpAddr, fqdn: &str) -> Result<()> {
+        let address = self.client.get_ip_address(ip_address).await?;
+        if address.rdns.as_deref() == Some(fqdn) {
+            info!("Reverse DNS record already defined in Linode");
+            return Ok(());
+        }
+        self.client.update_rdns(ip_address, fqdn).await?;
+        info!("Triggered RDNS update in Linode");
+        Ok(())
+    }
+
+    async fn reset_ptr(&self, ip_address: IpAddr, fqdn: &str) -> Result<()> {
+        let address = self.client.get_ip_address(ip_address).await?;
+        if address.rdns.as_deref() != Some(fqdn) {
+            info!("Reverse DNS record does not point to the node anymore, leaving it alone");
+            return Ok(());
+        }
+        self.client.reset_rdns(ip_address).await?;
+        info!("Reset RDNS to the Linode default");
+        Ok(())
+    }
+
+    /// Linode name servers are authoritative for the reverse zones of the Linode IP addresses too
+    fn reverse_name_servers(&self) -> Vec<String> {
+        self.name_servers.clone()
+    }
+
+    async fn validate_credentials(&self) -> Result<()> {
+        DnsProvider::validate_credentials(self).await
+    }
+
+    async fn watch_credentials(&self) {
+        DnsProvider::watch_credentials(self).await
+    }
+
+    fn rotates_credentials(&self) -> bool {
+        DnsProvider::rotates_credentials(self)
+    }
+
+    /// The token can write IP addresses, and a restricted user has read-write access to some Linodes, whose
+    /// addresses are the ones of the nodes
+    async fn check_access(&self) -> Result<()> {
+        self.check_scope("ips").await?;
+        if let Some((username, grants)) = self.restricted_grants().await? {
+            if !grants.linode.iter().any(linode::Grant::is_read_write) {
+                bail!(
+                    "Linode user {} has no read_write grant on any Linode, so cannot set the reverse DNS of the nodes",
+                    username
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The token in the file, without the trailing new line editors and `kubectl create secret --from-file` leave
+fn read_token(token_file: &Path) -> Result<String> {
+    let token = std::fs::read_to_string(token_file).context(format!(
+        "Cannot read the Linode API token from {}",
+        token_file.display()
+    ))?;
+    let token = token.trim();
+    if token.is_empty() {
+        bail!("Linode API token file {} is empty", token_file.display());
+    }
+    Ok(token.to_string())
+}
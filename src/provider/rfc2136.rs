@@ -0,0 +1,364 @@
+use super::{DnsProvider, Record, ReverseDnsProvider};
+use crate::config::Rfc2136Config;
+use crate::registry;
+use crate::tsig;
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::{debug, info};
+use trust_dns_resolver::proto::op::{Header, Message, MessageType, OpCode, Query, ResponseCode};
+use trust_dns_resolver::proto::rr::rdata::{NULL, TXT};
+use trust_dns_resolver::proto::rr::{DNSClass, RData, Record as ResourceRecord, RecordType};
+use trust_dns_resolver::proto::serialize::binary::{BinDecodable, BinDecoder};
+use trust_dns_resolver::Name;
+
+const DEFAULT_PORT: u16 = 53;
+
+/// How long to wait for the DNS server to answer a query or an update
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Record types published by the controller, the only ones `list_records` looks up: unlike the APIs, DNS has
+/// no way to query all the records of a name.
+const MANAGED_TYPES: [RecordType; 3] = [RecordType::A, RecordType::AAAA, RecordType::TXT];
+
+/// Any DNS server accepting dynamic updates (RFC 2136), such as BIND, Knot or PowerDNS, optionally
+/// authenticated with a TSIG key.
+pub struct Rfc2136Provider {
+    /// `host:port` of the primary server of the zones
+    server: String,
+    key: Option<tsig::Key>,
+    /// Zones where PTR records can be updated, e.g. `2.0.192.in-addr.arpa`
+    reverse_zones: Vec<String>,
+    timeout: Duration,
+    /// TTL of the PTR records, the same as the forward records get
+    ptr_ttl: u64,
+}
+
+impl Rfc2136Provider {
+    /// `server` is a host name or an IP address, with an optional port (53 by default).
+    pub fn new(server: &str, key: Option<tsig::Key>) -> Rfc2136Provider {
+        let server = if server.parse::<SocketAddr>().is_ok() {
+            server.to_string()
+        } else if let Ok(ip_address) = server.parse::<IpAddr>() {
+            SocketAddr::new(ip_address, DEFAULT_PORT).to_string()
+        } else if server.contains(':') {
+            server.to_string()
+        } else {
+            format!("{}:{}", server, DEFAULT_PORT)
+        };
+        Rfc2136Provider {
+            server,
+            key,
+            reverse_zones: vec![],
+            timeout: DEFAULT_TIMEOUT,
+            ptr_ttl: registry::DEFAULT_RECORD_TTL,
+        }
+    }
+
+    pub fn with_reverse_zones(mut self, reverse_zones: &[&str]) -> Self {
+        self.reverse_zones = reverse_zones.iter().map(|zone| zone.to_string()).collect();
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_ptr_ttl(mut self, ttl: u64) -> Self {
+        self.ptr_ttl = ttl;
+        self
+    }
+
+    pub fn from_config(config: &Rfc2136Config) -> Result<Rfc2136Provider> {
+        let server = config
+            .server
+            .as_deref()
+            .context("RFC2136_SERVER is required by the RFC 2136 provider")?;
+        let key = match &config.tsig_key_name {
+            Some(name) => {
+                let secret = config
+                    .tsig_secret
+                    .as_deref()
+                    .context("RFC2136_TSIG_SECRET is required with RFC2136_TSIG_KEY_NAME")?;
+                let algorithm = match &config.tsig_algorithm {
+                    Some(algorithm) => algorithm.parse()?,
+                    None => tsig::Algorithm::HmacSha256,
+                };
+                Some(tsig::Key::new(name, algorithm, secret)?)
+            }
+            None => None,
+        };
+        let reverse_zones: Vec<&str> = config.reverse_zones.iter().map(String::as_str).collect();
+        Ok(Rfc2136Provider::new(server, key).with_reverse_zones(&reverse_zones))
+    }
+
+    fn host(&self) -> &str {
+        match self.server.rsplit_once(':') {
+            Some((host, _)) => host.trim_start_matches('[').trim_end_matches(']'),
+            None => &self.server,
+        }
+    }
+
+    /// Send the message over TCP, and return the verified response.
+    async fn exchange(&self, message: Message) -> Result<Message> {
+        tokio::time::timeout(self.timeout, self.exchange_inner(message))
+            .await
+            .context(format!("DNS server {} did not answer in time", self.server))?
+    }
+
+    async fn exchange_inner(&self, message: Message) -> Result<Message> {
+        let request = message.to_vec()?;
+        let (request, request_mac) = match &self.key {
+            Some(key) => {
+                let (signed, mac) = key.sign(&request, None)?;
+                (signed, Some(mac))
+            }
+            None => (request, None),
+        };
+
+        let mut stream = TcpStream::connect(&self.server)
+            .await
+            .context(format!("Could not connect to DNS server {}", self.server))?;
+        stream.write_all(&(request.len() as u16).to_be_bytes()).await?;
+        stream.write_all(&request).await?;
+        let length = stream.read_u16().await? as usize;
+        let mut response = vec![0; length];
+        stream.read_exact(&mut response).await?;
+
+        let response = match (&self.key, &request_mac) {
+            (Some(key), Some(request_mac)) => match key.verify(&response, Some(request_mac)) {
+                Ok((unsigned, _)) => unsigned,
+                // Servers do not sign the responses to requests they could not authenticate
+                Err(error) => {
+                    let header = Header::read(&mut BinDecoder::new(&response))?;
+                    let code = ResponseCode::from(0, header.response_code());
+                    if code != ResponseCode::NoError {
+                        bail!("DNS server refused the request: {}", code);
+                    }
+                    return Err(error);
+                }
+            },
+            _ => response,
+        };
+        let response = Message::from_vec(&response)?;
+        if response.id() != message.id() {
+            bail!("DNS server answered with an unexpected message id");
+        }
+        Ok(response)
+    }
+
+    async fn query(&self, name: &Name, record_type: RecordType) -> Result<Vec<ResourceRecord>> {
+        let mut message = Message::new();
+        message
+            .set_id(rand::random())
+            .set_message_type(MessageType::Query)
+            .set_op_code(OpCode::Query)
+            .add_query(Query::query(name.clone(), record_type));
+        let response = self.exchange(message).await?;
+        match response.response_code() {
+            ResponseCode::NoError | ResponseCode::NXDomain => Ok(response
+                .answers()
+                .iter()
+                .filter(|answer| answer.name() == name && answer.rr_type() == record_type)
+                .cloned()
+                .collect()),
+            code => bail!("DNS query for {} {} failed: {}", name, record_type, code),
+        }
+    }
+
+    /// Apply the changes to the zone, all at once.
+    async fn update(&self, zone: &str, updates: Vec<ResourceRecord>) -> Result<()> {
+        let mut zone_query = Query::query(absolute_name(zone)?, RecordType::SOA);
+        zone_query.set_query_class(DNSClass::IN);
+        let mut message = Message::new();
+        message
+            .set_id(rand::random())
+            .set_message_type(MessageType::Query)
+            .set_op_code(OpCode::Update)
+            .add_query(zone_query)
+            .add_name_servers(updates);
+        let response = self.exchange(message).await?;
+        match response.response_code() {
+            ResponseCode::NoError => Ok(()),
+            code => bail!("DNS update of zone {} failed: {}", zone, code),
+        }
+    }
+
+    fn reverse_zone(&self, name: &Name) -> Option<&str> {
+        self.reverse_zones
+            .iter()
+            .find(|zone| absolute_name(zone).map(|zone| zone.zone_of(name)).unwrap_or(false))
+            .map(String::as_str)
+    }
+}
+
+fn absolute_name(name: &str) -> Result<Name> {
+    let mut name = Name::from_str(name).context(format!("Invalid DNS name {}", name))?;
+    name.set_fqdn(true);
+    Ok(name)
+}
+
+fn record_name(zone: &str, name: &str) -> Result<Name> {
+    absolute_name(&format!("{}.{}", name, zone))
+}
+
+fn rdata(record: &Record) -> Result<RData> {
+    Ok(match record.type_.as_str() {
+        "A" => RData::A(record.target.parse()?),
+        "AAAA" => RData::AAAA(record.target.parse()?),
+        "TXT" => RData::TXT(TXT::new(vec![record.target.clone()])),
+        other => bail!("Record type {} is not supported for dynamic updates", other),
+    })
+}
+
+fn target(rdata: &RData) -> String {
+    match rdata {
+        RData::TXT(txt) => txt
+            .txt_data()
+            .iter()
+            .map(|data| String::from_utf8_lossy(data))
+            .collect(),
+        other => other.to_string(),
+    }
+}
+
+/// Add a record to its RRset
+fn add(name: Name, ttl: u64, rdata: RData) -> ResourceRecord {
+    ResourceRecord::from_rdata(name, ttl as u32, rdata)
+}
+
+/// Delete this exact record, leaving the others in the RRset
+fn delete(name: Name, rdata: RData) -> ResourceRecord {
+    let mut record = ResourceRecord::from_rdata(name, 0, rdata);
+    record.set_dns_class(DNSClass::NONE);
+    record
+}
+
+/// Delete the whole RRset
+fn delete_all(name: Name, record_type: RecordType) -> ResourceRecord {
+    let mut record = ResourceRecord::with(name, record_type, 0);
+    record.set_dns_class(DNSClass::ANY).set_rdata(RData::NULL(NULL::new()));
+    record
+}
+
+#[async_trait]
+impl DnsProvider for Rfc2136Provider {
+    async fn list_records(&self, zone: &str, name: &str) -> Result<Vec<Record>> {
+        let record_name = record_name(zone, name)?;
+        let mut records = vec![];
+        for record_type in MANAGED_TYPES {
+            for answer in self.query(&record_name, record_type).await? {
+                records.push(Record {
+                    id: None,
+                    name: name.to_string(),
+                    type_: record_type.to_string(),
+                    target: target(answer.rdata()),
+                    ttl: answer.ttl() as u64,
+                });
+            }
+        }
+        Ok(records)
+    }
+
+    /// Would need a zone transfer (AXFR), which the server may not allow to the update key, and whose multi-message
+    /// TSIG verification is not implemented.
+    async fn list_zone_records(&self, zone: &str) -> Result<Vec<Record>> {
+        Err(anyhow::anyhow!(
+            "Listing the records of zone {} is not supported with RFC 2136",
+            zone
+        ))
+    }
+
+    async fn create_record(&self, zone: &str, record: &Record) -> Result<Record> {
+        let name = record_name(zone, &record.name)?;
+        self.update(zone, vec![add(name, record.ttl, rdata(record)?)]).await?;
+        Ok(record.clone())
+    }
+
+    /// Records have no ids in DNS: replaces the whole RRset with this record.
+    async fn update_record(&self, zone: &str, record: &Record) -> Result<Record> {
+        let name = record_name(zone, &record.name)?;
+        let rdata = rdata(record)?;
+        let updates = vec![
+            delete_all(name.clone(), rdata.to_record_type()),
+            add(name, record.ttl, rdata),
+        ];
+        self.update(zone, updates).await?;
+        Ok(record.clone())
+    }
+
+    async fn delete_record(&self, zone: &str, record: &Record) -> Result<()> {
+        let name = record_name(zone, &record.name)?;
+        self.update(zone, vec![delete(name, rdata(record)?)]).await
+    }
+
+    async fn name_servers(&self, _zone: &str) -> Result<Vec<String>> {
+        Ok(vec![self.host().to_string()])
+    }
+
+    /// The server only checks the TSIG key of an actual update
+    async fn validate_credentials(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ReverseDnsProvider for Rfc2136Provider {
+    async fn set_ptr(&self, ip_address: IpAddr, fqdn: &str) -> Result<()> {
+        let name = Name::from(ip_address);
+        let zone = match self.reverse_zone(&name) {
+            Some(zone) => zone,
+            None => {
+                info!("No reverse zone is configured for the IP address, not updating reverse DNS");
+                return Ok(());
+            }
+        };
+        let target = absolute_name(fqdn)?;
+        let current = self.query(&name, RecordType::PTR).await?;
+        if current.len() == 1 && current[0].rdata() == &RData::PTR(target.clone()) {
+            info!("Reverse DNS record already defined");
+            return Ok(());
+        }
+        debug!(zone, "Replacing the PTR record");
+        let updates = vec![
+            delete_all(name.clone(), RecordType::PTR),
+            add(name, self.ptr_ttl, RData::PTR(target)),
+        ];
+        self.update(zone, updates).await?;
+        info!("Reverse DNS record updated");
+        Ok(())
+    }
+
+    async fn reset_ptr(&self, ip_address: IpAddr, fqdn: &str) -> Result<()> {
+        let name = Name::from(ip_address);
+        let zone = match self.reverse_zone(&name) {
+            Some(zone) => zone,
+            None => {
+                info!("No reverse zone is configured for the IP address, not resetting reverse DNS");
+                return Ok(());
+            }
+        };
+        let target = RData::PTR(absolute_name(fqdn)?);
+        let current = self.query(&name, RecordType::PTR).await?;
+        if !current.iter().any(|record| record.rdata() == &target) {
+            info!("Reverse DNS record does not point to the node anymore, leaving it alone");
+            return Ok(());
+        }
+        self.update(zone, vec![delete(name, target)]).await?;
+        info!("Reverse DNS record deleted");
+        Ok(())
+    }
+
+    fn reverse_name_servers(&self) -> Vec<String> {
+        vec![self.host().to_string()]
+    }
+
+    async fn validate_credentials(&self) -> Result<()> {
+        DnsProvider::validate_credentials(self).await
+    }
+}
@@ -0,0 +1,134 @@
+//! Record ownership, the way external-dns does it: every name this controller publishes has a companion TXT
+//! record naming the owner, so that records created by hand or by another cluster are never modified.
+
+use crate::provider::{DnsProvider, Record};
+use anyhow::Result;
+use thiserror::Error;
+use tracing::info;
+
+/// Prefix of the ownership records: `_node-dns.node-1` describes `node-1`
+const RECORD_PREFIX: &str = "_node-dns";
+
+const HERITAGE: &str = "heritage=node-dns";
+
+pub const DEFAULT_OWNER_ID: &str = "default";
+
+/// TTL of the records, in seconds. With Linode, this should be one of the values available in the UI, not every
+/// seconds value is supported.
+pub const DEFAULT_RECORD_TTL: u64 = 5 * 60;
+
+/// Who owns the records of a name
+#[derive(Clone, Debug, PartialEq)]
+pub enum Ownership {
+    /// Created by this controller
+    Owned,
+    /// Created by another instance of this controller, with a different owner id
+    Foreign(String),
+    /// No ownership record: created by hand, or by something else
+    Unclaimed,
+}
+
+/// The records of the name were not created by this controller, so it refuses to change them.
+#[derive(Debug, Error)]
+#[error("DNS records of {name} are not owned by this controller ({ownership:?}), not changing them")]
+pub struct ForeignRecordsError {
+    pub name: String,
+    pub ownership: Ownership,
+}
+
+#[derive(Clone, Debug)]
+pub struct Registry {
+    owner_id: String,
+    /// Of the ownership records, and of the records they describe
+    record_ttl: u64,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::new(DEFAULT_OWNER_ID)
+    }
+}
+
+impl Registry {
+    /// `owner_id` tells apart the clusters sharing the same zone.
+    pub fn new(owner_id: &str) -> Registry {
+        Registry {
+            owner_id: owner_id.to_string(),
+            record_ttl: DEFAULT_RECORD_TTL,
+        }
+    }
+
+    pub fn with_record_ttl(mut self, record_ttl: u64) -> Self {
+        self.record_ttl = record_ttl;
+        self
+    }
+
+    pub fn record_ttl(&self) -> u64 {
+        self.record_ttl
+    }
+
+    fn record_name(name: &str) -> String {
+        format!("{}.{}", RECORD_PREFIX, name)
+    }
+
+    fn content(&self) -> String {
+        format!("{},node-dns/owner={}", HERITAGE, self.owner_id)
+    }
+
+    /// Owner id of an ownership record, if it is one
+    fn owner(record: &Record) -> Option<&str> {
+        let mut fields = record.target.trim_matches('"').split(',');
+        if fields.next() != Some(HERITAGE) {
+            return None;
+        }
+        fields.find_map(|field| field.strip_prefix("node-dns/owner="))
+    }
+
+    pub async fn ownership(&self, provider: &dyn DnsProvider, zone: &str, name: &str) -> Result<Ownership> {
+        let records = provider.list_records(zone, &Self::record_name(name)).await?;
+        let owners: Vec<&str> = records
+            .iter()
+            .filter(|r| r.type_ == "TXT")
+            .filter_map(Self::owner)
+            .collect();
+        Ok(match owners.first() {
+            None => Ownership::Unclaimed,
+            Some(_) if owners.contains(&self.owner_id.as_str()) => Ownership::Owned,
+            Some(owner) => Ownership::Foreign(owner.to_string()),
+        })
+    }
+
+    /// Names owned by this controller, according to the ownership records among the records of a whole zone
+    pub fn owned_names(&self, records: &[Record]) -> Vec<String> {
+        records
+            .iter()
+            .filter(|r| r.type_ == "TXT" && Self::owner(r) == Some(self.owner_id.as_str()))
+            .filter_map(|r| r.name.strip_prefix(&format!("{}.", RECORD_PREFIX)))
+            .map(String::from)
+            .collect()
+    }
+
+    /// Record the ownership of the name, before creating its records.
+    pub async fn claim(&self, provider: &dyn DnsProvider, zone: &str, name: &str) -> Result<()> {
+        let record = Record::new(&Self::record_name(name), "TXT", &self.content(), self.record_ttl);
+        provider.create_record(zone, &record).await?;
+        info!(
+            name,
+            owner = self.owner_id.as_str(),
+            "Claimed the ownership of DNS records"
+        );
+        Ok(())
+    }
+
+    /// Remove the ownership record of the name, after deleting its records.
+    pub async fn release(&self, provider: &dyn DnsProvider, zone: &str, name: &str) -> Result<()> {
+        let records = provider.list_records(zone, &Self::record_name(name)).await?;
+        for record in records
+            .iter()
+            .filter(|r| r.type_ == "TXT" && Self::owner(r) == Some(self.owner_id.as_str()))
+        {
+            provider.delete_record(zone, record).await?;
+        }
+        Ok(())
+    }
+}
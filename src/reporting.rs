@@ -0,0 +1,208 @@
+//! What the controller did to the records of a node, told on the node itself: Kubernetes events, and annotations
+//! with the published name, the addresses and whether they are visible.
+
+use crate::dns::Change;
+use crate::state::STATE_ANNOTATION;
+use k8s_openapi::api::core::v1::{Node, ObjectReference};
+use k8s_openapi::api::events::v1::Event;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{MicroTime, ObjectMeta};
+use k8s_openapi::chrono::{DateTime, SecondsFormat, Utc};
+use kube::api::{Api, Patch, PatchParams, PostParams, ResourceExt};
+use serde_json::{json, Value};
+use std::fmt;
+use std::net::IpAddr;
+use tracing::warn;
+
+/// Fully qualified name of the node records
+pub const FQDN_ANNOTATION: &str = "k8s.haim.dev/dns-fqdn";
+/// Comma separated addresses the name points to
+pub const ADDRESSES_ANNOTATION: &str = "k8s.haim.dev/dns-addresses";
+/// One of `Verified`, `Pending` or `Failed`
+pub const STATUS_ANNOTATION: &str = "k8s.haim.dev/dns-status";
+/// When the records were last seen on the authoritative name servers, RFC 3339
+pub const LAST_VERIFIED_ANNOTATION: &str = "k8s.haim.dev/dns-last-verified";
+
+const CONTROLLER: &str = "node-dns";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    /// The records are visible on the authoritative name servers
+    Verified,
+    /// The records are set, but not visible yet
+    Pending,
+    Failed,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventType {
+    Normal,
+    Warning,
+}
+
+/// Merge patch of the annotations, or `None` when they already say the same. The timestamp alone does not
+/// warrant a patch, every patch triggers another reconcile of the node.
+pub fn annotations_patch(
+    node: &Node,
+    fqdn: &str,
+    ip_addresses: &[IpAddr],
+    status: Status,
+    now: DateTime<Utc>,
+) -> Option<Value> {
+    let addresses = ip_addresses
+        .iter()
+        .map(IpAddr::to_string)
+        .collect::<Vec<String>>()
+        .join(",");
+    let verified = status == Status::Verified;
+    let status = status.to_string();
+    let annotations = node.annotations();
+    let unchanged = [
+        (FQDN_ANNOTATION, fqdn),
+        (ADDRESSES_ANNOTATION, addresses.as_str()),
+        (STATUS_ANNOTATION, status.as_str()),
+    ]
+    .iter()
+    .all(|(key, value)| annotations.get(*key).map(String::as_str) == Some(*value));
+    if unchanged {
+        return None;
+    }
+    let mut patch = json!({
+        FQDN_ANNOTATION: fqdn,
+        ADDRESSES_ANNOTATION: addresses,
+        STATUS_ANNOTATION: status,
+    });
+    if verified {
+        patch[LAST_VERIFIED_ANNOTATION] = json!(now.to_rfc3339_opts(SecondsFormat::Secs, true));
+    }
+    Some(json!({ "metadata": { "annotations": patch } }))
+}
+
+/// Merge patch setting the status to `Failed`, keeping the name and the addresses that were last published
+pub fn failed_patch(node: &Node) -> Option<Value> {
+    let failed = Status::Failed.to_string();
+    if node.annotations().get(STATUS_ANNOTATION) == Some(&failed) {
+        return None;
+    }
+    Some(json!({ "metadata": { "annotations": { STATUS_ANNOTATION: failed } } }))
+}
+
+/// Merge patch removing all the annotations, the applied state included, or `None` when the node has none of them
+pub fn clear_annotations_patch(node: &Node) -> Option<Value> {
+    let keys = [
+        FQDN_ANNOTATION,
+        ADDRESSES_ANNOTATION,
+        STATUS_ANNOTATION,
+        LAST_VERIFIED_ANNOTATION,
+        STATE_ANNOTATION,
+    ];
+    let annotations = node.annotations();
+    if !keys.iter().any(|key| annotations.contains_key(*key)) {
+        return None;
+    }
+    let patch: serde_json::Map<String, Value> = keys.iter().map(|key| (key.to_string(), Value::Null)).collect();
+    Some(json!({ "metadata": { "annotations": patch } }))
+}
+
+/// Publishes the events and the annotations. Failing to do either is logged, but does not fail the reconcile: the
+/// records are what matters.
+pub struct Reporter {
+    client: kube::Client,
+    /// Pod name, to tell the replicas apart
+    instance: String,
+    /// Nodes are not namespaced, but their events are. `kubectl describe node` finds them in any namespace.
+    namespace: String,
+}
+
+impl Reporter {
+    /// The instance is the pod name and the namespace the pod namespace, when set through the downward API
+    pub fn new(client: kube::Client, instance: Option<&str>, namespace: &str) -> Self {
+        Reporter {
+            client,
+            instance: instance.unwrap_or(CONTROLLER).to_string(),
+            namespace: namespace.to_string(),
+        }
+    }
+
+    pub async fn publish(&self, node: &Node, type_: EventType, reason: &str, note: String) {
+        let regarding = ObjectReference {
+            api_version: Some("v1".to_string()),
+            kind: Some("Node".to_string()),
+            name: Some(node.name()),
+            uid: node.uid(),
+            resource_version: node.resource_version(),
+            ..ObjectReference::default()
+        };
+        let event = Event {
+            metadata: ObjectMeta {
+                generate_name: Some(format!("{}-", CONTROLLER)),
+                namespace: Some(self.namespace.clone()),
+                ..ObjectMeta::default()
+            },
+            action: Some("Reconcile".to_string()),
+            reason: Some(reason.to_string()),
+            note: Some(note),
+            regarding: Some(regarding),
+            reporting_controller: Some(CONTROLLER.to_string()),
+            reporting_instance: Some(self.instance.clone()),
+            type_: Some(format!("{:?}", type_)),
+            event_time: MicroTime(Utc::now()),
+            related: None,
+            series: None,
+            deprecated_count: None,
+            deprecated_first_timestamp: None,
+            deprecated_last_timestamp: None,
+            deprecated_source: None,
+        };
+        let events: Api<Event> = Api::namespaced(self.client.clone(), &self.namespace);
+        if let Err(error) = events.create(&PostParams::default(), &event).await {
+            warn!(
+                node = node.name().as_str(),
+                reason,
+                error = format!("{}", error).as_str(),
+                "Failed to publish the event"
+            );
+        }
+    }
+
+    pub async fn changed(&self, node: &Node, change: &Change) {
+        let reason = match change {
+            Change::Created { .. } => "RecordCreated",
+            Change::Updated { .. } => "RecordUpdated",
+            Change::Adopted { .. } => "RecordAdopted",
+            Change::ReverseUpdated { .. } => "ReverseDnsUpdated",
+        };
+        self.publish(node, EventType::Normal, reason, change.to_string()).await;
+    }
+
+    pub async fn annotate(&self, node: &Node, fqdn: &str, ip_addresses: &[IpAddr], status: Status) {
+        if let Some(patch) = annotations_patch(node, fqdn, ip_addresses, status, Utc::now()) {
+            self.patch(node, patch).await;
+        }
+    }
+
+    pub async fn failed(&self, node: &Node) {
+        if let Some(patch) = failed_patch(node) {
+            self.patch(node, patch).await;
+        }
+    }
+
+    async fn patch(&self, node: &Node, patch: Value) {
+        let nodes: Api<Node> = Api::all(self.client.clone());
+        if let Err(error) = nodes
+            .patch(&node.name(), &PatchParams::default(), &Patch::Merge(&patch))
+            .await
+        {
+            warn!(
+                node = node.name().as_str(),
+                error = format!("{}", error).as_str(),
+                "Failed to annotate the node"
+            );
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! Retry policy shared by the API clients: when a failed request is worth retrying, and how long to wait.
+
+use rand::Rng;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// How many times a failed request is retried before giving up
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+
+/// Exponential backoff starts here, and is capped at the maximum. The actual delay is randomized (full jitter).
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+
+/// Longest wait before a retry, whether from the backoff or asked by the server, so that a bogus header cannot
+/// stall a reconcile
+pub const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Exponential backoff with full jitter: a random delay up to `BACKOFF_BASE * 2^attempt`, capped.
+pub fn backoff(attempt: u32) -> Duration {
+    let cap = BACKOFF_BASE.saturating_mul(2u32.saturating_pow(attempt)).min(MAX_DELAY);
+    rand::thread_rng().gen_range(Duration::ZERO..=cap)
+}
+
+/// How long the server asked us to wait, from the `Retry-After` header (in seconds), at most `MAX_DELAY`
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .map(|seconds| Duration::from_secs(seconds).min(MAX_DELAY))
+}
+
+/// Rate-limited requests (429) are always retried. Server errors only when the request is idempotent (anything
+/// but POST): a create may have been applied before the server failed.
+pub fn is_retryable_status(status: StatusCode, idempotent: bool) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || (idempotent && status.is_server_error())
+}
+
+/// Connection failures and timeouts, the request errors that may not happen again
+pub fn is_transient_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout()
+}
+
+/// Transient errors, under the same condition as server errors
+pub fn is_retryable_error(error: &reqwest::Error, idempotent: bool) -> bool {
+    idempotent && is_transient_error(error)
+}
+
+pub fn is_idempotent(request: &reqwest::Request) -> bool {
+    request.method() != reqwest::Method::POST
+}
@@ -0,0 +1,118 @@
+//! Which nodes get DNS records: the ones matching a label selector, minus the ones opting out with an annotation.
+
+use anyhow::{bail, Result};
+use k8s_openapi::api::core::v1::Node;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// `k8s.haim.dev/dns-skip: "true"` keeps a node out, even when it matches the selector
+pub const SKIP_ANNOTATION: &str = "k8s.haim.dev/dns-skip";
+
+#[derive(Clone, Debug, PartialEq)]
+enum Requirement {
+    Equals(String, String),
+    NotEquals(String, String),
+    In(String, Vec<String>),
+    NotIn(String, Vec<String>),
+    Exists(String),
+    NotExists(String),
+}
+
+impl Requirement {
+    fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        match self {
+            Requirement::Equals(key, value) => labels.get(key) == Some(value),
+            Requirement::NotEquals(key, value) => labels.get(key) != Some(value),
+            Requirement::In(key, values) => labels.get(key).map(|v| values.contains(v)).unwrap_or(false),
+            Requirement::NotIn(key, values) => labels.get(key).map(|v| !values.contains(v)).unwrap_or(true),
+            Requirement::Exists(key) => labels.contains_key(key),
+            Requirement::NotExists(key) => !labels.contains_key(key),
+        }
+    }
+}
+
+impl FromStr for Requirement {
+    type Err = anyhow::Error;
+
+    fn from_str(requirement: &str) -> Result<Self> {
+        let key = |key: &str| {
+            let key = key.trim();
+            if key.is_empty() || key.contains(char::is_whitespace) {
+                bail!("Invalid label key in node selector requirement {:?}", requirement);
+            }
+            Ok(key.to_string())
+        };
+        let values = |values: &str| {
+            let values = values.trim();
+            match values.strip_prefix('(').and_then(|values| values.strip_suffix(')')) {
+                Some(values) => Ok(values.split(',').map(|value| value.trim().to_string()).collect()),
+                None => bail!("Expected a list of values in parentheses in {:?}", requirement),
+            }
+        };
+        let requirement = requirement.trim();
+        if let Some(not) = requirement.strip_prefix('!') {
+            return Ok(Requirement::NotExists(key(not)?));
+        }
+        if let Some((k, v)) = requirement.split_once(" notin ") {
+            return Ok(Requirement::NotIn(key(k)?, values(v)?));
+        }
+        if let Some((k, v)) = requirement.split_once(" in ") {
+            return Ok(Requirement::In(key(k)?, values(v)?));
+        }
+        if let Some((k, v)) = requirement.split_once("!=") {
+            return Ok(Requirement::NotEquals(key(k)?, v.trim().to_string()));
+        }
+        if let Some((k, v)) = requirement.split_once("==").or_else(|| requirement.split_once('=')) {
+            return Ok(Requirement::Equals(key(k)?, v.trim().to_string()));
+        }
+        Ok(Requirement::Exists(key(requirement)?))
+    }
+}
+
+/// Label selector, in the `kubectl --selector` syntax, e.g. `lke.linode.com/pool-id in (5678,5679),!spot`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodeSelector {
+    requirements: Vec<Requirement>,
+}
+
+impl FromStr for NodeSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(selector: &str) -> Result<Self> {
+        // Commas separate the requirements, except inside the value lists
+        let mut requirements = vec![];
+        let mut depth = 0;
+        let mut start = 0;
+        for (index, c) in selector.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    requirements.push(selector[start..index].parse()?);
+                    start = index + 1;
+                }
+                _ => {}
+            }
+        }
+        if !selector[start..].trim().is_empty() {
+            requirements.push(selector[start..].parse()?);
+        }
+        Ok(NodeSelector { requirements })
+    }
+}
+
+impl NodeSelector {
+    /// Whether the node should have DNS records
+    pub fn matches(&self, node: &Node) -> bool {
+        let skipped = node
+            .metadata
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(SKIP_ANNOTATION))
+            .map(|skip| skip == "true")
+            .unwrap_or(false);
+        let no_labels = BTreeMap::new();
+        let labels = node.metadata.labels.as_ref().unwrap_or(&no_labels);
+        !skipped && self.requirements.iter().all(|requirement| requirement.matches(labels))
+    }
+}
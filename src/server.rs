@@ -0,0 +1,54 @@
+//! HTTP server of the controller, for Prometheus to scrape the metrics and for the Kubernetes probes.
+
+use crate::health::Health;
+use crate::metrics;
+use anyhow::{Context, Result};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tracing::{error, info};
+
+pub const DEFAULT_ADDRESS: &str = "0.0.0.0:8080";
+
+fn probe(ok: bool) -> hyper::http::Result<Response<Body>> {
+    let (status, body) = if ok {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not ok")
+    };
+    Response::builder().status(status).body(Body::from(body))
+}
+
+async fn handle(health: Arc<Health>, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(metrics::render())),
+        (&Method::GET, "/healthz") => probe(health.is_alive()),
+        (&Method::GET, "/readyz") => probe(health.is_ready()),
+        _ => Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()),
+    };
+    Ok(response.expect("Static responses are valid"))
+}
+
+/// Bind right away, so that a taken port fails the startup, and serve when the returned future is polled.
+pub fn serve(address: SocketAddr, health: Arc<Health>) -> Result<impl Future<Output = ()>> {
+    let server = hyper::Server::try_bind(&address)
+        .context(format!("Could not listen on {}", address))?
+        .serve(make_service_fn(move |_| {
+            let health = health.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |request| handle(health.clone(), request))) }
+        }));
+    info!(
+        address = address.to_string().as_str(),
+        "Serving the metrics and the probes"
+    );
+    Ok(async move {
+        if let Err(error) = server.await {
+            error!(error = format!("{}", error).as_str(), "HTTP server failed");
+        }
+    })
+}
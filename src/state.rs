@@ -0,0 +1,76 @@
+//! What was last applied to the records of a node, kept in an annotation on the node itself, so that it survives
+//! the restarts of the controller and the changes of leader.
+
+use k8s_openapi::api::core::v1::Node;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::api::ResourceExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::net::IpAddr;
+use tracing::debug;
+
+/// JSON of the `AppliedState`
+pub const STATE_ANNOTATION: &str = "k8s.haim.dev/dns-state";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedState {
+    /// Fully qualified name of the records
+    pub fqdn: String,
+    /// Name of the records, relative to the domain
+    pub host_name: String,
+    /// Fully qualified name of the internal records, `None` when they are not published
+    #[serde(default)]
+    pub internal_fqdn: Option<String>,
+    /// `DNS_PROVIDER`, the records are not on the new one when it changes
+    #[serde(default)]
+    pub provider: String,
+    /// Provider of the reverse DNS, `none` when it is not managed
+    #[serde(default)]
+    pub reverse_provider: String,
+    /// All the external addresses of the node, not only the published ones, to notice any change
+    pub ip_addresses: Vec<IpAddr>,
+    #[serde(default)]
+    pub internal_ip_addresses: Vec<IpAddr>,
+    /// When the records were verified
+    pub applied_at: DateTime<Utc>,
+}
+
+impl AppliedState {
+    /// The state of the node, `None` if it has none yet, or one this version cannot read: the node is then
+    /// verified again, and the state rewritten.
+    pub fn from_node(node: &Node) -> Option<Self> {
+        let annotation = node.annotations().get(STATE_ANNOTATION)?;
+        match serde_json::from_str(annotation) {
+            Ok(state) => Some(state),
+            Err(error) => {
+                debug!(
+                    node = node.name().as_str(),
+                    error = format!("{}", error).as_str(),
+                    "Ignoring unreadable applied state"
+                );
+                None
+            }
+        }
+    }
+
+    /// Whether the records are for the same names, addresses and providers as `other`, whenever they were applied
+    pub fn matches(&self, other: &AppliedState) -> bool {
+        let applied = AppliedState {
+            applied_at: other.applied_at,
+            ..self.clone()
+        };
+        applied == *other
+    }
+
+    /// Merge patch of the node saving the state
+    pub fn patch(&self) -> Value {
+        let state = serde_json::to_string(self).expect("Applied state is always serializable");
+        json!({ "metadata": { "annotations": { STATE_ANNOTATION: state } } })
+    }
+}
+
+/// Merge patch of the node forgetting its state, so that its records are verified again
+pub fn forget_patch() -> Value {
+    json!({ "metadata": { "annotations": { STATE_ANNOTATION: null } } })
+}
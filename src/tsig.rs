@@ -0,0 +1,286 @@
+//! Transaction signatures (TSIG, RFC 8945) for DNS messages, just what is needed to sign dynamic updates
+//! and verify the answers. trust-dns does not support TSIG in the version we use.
+
+use anyhow::{bail, Context, Result};
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
+use sha2::{Sha256, Sha512};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use trust_dns_resolver::proto::error::ProtoResult;
+use trust_dns_resolver::proto::op::{Header, Query};
+use trust_dns_resolver::proto::rr::Record;
+use trust_dns_resolver::proto::serialize::binary::{BinDecodable, BinDecoder};
+use trust_dns_resolver::Name;
+
+/// TSIG resource record type
+const TSIG_TYPE: u16 = 250;
+
+/// DNS class ANY, the only class TSIG records use
+const CLASS_ANY: u16 = 255;
+
+/// Allowed clock difference between the signer and the verifier, the value recommended by the RFC.
+const FUDGE: u16 = 300;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    HmacSha256,
+    HmacSha512,
+}
+
+impl Algorithm {
+    fn name(&self) -> &'static str {
+        match self {
+            Algorithm::HmacSha256 => "hmac-sha256.",
+            Algorithm::HmacSha512 => "hmac-sha512.",
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim_end_matches('.').to_ascii_lowercase().as_str() {
+            "hmac-sha256" => Ok(Algorithm::HmacSha256),
+            "hmac-sha512" => Ok(Algorithm::HmacSha512),
+            other => bail!("Unsupported TSIG algorithm {}", other),
+        }
+    }
+}
+
+/// A shared TSIG key, as configured on the DNS server (e.g. with `tsig-keygen` for BIND).
+#[derive(Clone)]
+pub struct Key {
+    name: Name,
+    algorithm: Algorithm,
+    secret: Vec<u8>,
+}
+
+/// The fields of a TSIG record
+struct Tsig {
+    name: Name,
+    algorithm: Name,
+    time_signed: u64,
+    fudge: u16,
+    mac: Vec<u8>,
+    error: u16,
+    other: Vec<u8>,
+}
+
+impl Key {
+    /// `secret` is base64-encoded, the way DNS servers present it.
+    pub fn new(name: &str, algorithm: Algorithm, secret: &str) -> Result<Key> {
+        let mut name = Name::from_str(name).context("Invalid TSIG key name")?;
+        name.set_fqdn(true);
+        let secret = base64::decode(secret.trim()).context("TSIG secret is not valid base64")?;
+        Ok(Key {
+            name,
+            algorithm,
+            secret,
+        })
+    }
+
+    /// Append a TSIG record to the encoded message. `request_mac` is the signature of the request, when
+    /// signing a response to it. Returns the signed message and its signature.
+    pub fn sign(&self, message: &[u8], request_mac: Option<&[u8]>) -> Result<(Vec<u8>, Vec<u8>)> {
+        self.sign_at(message, request_mac, now())
+    }
+
+    /// [`Key::sign`] with the given time, in seconds since the epoch
+    pub fn sign_at(&self, message: &[u8], request_mac: Option<&[u8]>, time_signed: u64) -> Result<(Vec<u8>, Vec<u8>)> {
+        if message.len() < 12 {
+            bail!("DNS message is too short");
+        }
+        let original_id = u16::from_be_bytes([message[0], message[1]]);
+        let variables = self.variables(time_signed, FUDGE, 0, &[]);
+        let mac = self.mac(request_mac, message, &variables);
+
+        let mut rdata = wire_name(&Name::from_str(self.algorithm.name())?);
+        rdata.extend_from_slice(&time_signed.to_be_bytes()[2..]);
+        rdata.extend_from_slice(&FUDGE.to_be_bytes());
+        rdata.extend_from_slice(&(mac.len() as u16).to_be_bytes());
+        rdata.extend_from_slice(&mac);
+        rdata.extend_from_slice(&original_id.to_be_bytes());
+        rdata.extend_from_slice(&0u16.to_be_bytes()); // error
+        rdata.extend_from_slice(&0u16.to_be_bytes()); // other data length
+
+        let mut signed = message.to_vec();
+        let count = additional_count(&signed) + 1;
+        set_additional_count(&mut signed, count);
+        signed.extend(wire_name(&self.name));
+        signed.extend_from_slice(&TSIG_TYPE.to_be_bytes());
+        signed.extend_from_slice(&CLASS_ANY.to_be_bytes());
+        signed.extend_from_slice(&0u32.to_be_bytes()); // TTL
+        signed.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        signed.extend(rdata);
+        Ok((signed, mac))
+    }
+
+    /// Check the TSIG record at the end of the encoded message. `request_mac` is the signature of the request,
+    /// when verifying a response to it. Returns the message without the TSIG record, and its signature.
+    pub fn verify(&self, message: &[u8], request_mac: Option<&[u8]>) -> Result<(Vec<u8>, Vec<u8>)> {
+        self.verify_at(message, request_mac, now())
+    }
+
+    /// [`Key::verify`] at the given time, in seconds since the epoch
+    pub fn verify_at(&self, message: &[u8], request_mac: Option<&[u8]>, now: u64) -> Result<(Vec<u8>, Vec<u8>)> {
+        let (unsigned, tsig) = split_tsig(message).context("Could not parse the DNS message")?;
+        let tsig = tsig.context("DNS message is not signed")?;
+        if tsig.name != self.name {
+            bail!("DNS message is signed with an unknown key {}", tsig.name);
+        }
+        if tsig.algorithm.to_lowercase() != Name::from_str(self.algorithm.name())? {
+            bail!("DNS message is signed with an unexpected algorithm {}", tsig.algorithm);
+        }
+        if tsig.error != 0 {
+            bail!("DNS server rejected the signature, TSIG error {}", tsig.error);
+        }
+        let variables = self.variables(tsig.time_signed, tsig.fudge, tsig.error, &tsig.other);
+        if !self.verify_mac(request_mac, &unsigned, &variables, &tsig.mac) {
+            bail!("DNS message signature does not match");
+        }
+        // Not `abs_diff`, which needs a newer Rust than the image is built with
+        let skew = now.max(tsig.time_signed) - now.min(tsig.time_signed);
+        if skew > tsig.fudge as u64 {
+            bail!("DNS message signature has expired, check the clocks");
+        }
+        Ok((unsigned, tsig.mac))
+    }
+
+    /// The TSIG fields that are signed, in addition to the message itself
+    fn variables(&self, time_signed: u64, fudge: u16, error: u16, other: &[u8]) -> Vec<u8> {
+        let mut variables = wire_name(&self.name);
+        variables.extend_from_slice(&CLASS_ANY.to_be_bytes());
+        variables.extend_from_slice(&0u32.to_be_bytes()); // TTL
+        variables.extend(wire_name(&Name::from_str(self.algorithm.name()).unwrap()));
+        variables.extend_from_slice(&time_signed.to_be_bytes()[2..]);
+        variables.extend_from_slice(&fudge.to_be_bytes());
+        variables.extend_from_slice(&error.to_be_bytes());
+        variables.extend_from_slice(&(other.len() as u16).to_be_bytes());
+        variables.extend_from_slice(other);
+        variables
+    }
+
+    fn signed_parts(request_mac: Option<&[u8]>, message: &[u8], variables: &[u8]) -> Vec<Vec<u8>> {
+        let mut parts = vec![];
+        if let Some(request_mac) = request_mac {
+            parts.push((request_mac.len() as u16).to_be_bytes().to_vec());
+            parts.push(request_mac.to_vec());
+        }
+        parts.push(message.to_vec());
+        parts.push(variables.to_vec());
+        parts
+    }
+
+    fn mac(&self, request_mac: Option<&[u8]>, message: &[u8], variables: &[u8]) -> Vec<u8> {
+        let parts = Self::signed_parts(request_mac, message, variables);
+        match self.algorithm {
+            Algorithm::HmacSha256 => hmac::<Hmac<Sha256>>(&self.secret, &parts)
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+            Algorithm::HmacSha512 => hmac::<Hmac<Sha512>>(&self.secret, &parts)
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+        }
+    }
+
+    fn verify_mac(&self, request_mac: Option<&[u8]>, message: &[u8], variables: &[u8], expected: &[u8]) -> bool {
+        let parts = Self::signed_parts(request_mac, message, variables);
+        match self.algorithm {
+            Algorithm::HmacSha256 => hmac::<Hmac<Sha256>>(&self.secret, &parts)
+                .verify_slice(expected)
+                .is_ok(),
+            Algorithm::HmacSha512 => hmac::<Hmac<Sha512>>(&self.secret, &parts)
+                .verify_slice(expected)
+                .is_ok(),
+        }
+    }
+}
+
+fn hmac<M: Mac + KeyInit>(secret: &[u8], parts: &[Vec<u8>]) -> M {
+    let mut mac = <M as KeyInit>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Uncompressed, lowercase wire format of a name, as used when computing signatures
+fn wire_name(name: &Name) -> Vec<u8> {
+    let mut wire = vec![];
+    for label in name.to_lowercase().iter() {
+        wire.push(label.len() as u8);
+        wire.extend_from_slice(label);
+    }
+    wire.push(0);
+    wire
+}
+
+fn additional_count(message: &[u8]) -> u16 {
+    u16::from_be_bytes([message[10], message[11]])
+}
+
+fn set_additional_count(message: &mut [u8], count: u16) {
+    message[10..12].copy_from_slice(&count.to_be_bytes());
+}
+
+/// Split the message into the part covered by the signature (with the original id, and the additional
+/// records count not including the TSIG record), and the TSIG record itself, if the last record is one.
+fn split_tsig(message: &[u8]) -> ProtoResult<(Vec<u8>, Option<Tsig>)> {
+    let mut decoder = BinDecoder::new(message);
+    let header = Header::read(&mut decoder)?;
+    for _ in 0..header.query_count() {
+        Query::read(&mut decoder)?;
+    }
+    let records =
+        header.answer_count() as usize + header.name_server_count() as usize + header.additional_count() as usize;
+    if header.additional_count() == 0 {
+        return Ok((message.to_vec(), None));
+    }
+    for _ in 0..records - 1 {
+        Record::read(&mut decoder)?;
+    }
+    let tsig_start = decoder.index();
+    let name = Name::read(&mut decoder)?;
+    let rr_type = decoder.read_u16()?.unverified();
+    if rr_type != TSIG_TYPE {
+        return Ok((message.to_vec(), None));
+    }
+    let _class = decoder.read_u16()?.unverified();
+    let _ttl = decoder.read_u32()?.unverified();
+    let _rdata_length = decoder.read_u16()?.unverified();
+    let algorithm = Name::read(&mut decoder)?;
+    let time_high = decoder.read_u16()?.unverified() as u64;
+    let time_low = decoder.read_u32()?.unverified() as u64;
+    let fudge = decoder.read_u16()?.unverified();
+    let mac_size = decoder.read_u16()?.unverified();
+    let mac = decoder.read_slice(mac_size as usize)?.unverified().to_vec();
+    let original_id = decoder.read_u16()?.unverified();
+    let error = decoder.read_u16()?.unverified();
+    let other_length = decoder.read_u16()?.unverified();
+    let other = decoder.read_slice(other_length as usize)?.unverified().to_vec();
+
+    let mut unsigned = message[..tsig_start].to_vec();
+    unsigned[0..2].copy_from_slice(&original_id.to_be_bytes());
+    set_additional_count(&mut unsigned, header.additional_count() - 1);
+    let tsig = Tsig {
+        name,
+        algorithm,
+        time_signed: (time_high << 32) | time_low,
+        fudge,
+        mac,
+        error,
+        other,
+    };
+    Ok((unsigned, Some(tsig)))
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use common::mock_cloudflare::{MockCloudflare, MockRecord, NAME_SERVERS, TOKEN};
+use common::mock_linode::MockLinode;
+use common::mock_resolver;
+use node_dns::cloudflare::{self, CloudflareError};
+use node_dns::dns::{self, Propagation};
+use node_dns::provider::{DnsProvider, Providers, Record};
+use node_dns::registry::Registry;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+const DOMAIN: &str = "k8s.example.com";
+
+fn find<'a>(records: &'a [MockRecord], name: &str, type_: &str) -> Option<&'a MockRecord> {
+    records.iter().find(|r| r.name == name && r.type_ == type_)
+}
+
+#[tokio::test]
+async fn manages_records_relative_to_the_zone() {
+    let mock = MockCloudflare::start().await;
+    let zone_id = mock.add_zone(DOMAIN);
+    mock.add_record(&zone_id, "node-1.k8s.example.com", "TXT", "hand-made");
+    let provider = mock.provider();
+
+    let created = provider
+        .create_record(DOMAIN, &Record::new("node-1", "A", "192.0.2.10", 300))
+        .await
+        .unwrap();
+    assert_eq!(created.name, "node-1");
+    let record = find(&mock.records(&zone_id), "node-1.k8s.example.com", "A")
+        .unwrap()
+        .clone();
+    assert_eq!(record.content, "192.0.2.10");
+    assert!(!record.proxied);
+
+    let records = provider.list_records(DOMAIN, "node-1").await.unwrap();
+    assert_eq!(records.len(), 2);
+    assert!(records.iter().all(|r| r.name == "node-1"));
+
+    let mut updated = created.clone();
+    updated.target = "192.0.2.11".to_string();
+    provider.update_record(DOMAIN, &updated).await.unwrap();
+    let records = mock.records(&zone_id);
+    assert_eq!(
+        find(&records, "node-1.k8s.example.com", "A").unwrap().content,
+        "192.0.2.11"
+    );
+
+    provider.delete_record(DOMAIN, &updated).await.unwrap();
+    let records = mock.records(&zone_id);
+    assert!(find(&records, "node-1.k8s.example.com", "A").is_none());
+    assert!(find(&records, "node-1.k8s.example.com", "TXT").is_some());
+
+    assert_eq!(provider.name_servers(DOMAIN).await.unwrap(), NAME_SERVERS);
+    // The zone was looked up once
+    let zone_lookups = mock.requests().iter().filter(|r| *r == "GET zones").count();
+    assert_eq!(zone_lookups, 1);
+}
+
+#[tokio::test]
+async fn forward_records_at_cloudflare_and_reverse_dns_at_linode() {
+    let cloudflare = MockCloudflare::start().await;
+    let zone_id = cloudflare.add_zone(DOMAIN);
+    let linode = MockLinode::start().await;
+    linode.add_ip("192.0.2.10");
+    let providers = Providers {
+        forward: Arc::new(cloudflare.provider()),
+        reverse: Some(Arc::new(linode.provider())),
+    };
+    let resolvers = mock_resolver::resolvers(cloudflare.forward_answer(), linode.reverse_answer()).await;
+    let ip: IpAddr = "192.0.2.10".parse().unwrap();
+
+    let outcome = dns::update(&providers, &Registry::default(), &resolvers, DOMAIN, "node-1", &[ip])
+        .await
+        .unwrap();
+
+    assert_eq!(outcome.propagation, Propagation::Verified);
+    let records = cloudflare.records(&zone_id);
+    assert_eq!(
+        find(&records, "node-1.k8s.example.com", "A").unwrap().content,
+        "192.0.2.10"
+    );
+    assert_eq!(
+        find(&records, "192.0.2.10._spf.k8s.example.com", "A").unwrap().content,
+        "192.0.2.10"
+    );
+    assert_eq!(linode.rdns("192.0.2.10"), Some("node-1.k8s.example.com".to_string()));
+    assert!(linode.requests().iter().all(|r| r.contains("networking/ips")));
+}
+
+#[tokio::test]
+async fn rejected_token_is_an_auth_failure() {
+    let mock = MockCloudflare::start().await;
+    mock.add_zone(DOMAIN);
+    let client = cloudflare::Client::new("wrong-token").with_base_url(&mock.url());
+
+    let error = client.zone(DOMAIN).await.unwrap_err();
+
+    assert!(matches!(error, CloudflareError::Unauthorized(_)));
+    assert!(error.is_auth_failure());
+    assert!(error.to_string().contains("Authentication error"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn retries_rate_limited_and_failed_requests() {
+    let mock = MockCloudflare::start().await;
+    mock.add_zone(DOMAIN);
+    mock.fail_next(429, Some(10));
+    mock.fail_next(502, None);
+    let client = cloudflare::Client::new(TOKEN).with_base_url(&mock.url());
+
+    let zone = client.zone(DOMAIN).await.unwrap();
+
+    assert!(zone.is_some());
+    assert_eq!(mock.requests().len(), 3);
+
+    mock.fail_next(503, None);
+    let error = client.with_max_retries(0).verify_token().await.unwrap_err();
+    assert!(error.is_retryable());
+}
@@ -0,0 +1,309 @@
+//! In-memory stand-in for the zones and DNS records endpoints of the Cloudflare API.
+
+use super::mock_resolver::{address, Answer};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+pub const TOKEN: &str = "mock-cloudflare-token";
+
+pub const NAME_SERVERS: [&str; 2] = ["ada.ns.cloudflare.com", "bob.ns.cloudflare.com"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockRecord {
+    pub id: String,
+    /// Fully qualified
+    pub name: String,
+    pub type_: String,
+    pub content: String,
+    pub proxied: bool,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    zones: Vec<(String, String)>,
+    records: Vec<(String, MockRecord)>,
+    requests: Vec<String>,
+    failures: Vec<(StatusCode, Option<u64>)>,
+}
+
+impl State {
+    fn next_id(&mut self) -> String {
+        self.next_id += 1;
+        format!("{:032x}", self.next_id)
+    }
+}
+
+pub struct MockCloudflare {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockCloudflare {
+    pub async fn start() -> MockCloudflare {
+        let state = Arc::new(Mutex::new(State::default()));
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let state = state.clone();
+                    async move { Ok::<_, Infallible>(route(state, request).await) }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        MockCloudflare { addr, state }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}/client/v4/", self.addr)
+    }
+
+    pub fn provider(&self) -> node_dns::provider::CloudflareProvider {
+        let client = node_dns::cloudflare::Client::new(TOKEN).with_base_url(&self.url());
+        node_dns::provider::CloudflareProvider::new(client)
+    }
+
+    pub fn add_zone(&self, name: &str) -> String {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id();
+        state.zones.push((id.clone(), name.to_string()));
+        id
+    }
+
+    pub fn add_record(&self, zone_id: &str, name: &str, type_: &str, content: &str) -> String {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id();
+        let record = MockRecord {
+            id: id.clone(),
+            name: name.to_string(),
+            type_: type_.to_string(),
+            content: content.to_string(),
+            proxied: false,
+        };
+        state.records.push((zone_id.to_string(), record));
+        id
+    }
+
+    pub fn records(&self, zone_id: &str) -> Vec<MockRecord> {
+        let state = self.state.lock().unwrap();
+        state
+            .records
+            .iter()
+            .filter(|(zone, _)| zone == zone_id)
+            .map(|(_, record)| record.clone())
+            .collect()
+    }
+
+    /// Fail the next request with the given status, and optionally a `Retry-After` header (in seconds).
+    /// Can be called several times to fail several requests in a row.
+    pub fn fail_next(&self, status: u16, retry_after: Option<u64>) {
+        let status = StatusCode::from_u16(status).unwrap();
+        self.state.lock().unwrap().failures.push((status, retry_after));
+    }
+
+    /// Requests served so far, as "METHOD path" strings.
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Name server answers for the records of all the zones
+    pub fn forward_answer(&self) -> Answer {
+        let state = self.state.clone();
+        Arc::new(move |name, record_type| {
+            let name = name.to_string();
+            let name = name.trim_end_matches('.');
+            state
+                .lock()
+                .unwrap()
+                .records
+                .iter()
+                .filter(|(_, r)| r.name == name && r.type_ == record_type.to_string())
+                .filter_map(|(_, r)| address(record_type, &r.content))
+                .collect()
+        })
+    }
+}
+
+fn envelope(status: StatusCode, result: Value, result_info: Option<Value>) -> Response<Body> {
+    let body = json!({
+        "success": status.is_success(),
+        "errors": [],
+        "messages": [],
+        "result": result,
+        "result_info": result_info,
+    });
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn error_response(status: StatusCode, code: u64, message: &str) -> Response<Body> {
+    let body = json!({
+        "success": false,
+        "errors": [{ "code": code, "message": message }],
+        "messages": [],
+        "result": null,
+    });
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn record_json(zone_id: &str, record: &MockRecord) -> Value {
+    json!({
+        "id": record.id,
+        "zone_id": zone_id,
+        "name": record.name,
+        "type": record.type_,
+        "content": record.content,
+        "proxiable": true,
+        "proxied": record.proxied,
+        "ttl": 300,
+        "locked": false,
+    })
+}
+
+fn query_param(request: &Request<Body>, name: &str) -> Option<String> {
+    request.uri().query()?.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then(|| value.to_string())
+    })
+}
+
+// `div_ceil` needs a newer Rust than the image is built with
+#[allow(clippy::manual_div_ceil)]
+fn paginated(request: &Request<Body>, items: Vec<Value>) -> Response<Body> {
+    let page: usize = query_param(request, "page").and_then(|p| p.parse().ok()).unwrap_or(1);
+    let per_page: usize = query_param(request, "per_page")
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(20);
+    let total_count = items.len();
+    let data: Vec<Value> = items.into_iter().skip((page - 1) * per_page).take(per_page).collect();
+    let result_info = json!({
+        "page": page,
+        "per_page": per_page,
+        "count": data.len(),
+        "total_count": total_count,
+        "total_pages": ((total_count + per_page - 1) / per_page).max(1),
+    });
+    envelope(StatusCode::OK, Value::Array(data), Some(result_info))
+}
+
+async fn route(state: Arc<Mutex<State>>, request: Request<Body>) -> Response<Body> {
+    let method = request.method().clone();
+    let path = request.uri().path().trim_start_matches("/client/v4/").to_string();
+    let failure = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(format!("{} {}", method, path));
+        (!state.failures.is_empty()).then(|| state.failures.remove(0))
+    };
+    if let Some((status, retry_after)) = failure {
+        let mut response = error_response(status, 10000, "Injected failure");
+        if let Some(retry_after) = retry_after {
+            response.headers_mut().insert("Retry-After", retry_after.into());
+        }
+        return response;
+    }
+
+    let authorized = request
+        .headers()
+        .get("Authorization")
+        .map(|value| value == format!("Bearer {}", TOKEN).as_str())
+        .unwrap_or(false);
+    if !authorized {
+        return error_response(StatusCode::FORBIDDEN, 10000, "Authentication error");
+    }
+
+    let segments: Vec<String> = path.split('/').map(String::from).collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let (parts, body) = request.into_parts();
+    let request = Request::from_parts(parts, Body::empty());
+    let body: Value = hyper::body::to_bytes(body)
+        .await
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or(Value::Null);
+
+    let mut state = state.lock().unwrap();
+    let zone_exists = |state: &State, zone_id: &str| state.zones.iter().any(|(id, _)| id == zone_id);
+    match (&method, segments.as_slice()) {
+        (&Method::GET, ["user", "tokens", "verify"]) => envelope(
+            StatusCode::OK,
+            json!({ "id": "mock-token-id", "status": "active" }),
+            None,
+        ),
+        (&Method::GET, ["zones"]) => {
+            let name = query_param(&request, "name");
+            let items = state
+                .zones
+                .iter()
+                .filter(|(_, zone)| name.as_ref().map(|name| name == zone).unwrap_or(true))
+                .map(|(id, zone)| json!({ "id": id, "name": zone, "status": "active", "name_servers": NAME_SERVERS }))
+                .collect();
+            paginated(&request, items)
+        }
+        (&Method::GET, ["zones", zone_id, "dns_records"]) if zone_exists(&state, zone_id) => {
+            let name = query_param(&request, "name");
+            let items = state
+                .records
+                .iter()
+                .filter(|(zone, record)| {
+                    zone == zone_id && name.as_ref().map(|name| *name == record.name).unwrap_or(true)
+                })
+                .map(|(zone, record)| record_json(zone, record))
+                .collect();
+            paginated(&request, items)
+        }
+        (&Method::POST, ["zones", zone_id, "dns_records"]) if zone_exists(&state, zone_id) => {
+            let record = MockRecord {
+                id: state.next_id(),
+                name: body["name"].as_str().unwrap_or_default().to_string(),
+                type_: body["type"].as_str().unwrap_or_default().to_string(),
+                content: body["content"].as_str().unwrap_or_default().to_string(),
+                proxied: body["proxied"].as_bool().unwrap_or_default(),
+            };
+            state.records.push((zone_id.to_string(), record.clone()));
+            envelope(StatusCode::OK, record_json(zone_id, &record), None)
+        }
+        (&Method::PUT, ["zones", zone_id, "dns_records", record_id]) => {
+            let record = state
+                .records
+                .iter_mut()
+                .find(|(zone, record)| zone == zone_id && record.id == *record_id);
+            match record {
+                Some((_, record)) => {
+                    record.name = body["name"].as_str().unwrap_or_default().to_string();
+                    record.type_ = body["type"].as_str().unwrap_or_default().to_string();
+                    record.content = body["content"].as_str().unwrap_or_default().to_string();
+                    record.proxied = body["proxied"].as_bool().unwrap_or_default();
+                    envelope(StatusCode::OK, record_json(zone_id, record), None)
+                }
+                None => error_response(StatusCode::NOT_FOUND, 81044, "Record does not exist."),
+            }
+        }
+        (&Method::DELETE, ["zones", zone_id, "dns_records", record_id]) => {
+            let before = state.records.len();
+            state
+                .records
+                .retain(|(zone, record)| !(zone == zone_id && record.id == *record_id));
+            if state.records.len() < before {
+                envelope(StatusCode::OK, json!({ "id": record_id }), None)
+            } else {
+                error_response(StatusCode::NOT_FOUND, 81044, "Record does not exist.")
+            }
+        }
+        _ => error_response(StatusCode::NOT_FOUND, 7003, "Could not route to the requested path"),
+    }
+}
@@ -0,0 +1,199 @@
+//! In-memory authoritative DNS server accepting dynamic updates (RFC 2136) over TCP, the way BIND does with
+//! an `update-policy` for a TSIG key. Requests without a valid signature are refused when a key is set.
+
+use super::mock_resolver::Answer;
+use node_dns::tsig;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use trust_dns_resolver::proto::op::{Message, MessageType, OpCode, ResponseCode};
+use trust_dns_resolver::proto::rr::rdata::TXT;
+use trust_dns_resolver::proto::rr::{DNSClass, RData, Record, RecordType};
+use trust_dns_resolver::Name;
+
+pub const KEY_NAME: &str = "node-dns";
+
+/// `tsig-keygen -a hmac-sha256`
+pub const KEY_SECRET: &str = "RmQ8q5cS3ZlQfXUyJ8i8P2q3w0mGdC5k8Zr3Xl4xJ0o=";
+
+#[derive(Default)]
+struct State {
+    records: Vec<Record>,
+    requests: Vec<OpCode>,
+}
+
+pub struct MockDns {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockDns {
+    /// Serve the zones, accepting the updates signed with the key, or any updates without one.
+    pub async fn start(key: Option<tsig::Key>) -> MockDns {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State::default()));
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, server_state.clone(), key.clone()));
+            }
+        });
+        MockDns { addr, state }
+    }
+
+    pub fn key() -> tsig::Key {
+        tsig::Key::new(KEY_NAME, tsig::Algorithm::HmacSha256, KEY_SECRET).unwrap()
+    }
+
+    pub fn server(&self) -> String {
+        self.addr.to_string()
+    }
+
+    pub fn add_record(&self, name: &str, type_: &str, target: &str) {
+        let record = Record::from_rdata(Name::from_str(name).unwrap(), 300, rdata(type_, target));
+        self.state.lock().unwrap().records.push(record);
+    }
+
+    /// Targets of the records with the given name and type
+    pub fn records(&self, name: &str, type_: &str) -> Vec<String> {
+        let name = Name::from_str(name).unwrap();
+        let type_ = RecordType::from_str(type_).unwrap();
+        self.state
+            .lock()
+            .unwrap()
+            .records
+            .iter()
+            .filter(|r| r.name() == &name && r.rr_type() == type_)
+            .map(|r| match r.rdata() {
+                RData::TXT(txt) => txt
+                    .txt_data()
+                    .iter()
+                    .map(|data| String::from_utf8_lossy(data))
+                    .collect(),
+                rdata => rdata.to_string(),
+            })
+            .collect()
+    }
+
+    /// Name server answers for the records of all the zones, as seen over UDP by the resolvers
+    pub fn answer(&self) -> Answer {
+        let state = self.state.clone();
+        Arc::new(move |name, record_type| {
+            state
+                .lock()
+                .unwrap()
+                .records
+                .iter()
+                .filter(|r| r.name() == name && r.rr_type() == record_type)
+                .map(|r| r.rdata().clone())
+                .collect()
+        })
+    }
+
+    /// Operations of the requests served so far
+    pub fn requests(&self) -> Vec<OpCode> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+fn rdata(type_: &str, target: &str) -> RData {
+    match type_ {
+        "A" => RData::A(target.parse().unwrap()),
+        "AAAA" => RData::AAAA(target.parse().unwrap()),
+        "TXT" => RData::TXT(TXT::new(vec![target.to_string()])),
+        "PTR" => RData::PTR(Name::from_str(target).unwrap()),
+        other => panic!("Unsupported record type {}", other),
+    }
+}
+
+async fn serve(mut stream: TcpStream, state: Arc<Mutex<State>>, key: Option<tsig::Key>) {
+    loop {
+        let length = match stream.read_u16().await {
+            Ok(length) => length as usize,
+            Err(_) => return,
+        };
+        let mut request = vec![0; length];
+        if stream.read_exact(&mut request).await.is_err() {
+            return;
+        }
+        let response = respond(&request, &state, key.as_ref());
+        stream.write_all(&(response.len() as u16).to_be_bytes()).await.unwrap();
+        stream.write_all(&response).await.unwrap();
+    }
+}
+
+fn respond(request: &[u8], state: &Mutex<State>, key: Option<&tsig::Key>) -> Vec<u8> {
+    let (request, request_mac) = match key.map(|key| key.verify(request, None)) {
+        Some(Ok((unsigned, mac))) => (Message::from_vec(&unsigned).unwrap(), Some(mac)),
+        // A real server would answer with a TSIG error, without signing the response
+        Some(Err(_)) => {
+            let request = Message::from_vec(request).unwrap();
+            return response(&request, ResponseCode::NotAuth).to_vec().unwrap();
+        }
+        None => (Message::from_vec(request).unwrap(), None),
+    };
+
+    let mut state = state.lock().unwrap();
+    state.requests.push(request.op_code());
+    let message = match request.op_code() {
+        OpCode::Update => {
+            let zone = request.queries()[0].name().clone();
+            if request.name_servers().iter().any(|update| !zone.zone_of(update.name())) {
+                response(&request, ResponseCode::NotZone)
+            } else {
+                for update in request.name_servers() {
+                    apply(&mut state.records, update);
+                }
+                response(&request, ResponseCode::NoError)
+            }
+        }
+        _ => {
+            let query = &request.queries()[0];
+            let answers: Vec<Record> = state
+                .records
+                .iter()
+                .filter(|r| r.name() == query.name() && r.rr_type() == query.query_type())
+                .cloned()
+                .collect();
+            let mut message = response(&request, ResponseCode::NoError);
+            message.add_answers(answers);
+            message
+        }
+    };
+    let message = message.to_vec().unwrap();
+    match (key, request_mac) {
+        (Some(key), Some(request_mac)) => key.sign(&message, Some(&request_mac)).unwrap().0,
+        _ => message,
+    }
+}
+
+fn response(request: &Message, code: ResponseCode) -> Message {
+    let mut message = Message::new();
+    message
+        .set_id(request.id())
+        .set_message_type(MessageType::Response)
+        .set_op_code(request.op_code())
+        .set_response_code(code)
+        .add_queries(request.queries().to_vec());
+    message
+}
+
+/// Apply one record of the update section, RFC 2136 section 3.4.2
+fn apply(records: &mut Vec<Record>, update: &Record) {
+    match update.dns_class() {
+        DNSClass::ANY => records.retain(|r| !(r.name() == update.name() && r.rr_type() == update.rr_type())),
+        DNSClass::NONE => records
+            .retain(|r| !(r.name() == update.name() && r.rr_type() == update.rr_type() && r.rdata() == update.rdata())),
+        _ => {
+            if !records
+                .iter()
+                .any(|r| r.name() == update.name() && r.rdata() == update.rdata())
+            {
+                records.push(update.clone());
+            }
+        }
+    }
+}
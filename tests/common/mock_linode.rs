@@ -0,0 +1,485 @@
+//! In-memory stand-in for the parts of the Linode API this controller uses: domains, domain records and
+//! networking/ips. Supports pagination the same way Linode does, so small page sizes can be exercised.
+
+use super::mock_resolver::{self, address, relative_name, Answer};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use node_dns::dns::Resolvers;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use trust_dns_resolver::proto::rr::{RData, RecordType};
+use trust_dns_resolver::Name;
+
+pub const TOKEN: &str = "mock-token";
+
+/// Request quota advertised in the `X-RateLimit-*` headers
+pub const RATE_LIMIT: usize = 800;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockRecord {
+    pub id: u64,
+    pub name: String,
+    pub type_: String,
+    pub target: String,
+    pub ttl_sec: u64,
+    pub tag: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    domains: Vec<(u64, String)>,
+    records: HashMap<u64, Vec<MockRecord>>,
+    ips: Vec<(String, Option<String>)>,
+    requests: Vec<String>,
+    failures: Vec<(StatusCode, Option<u64>)>,
+    /// Tokens accepted on top of `TOKEN`
+    tokens: Vec<String>,
+    /// `X-OAuth-Scopes` of the token, all of them when not set
+    scopes: Option<String>,
+    /// Grants of the user, who is unrestricted when not set
+    grants: Option<Value>,
+    /// Type of the domains other than `master`
+    domain_types: HashMap<u64, String>,
+}
+
+impl State {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+pub struct MockLinode {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockLinode {
+    pub async fn start() -> MockLinode {
+        let state = Arc::new(Mutex::new(State::default()));
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let state = state.clone();
+                    async move { Ok::<_, Infallible>(handle(state, request).await) }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        MockLinode { addr, state }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}/v4/", self.addr)
+    }
+
+    pub fn client(&self) -> node_dns::linode::Client {
+        node_dns::linode::Client::new(TOKEN).with_base_url(&self.url())
+    }
+
+    pub fn provider(&self) -> node_dns::provider::LinodeProvider {
+        node_dns::provider::LinodeProvider::new(self.client())
+    }
+
+    /// Linode for both the forward records and the reverse DNS, the default configuration
+    pub fn providers(&self) -> node_dns::provider::Providers {
+        let provider = Arc::new(self.provider());
+        node_dns::provider::Providers {
+            forward: provider.clone(),
+            reverse: Some(provider),
+        }
+    }
+
+    pub fn add_domain(&self, domain: &str) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id();
+        state.domains.push((id, domain.to_string()));
+        state.records.insert(id, vec![]);
+        id
+    }
+
+    /// Domain whose records are transferred from another name server, so cannot be changed through the API
+    pub fn add_slave_domain(&self, domain: &str) -> u64 {
+        let id = self.add_domain(domain);
+        self.state.lock().unwrap().domain_types.insert(id, "slave".to_string());
+        id
+    }
+
+    pub fn add_record(&self, domain_id: u64, name: &str, type_: &str, target: &str) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id();
+        state.records.get_mut(&domain_id).unwrap().push(MockRecord {
+            id,
+            name: name.to_string(),
+            type_: type_.to_string(),
+            target: target.to_string(),
+            ttl_sec: 300,
+            tag: None,
+        });
+        id
+    }
+
+    /// Tag the record, as someone could in the Linode UI
+    pub fn tag_record(&self, domain_id: u64, record_id: u64, tag: &str) {
+        let mut state = self.state.lock().unwrap();
+        let records = state.records.get_mut(&domain_id).unwrap();
+        let record = records.iter_mut().find(|r| r.id == record_id).unwrap();
+        record.tag = Some(tag.to_string());
+    }
+
+    pub fn add_ip(&self, address: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.ips.push((address.to_string(), None));
+    }
+
+    pub fn records(&self, domain_id: u64) -> Vec<MockRecord> {
+        self.state.lock().unwrap().records[&domain_id].clone()
+    }
+
+    pub fn rdns(&self, address: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .ips
+            .iter()
+            .find(|(a, _)| a == address)
+            .and_then(|(_, rdns)| rdns.clone())
+    }
+
+    /// Fail the next request with the given status, and optionally a `Retry-After` header (in seconds).
+    /// Can be called several times to fail several requests in a row.
+    pub fn fail_next(&self, status: u16, retry_after: Option<u64>) {
+        let status = StatusCode::from_u16(status).unwrap();
+        self.state.lock().unwrap().failures.push((status, retry_after));
+    }
+
+    /// Accept another token, as if it had just been created
+    pub fn accept_token(&self, token: &str) {
+        self.state.lock().unwrap().tokens.push(token.to_string());
+    }
+
+    pub fn revoke_token(&self, token: &str) {
+        self.state.lock().unwrap().tokens.retain(|accepted| accepted != token);
+    }
+
+    /// Scopes of the tokens, e.g. `domains:read_only ips:read_write`
+    pub fn set_scopes(&self, scopes: &str) {
+        self.state.lock().unwrap().scopes = Some(scopes.to_string());
+    }
+
+    /// Make the user restricted, with the given grants, e.g. `{"domain": [{"id": 1, "permissions": "read_write"}]}`
+    pub fn restrict(&self, grants: Value) {
+        self.state.lock().unwrap().grants = Some(grants);
+    }
+
+    /// Requests served so far, as "METHOD path" strings.
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Name server answers for the records of the domain
+    pub fn forward_answer(&self, domain: &str) -> Answer {
+        let state = self.state.clone();
+        let domain = domain.to_string();
+        Arc::new(move |name, record_type| {
+            let state = state.lock().unwrap();
+            let (name, domain_id) = match (
+                relative_name(name, &domain),
+                state.domains.iter().find(|(_, d)| *d == domain),
+            ) {
+                (Some(name), Some((domain_id, _))) => (name, *domain_id),
+                _ => return vec![],
+            };
+            state.records[&domain_id]
+                .iter()
+                .filter(|r| r.name == name && r.type_ == record_type.to_string())
+                .filter_map(|r| address(record_type, &r.target))
+                .collect()
+        })
+    }
+
+    /// Name server answers for the reverse DNS of the IP addresses
+    pub fn reverse_answer(&self) -> Answer {
+        let state = self.state.clone();
+        Arc::new(move |name, record_type| {
+            let state = state.lock().unwrap();
+            if record_type != RecordType::PTR {
+                return vec![];
+            }
+            state
+                .ips
+                .iter()
+                .filter(|(address, _)| Name::from(address.parse::<IpAddr>().unwrap()) == *name)
+                .filter_map(|(_, rdns)| rdns.as_ref())
+                .map(|rdns| RData::PTR(Name::from_str(&format!("{}.", rdns)).unwrap()))
+                .collect()
+        })
+    }
+
+    /// Resolvers seeing the changes made through the API right away
+    pub async fn resolvers(&self, domain: &str) -> Resolvers {
+        mock_resolver::resolvers(self.forward_answer(domain), self.reverse_answer()).await
+    }
+}
+
+fn json_response(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn error_response(status: StatusCode, reason: &str) -> Response<Body> {
+    json_response(status, json!({ "errors": [{ "reason": reason }] }))
+}
+
+fn record_json(record: &MockRecord) -> Value {
+    json!({
+        "created": "2022-01-01T00:00:00",
+        "id": record.id,
+        "name": record.name,
+        "port": 0,
+        "priority": 0,
+        "protocol": null,
+        "service": null,
+        "tag": record.tag,
+        "target": record.target,
+        "ttl_sec": record.ttl_sec,
+        "type": record.type_,
+        "updated": "2022-01-01T00:00:00",
+        "weight": 0,
+    })
+}
+
+fn domain_json(id: u64, domain: &str, type_: &str) -> Value {
+    json!({
+        "axfr_ips": [],
+        "description": null,
+        "domain": domain,
+        "expire_sec": 0,
+        "group": null,
+        "id": id,
+        "master_ips": [],
+        "refresh_sec": 0,
+        "retry_sec": 0,
+        "soa_email": "admin@example.com",
+        "status": "active",
+        "tags": [],
+        "ttl_sec": 0,
+        "type": type_,
+    })
+}
+
+fn ip_json(address: &str, rdns: &Option<String>) -> Value {
+    json!({
+        "address": address,
+        "gateway": "192.0.2.1",
+        "linode_id": 1,
+        "prefix": 24,
+        "public": true,
+        "rdns": rdns,
+        "region": "us-east",
+        "subnet_mask": "255.255.255.0",
+        "type": if address.contains(':') { "ipv6" } else { "ipv4" },
+    })
+}
+
+fn query_param(request: &Request<Body>, name: &str) -> Option<u64> {
+    request.uri().query()?.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        if key == name {
+            value.parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+/// Keep the items whose fields equal all the values given in the `X-Filter` header
+fn filtered(request: &Request<Body>, items: Vec<Value>) -> Vec<Value> {
+    let filter: Value = request
+        .headers()
+        .get("X-Filter")
+        .and_then(|value| serde_json::from_slice(value.as_bytes()).ok())
+        .unwrap_or(Value::Null);
+    match filter.as_object() {
+        Some(conditions) => items
+            .into_iter()
+            .filter(|item| conditions.iter().all(|(key, value)| item[key] == *value))
+            .collect(),
+        None => items,
+    }
+}
+
+// `div_ceil` needs a newer Rust than the image is built with
+#[allow(clippy::manual_div_ceil)]
+fn paginated(request: &Request<Body>, items: Vec<Value>) -> Response<Body> {
+    let items = filtered(request, items);
+    let page = query_param(request, "page").unwrap_or(1).max(1);
+    let page_size = query_param(request, "page_size").unwrap_or(100) as usize;
+    let results = items.len();
+    let pages = ((results + page_size - 1) / page_size).max(1);
+    let data: Vec<Value> = items
+        .into_iter()
+        .skip((page as usize - 1) * page_size)
+        .take(page_size)
+        .collect();
+    json_response(
+        StatusCode::OK,
+        json!({ "data": data, "page": page, "pages": pages, "results": results }),
+    )
+}
+
+async fn handle(state: Arc<Mutex<State>>, request: Request<Body>) -> Response<Body> {
+    let mut response = route(state.clone(), request).await;
+    let (remaining, scopes) = {
+        let state = state.lock().unwrap();
+        let remaining = RATE_LIMIT.saturating_sub(state.requests.len());
+        (remaining, state.scopes.clone().unwrap_or_else(|| "*".to_string()))
+    };
+    let headers = response.headers_mut();
+    headers.insert("X-OAuth-Scopes", scopes.parse().unwrap());
+    headers.insert("X-RateLimit-Limit", RATE_LIMIT.into());
+    headers.insert("X-RateLimit-Remaining", remaining.into());
+    headers.insert("X-RateLimit-Reset", 0.into());
+    response
+}
+
+async fn route(state: Arc<Mutex<State>>, request: Request<Body>) -> Response<Body> {
+    let method = request.method().clone();
+    let path = request.uri().path().trim_start_matches("/v4/").to_string();
+    let failure = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(format!("{} {}", method, path));
+        (!state.failures.is_empty()).then(|| state.failures.remove(0))
+    };
+    if let Some((status, retry_after)) = failure {
+        let mut response = error_response(status, "Injected failure");
+        if let Some(retry_after) = retry_after {
+            response.headers_mut().insert("Retry-After", retry_after.into());
+        }
+        return response;
+    }
+
+    let authorized = request
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token == TOKEN || state.lock().unwrap().tokens.iter().any(|accepted| accepted == token))
+        .unwrap_or(false);
+    if !authorized {
+        return error_response(StatusCode::UNAUTHORIZED, "Invalid Token");
+    }
+
+    let segments: Vec<&str> = path.split('/').collect();
+    let (parts, body) = request.into_parts();
+    let request = Request::from_parts(parts, Body::empty());
+    let body: Value = hyper::body::to_bytes(body)
+        .await
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or(Value::Null);
+
+    let mut state = state.lock().unwrap();
+    match (&method, segments.as_slice()) {
+        (&Method::GET, ["profile"]) => json_response(
+            StatusCode::OK,
+            json!({ "username": "node-dns", "restricted": state.grants.is_some() }),
+        ),
+        (&Method::GET, ["profile", "grants"]) => match &state.grants {
+            Some(grants) => json_response(StatusCode::OK, grants.clone()),
+            None => Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .body(Body::empty())
+                .unwrap(),
+        },
+        (&Method::GET, ["domains"]) => {
+            let items = state
+                .domains
+                .iter()
+                .map(|(id, domain)| {
+                    let type_ = state.domain_types.get(id).map(String::as_str).unwrap_or("master");
+                    domain_json(*id, domain, type_)
+                })
+                .collect();
+            paginated(&request, items)
+        }
+        (&Method::GET, ["domains", domain_id, "records"]) => {
+            match domain_id.parse().ok().and_then(|id: u64| state.records.get(&id)) {
+                Some(records) => paginated(&request, records.iter().map(record_json).collect()),
+                None => error_response(StatusCode::NOT_FOUND, "Not found"),
+            }
+        }
+        (&Method::POST, ["domains", domain_id, "records"]) => {
+            let id = state.next_id();
+            let record = MockRecord {
+                id,
+                name: body["name"].as_str().unwrap_or_default().to_string(),
+                type_: body["type"].as_str().unwrap_or_default().to_string(),
+                target: body["target"].as_str().unwrap_or_default().to_string(),
+                ttl_sec: body["ttl_sec"].as_u64().unwrap_or_default(),
+                tag: body["tag"].as_str().map(String::from),
+            };
+            match domain_id.parse().ok().and_then(|id: u64| state.records.get_mut(&id)) {
+                Some(records) => {
+                    records.push(record.clone());
+                    json_response(StatusCode::OK, record_json(&record))
+                }
+                None => error_response(StatusCode::NOT_FOUND, "Not found"),
+            }
+        }
+        (&Method::PUT, ["domains", domain_id, "records", record_id]) => {
+            let record = domain_id
+                .parse()
+                .ok()
+                .and_then(|id: u64| state.records.get_mut(&id))
+                .and_then(|records| records.iter_mut().find(|r| r.id.to_string() == *record_id));
+            match record {
+                Some(record) => {
+                    record.name = body["name"].as_str().unwrap_or_default().to_string();
+                    record.target = body["target"].as_str().unwrap_or_default().to_string();
+                    record.ttl_sec = body["ttl_sec"].as_u64().unwrap_or_default();
+                    record.tag = body["tag"].as_str().map(String::from);
+                    json_response(StatusCode::OK, record_json(record))
+                }
+                None => error_response(StatusCode::NOT_FOUND, "Not found"),
+            }
+        }
+        (&Method::DELETE, ["domains", domain_id, "records", record_id]) => {
+            let records = domain_id.parse().ok().and_then(|id: u64| state.records.get_mut(&id));
+            match records {
+                Some(records) if records.iter().any(|r| r.id.to_string() == *record_id) => {
+                    records.retain(|r| r.id.to_string() != *record_id);
+                    json_response(StatusCode::OK, json!({}))
+                }
+                _ => error_response(StatusCode::NOT_FOUND, "Not found"),
+            }
+        }
+        (&Method::GET, ["networking", "ips"]) => {
+            let items = state.ips.iter().map(|(address, rdns)| ip_json(address, rdns)).collect();
+            paginated(&request, items)
+        }
+        (&Method::GET, ["networking", "ips", address]) => match state.ips.iter().find(|(a, _)| a == address) {
+            Some((address, rdns)) => json_response(StatusCode::OK, ip_json(address, rdns)),
+            None => error_response(StatusCode::NOT_FOUND, "Not found"),
+        },
+        (&Method::PUT, ["networking", "ips", address]) => match state.ips.iter_mut().find(|(a, _)| a == address) {
+            Some(ip) => {
+                ip.1 = body["rdns"].as_str().map(String::from);
+                json_response(StatusCode::OK, ip_json(&ip.0, &ip.1))
+            }
+            None => error_response(StatusCode::NOT_FOUND, "Not found"),
+        },
+        _ => error_response(StatusCode::NOT_FOUND, "Not found"),
+    }
+}
@@ -0,0 +1,88 @@
+//! Authoritative name server answering over UDP from the state of a mock provider, so that the changes made by
+//! the code under test are visible right away, as if they propagated instantly.
+
+use node_dns::dns::Resolvers;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use trust_dns_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use trust_dns_resolver::proto::op::{Message, MessageType, ResponseCode};
+use trust_dns_resolver::proto::rr::{RData, Record, RecordType};
+use trust_dns_resolver::{Name, TokioAsyncResolver};
+
+/// The records with the given name and type, according to the mock
+pub type Answer = Arc<dyn Fn(&Name, RecordType) -> Vec<RData> + Send + Sync>;
+
+async fn serve(answer: Answer) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buffer = vec![0; 4096];
+        while let Ok((length, peer)) = socket.recv_from(&mut buffer).await {
+            let request = match Message::from_vec(&buffer[..length]) {
+                Ok(request) => request,
+                Err(_) => continue,
+            };
+            let mut response = Message::new();
+            response
+                .set_id(request.id())
+                .set_message_type(MessageType::Response)
+                .set_op_code(request.op_code())
+                .set_authoritative(true)
+                .set_response_code(ResponseCode::NoError)
+                .add_queries(request.queries().to_vec());
+            for query in request.queries() {
+                for rdata in answer(query.name(), query.query_type()) {
+                    response.add_answer(Record::from_rdata(query.name().clone(), 0, rdata));
+                }
+            }
+            let _ = socket.send_to(&response.to_vec().unwrap(), peer).await;
+        }
+    });
+    addr
+}
+
+fn resolver(addr: SocketAddr) -> TokioAsyncResolver {
+    let config = ResolverConfig::from_parts(
+        None,
+        vec![],
+        NameServerConfigGroup::from_ips_clear(&[IpAddr::V4(Ipv4Addr::LOCALHOST)], addr.port(), true),
+    );
+    let opts = ResolverOpts {
+        positive_max_ttl: Some(Duration::ZERO),
+        negative_max_ttl: Some(Duration::ZERO),
+        ..ResolverOpts::default()
+    };
+    TokioAsyncResolver::tokio(config, opts).unwrap()
+}
+
+/// Resolvers answering the forward and reverse lookups from the given mocks
+pub async fn resolvers(forward: Answer, reverse: Answer) -> Resolvers {
+    Resolvers {
+        forward: resolver(serve(forward).await),
+        reverse: resolver(serve(reverse).await),
+    }
+}
+
+/// Resolver answering all the lookups from the given mock
+pub async fn answering(answer: Answer) -> TokioAsyncResolver {
+    resolver(serve(answer).await)
+}
+
+/// Name relative to the domain, e.g. `node-1` for `node-1.k8s.example.com.`
+pub fn relative_name(name: &Name, domain: &str) -> Option<String> {
+    let name = name.to_string();
+    name.trim_end_matches('.')
+        .strip_suffix(&format!(".{}", domain))
+        .map(String::from)
+}
+
+/// Answer of an A or AAAA query for one of the targets
+pub fn address(record_type: RecordType, target: &str) -> Option<RData> {
+    match (record_type, target.parse::<IpAddr>().ok()?) {
+        (RecordType::A, IpAddr::V4(ip)) => Some(RData::A(ip)),
+        (RecordType::AAAA, IpAddr::V6(ip)) => Some(RData::AAAA(ip)),
+        _ => None,
+    }
+}
@@ -0,0 +1,32 @@
+#![allow(dead_code)]
+
+pub mod mock_cloudflare;
+pub mod mock_dns;
+pub mod mock_linode;
+pub mod mock_resolver;
+
+use node_dns::dns::Resolvers;
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use trust_dns_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use trust_dns_resolver::TokioAsyncResolver;
+
+/// Resolvers pointed at a local socket that never answers: every lookup times out, so the code under test
+/// always believes the records are missing. Meant for tests running with a paused clock, where the timeouts
+/// elapse instantly.
+pub fn silent_resolvers() -> (Resolvers, UdpSocket) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let port = socket.local_addr().unwrap().port();
+    let resolver = || {
+        let config = ResolverConfig::from_parts(
+            None,
+            vec![],
+            NameServerConfigGroup::from_ips_clear(&[IpAddr::V4(Ipv4Addr::LOCALHOST)], port, true),
+        );
+        TokioAsyncResolver::tokio(config, ResolverOpts::default()).unwrap()
+    };
+    let resolvers = Resolvers {
+        forward: resolver(),
+        reverse: resolver(),
+    };
+    (resolvers, socket)
+}
@@ -0,0 +1,226 @@
+use node_dns::config::{self, Command, Config, ConfigError};
+use std::collections::HashMap;
+use std::time::Duration;
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+fn load(arguments: &[&str], env: &[(&str, &str)]) -> Result<Command, ConfigError> {
+    let env: HashMap<String, String> = env
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    Config::load(&args(arguments), |name| env.get(name).cloned())
+}
+
+fn run(arguments: &[&str], env: &[(&str, &str)]) -> Config {
+    match load(arguments, env).unwrap() {
+        Command::Run(config) => config,
+        other => panic!("Expected to run, got {:?}", other),
+    }
+}
+
+/// Written to the temporary directory, under a name unique to the test
+fn config_file(name: &str, content: &str) -> String {
+    let path = std::env::temp_dir().join(format!("node-dns-{}-{}.yaml", name, std::process::id()));
+    std::fs::write(&path, content).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn defaults_with_the_required_settings() {
+    let config = run(
+        &[],
+        &[("NODE_DOMAIN", "k8s.example.com"), ("LINODE_API_TOKEN", "token")],
+    );
+
+    assert_eq!(config.node_domain, "k8s.example.com");
+    assert_eq!(config.record_ttl, 300);
+    assert_eq!(config.propagation_timeout, config::DEFAULT_PROPAGATION_TIMEOUT);
+    assert_eq!(config.finalizer, config::DEFAULT_FINALIZER);
+    assert_eq!(config.reverse_provider(), "linode");
+    assert_eq!(config.linode.name_servers.len(), 5);
+    assert_eq!(config.http_listen_address.to_string(), "0.0.0.0:8080");
+}
+
+#[test]
+fn flags_override_the_environment_which_overrides_the_file() {
+    let path = config_file(
+        "precedence",
+        r#"
+nodeDomain: file.example.com
+recordTtl: 3600
+retryInterval: 60
+finalizer: example.com/from-file
+linode:
+  apiToken: file-token
+  nameServers: [ns1.example.com]
+"#,
+    );
+
+    let config = run(
+        &[
+            "--config",
+            &path,
+            "--dns-record-ttl",
+            "120",
+            "--node-domain=flag.example.com",
+        ],
+        &[("NODE_DOMAIN", "env.example.com"), ("DNS_RECORD_TTL", "600")],
+    );
+
+    assert_eq!(config.node_domain, "flag.example.com");
+    assert_eq!(config.record_ttl, 120);
+    assert_eq!(config.retry_interval, Duration::from_secs(60));
+    assert_eq!(config.finalizer, "example.com/from-file");
+    assert_eq!(config.linode.api_token.as_deref(), Some("file-token"));
+    assert_eq!(config.linode.name_servers, vec!["ns1.example.com"]);
+
+    let config = run(
+        &[],
+        &[
+            ("CONFIG_FILE", &path),
+            ("LINODE_NAME_SERVERS", "ns1.example.net, ns2.example.net"),
+        ],
+    );
+    assert_eq!(config.node_domain, "file.example.com");
+    assert_eq!(config.linode.name_servers, vec!["ns1.example.net", "ns2.example.net"]);
+}
+
+#[test]
+fn reports_all_the_problems_at_once() {
+    let error = load(
+        &[
+            "--dns-propagation-poll-interval",
+            "60",
+            "--dns-propagation-timeout",
+            "30",
+        ],
+        &[("FINALIZER", "unqualified"), ("DNS_NAME_TEMPLATE", "{unknown}")],
+    )
+    .unwrap_err();
+
+    match error {
+        ConfigError::Invalid(problems) => {
+            assert!(problems.iter().any(|problem| problem.contains("NODE_DOMAIN")));
+            assert!(problems.iter().any(|problem| problem.contains("DNS_NAME_TEMPLATE")));
+            assert!(problems.iter().any(|problem| problem.contains("LINODE_API_TOKEN")));
+            assert!(problems
+                .iter()
+                .any(|problem| problem.contains("DNS_PROPAGATION_TIMEOUT")));
+            assert!(problems.iter().any(|problem| problem.contains("FINALIZER")));
+            assert_eq!(problems.len(), 5);
+        }
+        other => panic!("Expected invalid configuration, got {:?}", other),
+    }
+}
+
+#[test]
+fn rejects_unknown_or_malformed_settings() {
+    let required = [("NODE_DOMAIN", "k8s.example.com"), ("LINODE_API_TOKEN", "token")];
+
+    assert!(matches!(
+        load(&["--unknown", "value"], &required),
+        Err(ConfigError::UnknownOption(option)) if option == "--unknown"
+    ));
+    assert!(matches!(
+        load(&["--dns-record-ttl"], &required),
+        Err(ConfigError::MissingValue(_))
+    ));
+    assert!(matches!(
+        load(&[], &[("DNS_RECORD_TTL", "five minutes"), required[0], required[1]]),
+        Err(ConfigError::InvalidValue(name, ..)) if name == "DNS_RECORD_TTL"
+    ));
+
+    let path = config_file("unknown-field", "nodeDomain: k8s.example.com\nrecordTTL: 300\n");
+    assert!(matches!(
+        load(&["--config", &path], &required),
+        Err(ConfigError::ParseFile(..))
+    ));
+}
+
+#[test]
+fn prints_the_configuration_without_the_secrets() {
+    let config = match load(
+        &[
+            "--print-config",
+            "--dns-provider",
+            "cloudflare",
+            "--rdns-provider",
+            "none",
+        ],
+        &[
+            ("NODE_DOMAIN", "k8s.example.com"),
+            ("CLOUDFLARE_API_TOKEN", "secret-token"),
+        ],
+    )
+    .unwrap()
+    {
+        Command::PrintConfig(config) => config,
+        other => panic!("Expected to print the configuration, got {:?}", other),
+    };
+
+    let yaml = config.to_yaml();
+    assert!(!yaml.contains("secret-token"));
+    assert!(yaml.contains("<redacted>"));
+    let mut printed: Config = serde_yaml::from_str(&yaml).unwrap();
+    printed.cloudflare.api_token = config.cloudflare.api_token.clone();
+    assert_eq!(printed, config);
+
+    assert!(matches!(load(&["--help"], &[]), Ok(Command::Help)));
+    assert!(config::usage().contains("--dns-record-ttl <VALUE>"));
+}
+
+#[test]
+fn reads_the_linode_token_from_a_file() {
+    let path = config_file("token", "file-token\n");
+
+    let config = run(
+        &[],
+        &[("NODE_DOMAIN", "k8s.example.com"), ("LINODE_API_TOKEN_FILE", &path)],
+    );
+    assert_eq!(config.linode.api_token_file.as_deref(), Some(path.as_str()));
+
+    let error = load(
+        &[],
+        &[
+            ("NODE_DOMAIN", "k8s.example.com"),
+            ("LINODE_API_TOKEN", "token"),
+            ("LINODE_API_TOKEN_FILE", &path),
+        ],
+    )
+    .unwrap_err();
+    assert!(matches!(error, ConfigError::Invalid(problems) if problems[0].contains("Only one of")));
+
+    let error = load(
+        &[],
+        &[
+            ("NODE_DOMAIN", "k8s.example.com"),
+            ("LINODE_API_TOKEN_FILE", "/nonexistent/token"),
+        ],
+    )
+    .unwrap_err();
+    assert!(matches!(error, ConfigError::Invalid(problems) if problems[0].contains("/nonexistent/token")));
+}
+
+#[test]
+fn requires_resync_to_be_disabled_with_rfc2136() {
+    let env = [
+        ("NODE_DOMAIN", "k8s.example.com"),
+        ("DNS_PROVIDER", "rfc2136"),
+        ("RFC2136_SERVER", "192.0.2.53"),
+        ("RDNS_PROVIDER", "none"),
+    ];
+
+    match load(&[], &env).unwrap_err() {
+        ConfigError::Invalid(problems) => assert_eq!(
+            problems,
+            vec!["DNS_RESYNC_INTERVAL must be 0 with DNS_PROVIDER=rfc2136, which cannot list a zone"]
+        ),
+        other => panic!("Expected invalid configuration, got {:?}", other),
+    }
+
+    let config = run(&["--dns-resync-interval", "0"], &env);
+    assert!(config.resync_interval.is_zero());
+}
@@ -0,0 +1,56 @@
+use k8s_openapi::api::core::v1::Node;
+use kube::api::ObjectMeta;
+use node_dns::controller::release_patch;
+use node_dns::reporting::{FQDN_ANNOTATION, STATUS_ANNOTATION};
+use node_dns::state::STATE_ANNOTATION;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+const FINALIZER: &str = "k8s.haim.dev/linode-dns-finalizer";
+
+fn node(annotations: &[(&str, &str)]) -> Node {
+    Node {
+        metadata: ObjectMeta {
+            name: Some("node-1".to_string()),
+            resource_version: Some("42".to_string()),
+            finalizers: Some(vec!["example.com/other".to_string(), FINALIZER.to_string()]),
+            annotations: Some(
+                annotations
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect::<BTreeMap<String, String>>(),
+            ),
+            ..ObjectMeta::default()
+        },
+        ..Node::default()
+    }
+}
+
+#[test]
+fn releases_a_node_in_a_single_patch() {
+    let node = node(&[
+        (FQDN_ANNOTATION, "node-1.k8s.example.com"),
+        (STATUS_ANNOTATION, "Verified"),
+        (STATE_ANNOTATION, "{}"),
+        ("other", "value"),
+    ]);
+
+    let patch = release_patch(&node, FINALIZER);
+
+    assert_eq!(patch["metadata"]["finalizers"], json!(["example.com/other"]));
+    assert_eq!(patch["metadata"]["resourceVersion"], json!("42"));
+    let annotations = patch["metadata"]["annotations"].as_object().unwrap();
+    assert_eq!(annotations[FQDN_ANNOTATION], json!(null));
+    assert_eq!(annotations[STATE_ANNOTATION], json!(null));
+    assert!(!annotations.contains_key("other"));
+}
+
+#[test]
+fn releases_a_node_without_annotations() {
+    let patch = release_patch(&node(&[]), FINALIZER);
+
+    assert_eq!(
+        patch,
+        json!({ "metadata": { "finalizers": ["example.com/other"], "resourceVersion": "42" } })
+    );
+}
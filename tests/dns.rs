@@ -0,0 +1,667 @@
+mod common;
+
+use common::mock_linode::{MockLinode, MockRecord};
+use common::silent_resolvers;
+use node_dns::dns::{self, Change, InternalDomain, InternalRecords, Propagation};
+use node_dns::provider::DnsProvider;
+use node_dns::registry::{ForeignRecordsError, Ownership, Registry, DEFAULT_OWNER_ID};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+const DOMAIN: &str = "k8s.example.com";
+
+fn find<'a>(records: &'a [MockRecord], name: &str, type_: &str) -> Option<&'a MockRecord> {
+    records.iter().find(|r| r.name == name && r.type_ == type_)
+}
+
+/// Add the ownership record of the name, as if the controller created its records
+fn claim(mock: &MockLinode, domain_id: u64, name: &str) {
+    let owner = format!("heritage=node-dns,node-dns/owner={}", DEFAULT_OWNER_ID);
+    mock.add_record(domain_id, &format!("_node-dns.{}", name), "TXT", &owner);
+}
+
+#[tokio::test]
+async fn update_creates_forward_spf_and_reverse_records() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.add_ip("192.0.2.10");
+    let resolvers = mock.resolvers(DOMAIN).await;
+    let ip: IpAddr = "192.0.2.10".parse().unwrap();
+
+    let outcome = dns::update(
+        &mock.providers(),
+        &Registry::default(),
+        &resolvers,
+        DOMAIN,
+        "node-1",
+        &[ip],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(outcome.propagation, Propagation::Verified);
+    assert_eq!(
+        outcome.changes,
+        vec![
+            Change::Created {
+                fqdn: "node-1.k8s.example.com".to_string(),
+                ip_address: ip
+            },
+            Change::Created {
+                fqdn: "192.0.2.10._spf.k8s.example.com".to_string(),
+                ip_address: ip
+            },
+            Change::ReverseUpdated {
+                ip_address: ip,
+                fqdn: "node-1.k8s.example.com".to_string()
+            },
+        ]
+    );
+    let records = mock.records(domain_id);
+    assert_eq!(find(&records, "node-1", "A").unwrap().target, "192.0.2.10");
+    assert_eq!(find(&records, "192.0.2.10._spf", "A").unwrap().target, "192.0.2.10");
+    assert_eq!(mock.rdns("192.0.2.10"), Some("node-1.k8s.example.com".to_string()));
+}
+
+#[tokio::test]
+async fn update_replaces_stale_forward_record() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    let record_id = mock.add_record(domain_id, "node-1", "A", "192.0.2.99");
+    claim(&mock, domain_id, "node-1");
+    mock.add_ip("192.0.2.10");
+    let resolvers = mock.resolvers(DOMAIN).await;
+    let ip: IpAddr = "192.0.2.10".parse().unwrap();
+
+    let outcome = dns::update(
+        &mock.providers(),
+        &Registry::default(),
+        &resolvers,
+        DOMAIN,
+        "node-1",
+        &[ip],
+    )
+    .await
+    .unwrap();
+
+    assert!(outcome.changes.contains(&Change::Updated {
+        fqdn: "node-1.k8s.example.com".to_string(),
+        ip_address: ip
+    }));
+    let records = mock.records(domain_id);
+    let record = find(&records, "node-1", "A").unwrap();
+    assert_eq!(record.id, record_id);
+    assert_eq!(record.target, "192.0.2.10");
+    assert_eq!(records.iter().filter(|r| r.name == "node-1").count(), 1);
+}
+
+#[tokio::test]
+async fn update_of_visible_records_makes_no_api_calls() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.add_record(domain_id, "node-1", "A", "192.0.2.10");
+    mock.add_record(domain_id, "192.0.2.10._spf", "A", "192.0.2.10");
+    mock.add_ip("192.0.2.10");
+    let resolvers = mock.resolvers(DOMAIN).await;
+    let providers = mock.providers();
+    let ip: IpAddr = "192.0.2.10".parse().unwrap();
+    dns::update(&providers, &Registry::default(), &resolvers, DOMAIN, "node-1", &[ip])
+        .await
+        .unwrap();
+    let requests = mock.requests().len();
+
+    let outcome = dns::update(&providers, &Registry::default(), &resolvers, DOMAIN, "node-1", &[ip])
+        .await
+        .unwrap();
+
+    assert_eq!(outcome.propagation, Propagation::Verified);
+    assert!(outcome.changes.is_empty());
+    assert_eq!(mock.requests().len(), requests);
+}
+
+#[tokio::test(start_paused = true)]
+async fn update_is_pending_until_records_are_visible() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.add_ip("192.0.2.10");
+    let (resolvers, _socket) = silent_resolvers();
+    let providers = mock.providers();
+    let ip: IpAddr = "192.0.2.10".parse().unwrap();
+
+    let outcome = dns::update(&providers, &Registry::default(), &resolvers, DOMAIN, "node-1", &[ip])
+        .await
+        .unwrap();
+
+    assert_eq!(outcome.propagation, Propagation::Pending);
+    let records = mock.records(domain_id);
+    assert!(find(&records, "node-1", "A").is_some());
+    assert!(find(&records, "192.0.2.10._spf", "A").is_some());
+    // The reverse DNS waits for the forward record to resolve
+    assert_eq!(mock.rdns("192.0.2.10"), None);
+
+    // Checking again does not duplicate anything
+    let outcome = dns::update(&providers, &Registry::default(), &resolvers, DOMAIN, "node-1", &[ip])
+        .await
+        .unwrap();
+    assert_eq!(outcome.propagation, Propagation::Pending);
+    // The two address records and their ownership records
+    assert_eq!(mock.records(domain_id).len(), 4);
+}
+
+#[tokio::test]
+async fn delete_removes_forward_and_spf_records() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.add_record(domain_id, "node-1", "A", "192.0.2.10");
+    mock.add_record(domain_id, "192.0.2.10._spf", "A", "192.0.2.10");
+    claim(&mock, domain_id, "node-1");
+    claim(&mock, domain_id, "192.0.2.10._spf");
+    mock.add_record(domain_id, "node-2", "A", "192.0.2.20");
+    let ip: IpAddr = "192.0.2.10".parse().unwrap();
+
+    dns::delete(&mock.provider(), &Registry::default(), DOMAIN, "node-1", &[ip])
+        .await
+        .unwrap();
+
+    let records = mock.records(domain_id);
+    assert_eq!(records.len(), 1);
+    assert!(find(&records, "node-2", "A").is_some());
+}
+
+#[tokio::test]
+async fn reuses_cached_domain_and_records() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.add_ip("192.0.2.10");
+    mock.add_ip("192.0.2.20");
+    let resolvers = mock.resolvers(DOMAIN).await;
+    let providers = mock.providers();
+
+    dns::update(
+        &providers,
+        &Registry::default(),
+        &resolvers,
+        DOMAIN,
+        "node-1",
+        &["192.0.2.10".parse().unwrap()],
+    )
+    .await
+    .unwrap();
+    dns::update(
+        &providers,
+        &Registry::default(),
+        &resolvers,
+        DOMAIN,
+        "node-2",
+        &["192.0.2.20".parse().unwrap()],
+    )
+    .await
+    .unwrap();
+    dns::delete(
+        providers.forward.as_ref(),
+        &Registry::default(),
+        DOMAIN,
+        "node-1",
+        &["192.0.2.10".parse().unwrap()],
+    )
+    .await
+    .unwrap();
+
+    let requests = mock.requests();
+    assert_eq!(requests.iter().filter(|r| *r == "GET domains").count(), 1);
+    // One filtered lookup per name: node-1, node-2, their SPF glue records and the ownership record of each, none
+    // repeated for the delete
+    let records_path = format!("GET domains/{}/records", domain_id);
+    assert_eq!(requests.iter().filter(|r| **r == records_path).count(), 8);
+    let records = mock.records(domain_id);
+    assert_eq!(records.len(), 4);
+    assert!(find(&records, "node-2", "A").is_some());
+}
+
+#[tokio::test]
+async fn update_claims_the_names_it_creates() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.add_ip("192.0.2.10");
+    let resolvers = mock.resolvers(DOMAIN).await;
+    let registry = Registry::new("cluster-a");
+
+    dns::update(
+        &mock.providers(),
+        &registry,
+        &resolvers,
+        DOMAIN,
+        "node-1",
+        &["192.0.2.10".parse().unwrap()],
+    )
+    .await
+    .unwrap();
+
+    let records = mock.records(domain_id);
+    let owner = "heritage=node-dns,node-dns/owner=cluster-a";
+    assert_eq!(find(&records, "_node-dns.node-1", "TXT").unwrap().target, owner);
+    assert_eq!(
+        find(&records, "_node-dns.192.0.2.10._spf", "TXT").unwrap().target,
+        owner
+    );
+}
+
+#[tokio::test]
+async fn update_adopts_unclaimed_records_pointing_to_the_node() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    // Published by a version without ownership records
+    mock.add_record(domain_id, "node-1", "A", "192.0.2.10");
+    mock.add_record(domain_id, "192.0.2.10._spf", "A", "192.0.2.10");
+    mock.add_ip("192.0.2.10");
+    let resolvers = mock.resolvers(DOMAIN).await;
+    let providers = mock.providers();
+    let registry = Registry::default();
+    let ip: IpAddr = "192.0.2.10".parse().unwrap();
+
+    let outcome = dns::update(&providers, &registry, &resolvers, DOMAIN, "node-1", &[ip])
+        .await
+        .unwrap();
+
+    assert!(outcome.changes.contains(&Change::Adopted {
+        fqdn: "node-1.k8s.example.com".to_string(),
+        ip_address: ip
+    }));
+    let records = mock.records(domain_id);
+    assert!(find(&records, "_node-dns.node-1", "TXT").is_some());
+    assert!(find(&records, "_node-dns.192.0.2.10._spf", "TXT").is_some());
+
+    // Adopted records are deleted with the node
+    dns::delete(&mock.provider(), &registry, DOMAIN, "node-1", &[ip])
+        .await
+        .unwrap();
+    assert!(mock.records(domain_id).is_empty());
+}
+
+#[tokio::test]
+async fn update_refuses_to_replace_records_it_does_not_own() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.add_record(domain_id, "node-1", "A", "192.0.2.99");
+    mock.add_record(
+        domain_id,
+        "_node-dns.node-1",
+        "TXT",
+        "heritage=node-dns,node-dns/owner=cluster-b",
+    );
+    mock.add_ip("192.0.2.10");
+    let resolvers = mock.resolvers(DOMAIN).await;
+
+    let error = dns::update(
+        &mock.providers(),
+        &Registry::new("cluster-a"),
+        &resolvers,
+        DOMAIN,
+        "node-1",
+        &["192.0.2.10".parse().unwrap()],
+    )
+    .await
+    .unwrap_err();
+
+    let error = error.downcast::<ForeignRecordsError>().unwrap();
+    assert_eq!(error.ownership, Ownership::Foreign("cluster-b".to_string()));
+    let records = mock.records(domain_id);
+    assert_eq!(find(&records, "node-1", "A").unwrap().target, "192.0.2.99");
+    assert_eq!(mock.rdns("192.0.2.10"), None);
+}
+
+#[tokio::test]
+async fn delete_leaves_records_it_does_not_own() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.add_record(domain_id, "node-1", "A", "192.0.2.10");
+    mock.add_record(domain_id, "node-1", "MX", "mail.example.com");
+    claim(&mock, domain_id, "node-1");
+    // Created by hand, without an ownership record
+    mock.add_record(domain_id, "192.0.2.10._spf", "A", "192.0.2.10");
+    let ip: IpAddr = "192.0.2.10".parse().unwrap();
+
+    dns::delete(&mock.provider(), &Registry::default(), DOMAIN, "node-1", &[ip])
+        .await
+        .unwrap();
+
+    let records = mock.records(domain_id);
+    assert!(find(&records, "node-1", "A").is_none());
+    assert!(find(&records, "_node-dns.node-1", "TXT").is_none());
+    assert!(find(&records, "node-1", "MX").is_some());
+    assert!(find(&records, "192.0.2.10._spf", "A").is_some());
+}
+
+#[tokio::test]
+async fn remove_old_addresses_after_ip_change() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.add_ip("192.0.2.10");
+    mock.add_ip("192.0.2.20");
+    let resolvers = mock.resolvers(DOMAIN).await;
+    let providers = mock.providers();
+    let registry = Registry::default();
+    let old_ip: IpAddr = "192.0.2.10".parse().unwrap();
+    let new_ip: IpAddr = "192.0.2.20".parse().unwrap();
+    dns::update(&providers, &registry, &resolvers, DOMAIN, "node-1", &[old_ip])
+        .await
+        .unwrap();
+    dns::update(&providers, &registry, &resolvers, DOMAIN, "node-1", &[new_ip])
+        .await
+        .unwrap();
+
+    dns::remove_old_addresses(&providers, &registry, DOMAIN, "node-1", &[old_ip], &[new_ip], &[])
+        .await
+        .unwrap();
+
+    let records = mock.records(domain_id);
+    assert_eq!(find(&records, "node-1", "A").unwrap().target, "192.0.2.20");
+    assert!(find(&records, "192.0.2.10._spf", "A").is_none());
+    assert!(find(&records, "_node-dns.192.0.2.10._spf", "TXT").is_none());
+    assert!(find(&records, "192.0.2.20._spf", "A").is_some());
+    assert_eq!(mock.rdns("192.0.2.10"), None);
+    assert_eq!(mock.rdns("192.0.2.20"), Some("node-1.k8s.example.com".to_string()));
+}
+
+#[tokio::test]
+async fn remove_old_addresses_keeps_the_glue_of_an_address_another_node_has() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.add_ip("192.0.2.10");
+    mock.add_ip("192.0.2.20");
+    let resolvers = mock.resolvers(DOMAIN).await;
+    let providers = mock.providers();
+    let registry = Registry::default();
+    let old_ip: IpAddr = "192.0.2.10".parse().unwrap();
+    let new_ip: IpAddr = "192.0.2.20".parse().unwrap();
+    dns::update(&providers, &registry, &resolvers, DOMAIN, "node-1", &[new_ip])
+        .await
+        .unwrap();
+    // node-2 took the previous address of node-1
+    dns::update(&providers, &registry, &resolvers, DOMAIN, "node-2", &[old_ip])
+        .await
+        .unwrap();
+
+    dns::remove_old_addresses(&providers, &registry, DOMAIN, "node-1", &[old_ip], &[new_ip], &[old_ip])
+        .await
+        .unwrap();
+
+    let records = mock.records(domain_id);
+    assert!(find(&records, "192.0.2.10._spf", "A").is_some());
+    assert!(find(&records, "_node-dns.192.0.2.10._spf", "TXT").is_some());
+    assert_eq!(mock.rdns("192.0.2.10"), Some("node-2.k8s.example.com".to_string()));
+}
+
+#[tokio::test]
+async fn update_publishes_both_address_families() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.add_ip("192.0.2.10");
+    mock.add_ip("2001:db8::10");
+    let resolvers = mock.resolvers(DOMAIN).await;
+    let ip_addresses: Vec<IpAddr> = vec!["192.0.2.10".parse().unwrap(), "2001:db8::10".parse().unwrap()];
+
+    let outcome = dns::update(
+        &mock.providers(),
+        &Registry::default(),
+        &resolvers,
+        DOMAIN,
+        "node-1",
+        &ip_addresses,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(outcome.propagation, Propagation::Verified);
+    let records = mock.records(domain_id);
+    assert_eq!(find(&records, "node-1", "A").unwrap().target, "192.0.2.10");
+    assert_eq!(find(&records, "node-1", "AAAA").unwrap().target, "2001:db8::10");
+    assert_eq!(find(&records, "192.0.2.10._spf", "A").unwrap().target, "192.0.2.10");
+    // The nibbles of 2001:0db8:0000:0000:0000:0000:0000:0010
+    let spf_glue = "2.0.0.1.0.d.b.8.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.1.0._spf";
+    assert_eq!(find(&records, spf_glue, "A").unwrap().target, "127.0.0.2");
+    assert!(find(&records, spf_glue, "AAAA").is_none());
+    assert_eq!(mock.rdns("192.0.2.10"), Some("node-1.k8s.example.com".to_string()));
+    assert_eq!(mock.rdns("2001:db8::10"), Some("node-1.k8s.example.com".to_string()));
+}
+
+#[tokio::test]
+async fn update_replaces_the_aaaa_spf_glue_of_earlier_versions() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    let spf_glue = "2.0.0.1.0.d.b.8.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.1.0._spf";
+    mock.add_record(domain_id, spf_glue, "AAAA", "2001:db8::10");
+    claim(&mock, domain_id, spf_glue);
+    mock.add_ip("2001:db8::10");
+    let resolvers = mock.resolvers(DOMAIN).await;
+    let ip: IpAddr = "2001:db8::10".parse().unwrap();
+
+    dns::update(
+        &mock.providers(),
+        &Registry::default(),
+        &resolvers,
+        DOMAIN,
+        "node-1",
+        &[ip],
+    )
+    .await
+    .unwrap();
+
+    let records = mock.records(domain_id);
+    assert_eq!(find(&records, spf_glue, "A").unwrap().target, "127.0.0.2");
+    assert!(find(&records, spf_glue, "AAAA").is_none());
+    assert!(find(&records, &format!("_node-dns.{}", spf_glue), "TXT").is_some());
+}
+
+#[tokio::test]
+async fn remove_old_addresses_of_a_lost_family() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.add_ip("192.0.2.10");
+    mock.add_ip("2001:db8::10");
+    let resolvers = mock.resolvers(DOMAIN).await;
+    let providers = mock.providers();
+    let registry = Registry::default();
+    let ipv4: IpAddr = "192.0.2.10".parse().unwrap();
+    let ipv6: IpAddr = "2001:db8::10".parse().unwrap();
+    dns::update(&providers, &registry, &resolvers, DOMAIN, "node-1", &[ipv4, ipv6])
+        .await
+        .unwrap();
+
+    dns::remove_old_addresses(&providers, &registry, DOMAIN, "node-1", &[ipv4, ipv6], &[ipv4], &[])
+        .await
+        .unwrap();
+
+    let records = mock.records(domain_id);
+    assert!(find(&records, "node-1", "A").is_some());
+    assert!(find(&records, "node-1", "AAAA").is_none());
+    assert!(find(&records, "_node-dns.node-1", "TXT").is_some());
+    assert!(records.iter().all(|r| r.type_ != "AAAA"));
+    assert_eq!(mock.rdns("192.0.2.10"), Some("node-1.k8s.example.com".to_string()));
+    assert_eq!(mock.rdns("2001:db8::10"), None);
+}
+
+#[tokio::test]
+async fn resync_deletes_orphans_and_reports_missing_records() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    // node-1 is in sync
+    mock.add_record(domain_id, "node-1", "A", "192.0.2.10");
+    mock.add_record(domain_id, "192.0.2.10._spf", "A", "192.0.2.10");
+    claim(&mock, domain_id, "node-1");
+    claim(&mock, domain_id, "192.0.2.10._spf");
+    // node-2 is gone
+    mock.add_record(domain_id, "node-2", "A", "192.0.2.20");
+    mock.add_record(domain_id, "192.0.2.20._spf", "A", "192.0.2.20");
+    claim(&mock, domain_id, "node-2");
+    claim(&mock, domain_id, "192.0.2.20._spf");
+    mock.add_record(domain_id, "node-1.internal", "A", "10.0.0.1");
+    claim(&mock, domain_id, "node-1.internal");
+    // node-3 lost its SPF glue record
+    mock.add_record(domain_id, "node-3", "A", "192.0.2.30");
+    claim(&mock, domain_id, "node-3");
+    // node-4 lost its internal record
+    mock.add_record(domain_id, "node-4", "A", "192.0.2.40");
+    mock.add_record(domain_id, "192.0.2.40._spf", "A", "192.0.2.40");
+    claim(&mock, domain_id, "node-4");
+    claim(&mock, domain_id, "192.0.2.40._spf");
+    // Not ours
+    mock.add_record(domain_id, "www", "A", "192.0.2.80");
+    let provider = mock.provider();
+    let nodes: HashMap<String, Vec<IpAddr>> = [
+        ("node-1".to_string(), vec!["192.0.2.10".parse().unwrap()]),
+        ("node-3".to_string(), vec!["192.0.2.30".parse().unwrap()]),
+        ("node-4".to_string(), vec!["192.0.2.40".parse().unwrap()]),
+    ]
+    .into_iter()
+    .collect();
+    let internal_addresses: HashMap<String, Vec<IpAddr>> = [
+        ("node-1".to_string(), vec!["10.0.0.1".parse().unwrap()]),
+        ("node-3".to_string(), vec![]),
+        ("node-4".to_string(), vec!["10.0.0.4".parse().unwrap()]),
+    ]
+    .into_iter()
+    .collect();
+    let records = provider.list_zone_records(DOMAIN).await.unwrap();
+
+    let internal_domain = InternalDomain::new("internal.k8s.example.com", DOMAIN);
+    let internal = InternalRecords {
+        domain: &internal_domain,
+        records: &records,
+        nodes: &internal_addresses,
+    };
+    let report = dns::resync(
+        &provider,
+        &Registry::default(),
+        DOMAIN,
+        Some(internal),
+        &records,
+        &nodes,
+        &[],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(report.deleted, vec!["192.0.2.20._spf", "node-2"]);
+    assert_eq!(report.out_of_sync, vec!["node-3", "node-4"]);
+    assert_eq!(report.managed_records, 6);
+    let records = mock.records(domain_id);
+    assert!(find(&records, "node-2", "A").is_none());
+    assert!(find(&records, "192.0.2.20._spf", "A").is_none());
+    assert!(find(&records, "_node-dns.node-2", "TXT").is_none());
+    assert!(find(&records, "node-1", "A").is_some());
+    assert!(find(&records, "node-1.internal", "A").is_some());
+    assert!(find(&records, "node-3", "A").is_some());
+    assert!(find(&records, "www", "A").is_some());
+}
+
+#[tokio::test]
+async fn resync_keeps_the_records_of_unreadable_nodes() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    // node-1 has no address at the moment
+    mock.add_record(domain_id, "node-1", "A", "192.0.2.10");
+    mock.add_record(domain_id, "192.0.2.10._spf", "A", "192.0.2.10");
+    mock.add_record(domain_id, "node-1.internal", "A", "10.0.0.1");
+    claim(&mock, domain_id, "node-1");
+    claim(&mock, domain_id, "192.0.2.10._spf");
+    claim(&mock, domain_id, "node-1.internal");
+    let provider = mock.provider();
+    let records = provider.list_zone_records(DOMAIN).await.unwrap();
+
+    let internal_domain = InternalDomain::new("internal.k8s.example.com", DOMAIN);
+    let internal = InternalRecords {
+        domain: &internal_domain,
+        records: &records,
+        nodes: &HashMap::new(),
+    };
+    let report = dns::resync(
+        &provider,
+        &Registry::default(),
+        DOMAIN,
+        Some(internal),
+        &records,
+        &HashMap::new(),
+        &["node-1".to_string()],
+    )
+    .await
+    .unwrap();
+
+    assert!(report.deleted.is_empty());
+    assert!(report.out_of_sync.is_empty());
+    assert_eq!(mock.records(domain_id).len(), 6);
+}
+
+#[tokio::test]
+async fn internal_records_in_a_subdomain() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    let resolvers = mock.resolvers(DOMAIN).await;
+    let provider = mock.provider();
+    let registry = Registry::default();
+    let internal_domain = InternalDomain::new("internal.k8s.example.com", DOMAIN);
+    let ip: IpAddr = "192.168.128.10".parse().unwrap();
+
+    let outcome = dns::update_internal(
+        &provider,
+        &registry,
+        &resolvers.forward,
+        &internal_domain,
+        "node-1",
+        &[ip],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(outcome.propagation, Propagation::Verified);
+    let records = mock.records(domain_id);
+    assert_eq!(find(&records, "node-1.internal", "A").unwrap().target, "192.168.128.10");
+    assert!(find(&records, "_node-dns.node-1.internal", "TXT").is_some());
+    // No SPF glue nor reverse DNS for the internal addresses
+    assert_eq!(records.len(), 2);
+
+    dns::delete_internal(&provider, &registry, &internal_domain, "node-1")
+        .await
+        .unwrap();
+    assert!(mock.records(domain_id).is_empty());
+}
+
+#[tokio::test]
+async fn internal_records_in_a_zone_of_their_own() {
+    let mock = MockLinode::start().await;
+    mock.add_domain(DOMAIN);
+    let internal_id = mock.add_domain("k8s.example.internal");
+    let resolvers = mock.resolvers("k8s.example.internal").await;
+    let provider = mock.provider();
+    let registry = Registry::default();
+    let internal_domain = InternalDomain::new("k8s.example.internal", DOMAIN);
+    let ipv4: IpAddr = "192.168.128.10".parse().unwrap();
+    let ipv6: IpAddr = "fd00::10".parse().unwrap();
+    dns::update_internal(
+        &provider,
+        &registry,
+        &resolvers.forward,
+        &internal_domain,
+        "node-1",
+        &[ipv4, ipv6],
+    )
+    .await
+    .unwrap();
+
+    // The node lost its IPv6 internal address
+    dns::update_internal(
+        &provider,
+        &registry,
+        &resolvers.forward,
+        &internal_domain,
+        "node-1",
+        &[ipv4],
+    )
+    .await
+    .unwrap();
+
+    let records = mock.records(internal_id);
+    assert_eq!(find(&records, "node-1", "A").unwrap().target, "192.168.128.10");
+    assert!(find(&records, "node-1", "AAAA").is_none());
+    assert!(find(&records, "_node-dns.node-1", "TXT").is_some());
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use common::mock_cloudflare::MockCloudflare;
+use common::mock_linode::MockLinode;
+use node_dns::errors::Error;
+use node_dns::health::Health;
+use node_dns::linode::Client;
+use node_dns::provider::{LinodeProvider, Providers};
+use std::sync::Arc;
+use std::time::Duration;
+
+const LIVENESS_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[tokio::test(start_paused = true)]
+async fn ready_once_validated_and_synced() {
+    let health = Health::new(LIVENESS_TIMEOUT);
+    assert!(!health.is_ready());
+
+    health.credentials_validated();
+    assert!(!health.is_ready());
+
+    drop(health.reconcile_started());
+    assert!(health.is_ready());
+}
+
+#[tokio::test(start_paused = true)]
+async fn not_alive_when_a_reconcile_is_stuck() {
+    let health = Health::new(LIVENESS_TIMEOUT);
+    let finished = health.reconcile_started();
+    let stuck = health.reconcile_started();
+    drop(finished);
+    health.tick();
+
+    tokio::time::advance(LIVENESS_TIMEOUT - Duration::from_secs(1)).await;
+    assert!(health.is_alive());
+    tokio::time::advance(Duration::from_secs(1)).await;
+    assert!(!health.is_alive());
+
+    drop(stuck);
+    health.tick();
+    assert!(health.is_alive());
+}
+
+#[tokio::test(start_paused = true)]
+async fn not_alive_when_the_controller_loop_stops_ticking() {
+    let health = Health::new(LIVENESS_TIMEOUT);
+    drop(health.reconcile_started());
+    health.tick();
+
+    tokio::time::advance(LIVENESS_TIMEOUT - Duration::from_secs(1)).await;
+    assert!(health.is_alive());
+    tokio::time::advance(Duration::from_secs(1)).await;
+    assert!(!health.is_alive());
+
+    // No reconcile is needed in a quiet cluster
+    health.tick();
+    assert!(health.is_alive());
+    health.stopped();
+    assert!(!health.is_alive());
+}
+
+#[tokio::test(start_paused = true)]
+async fn not_alive_when_the_watcher_never_syncs() {
+    let health = Health::new(LIVENESS_TIMEOUT);
+
+    assert!(health.is_alive());
+    tokio::time::advance(LIVENESS_TIMEOUT / 2).await;
+    health.tick();
+    tokio::time::advance(LIVENESS_TIMEOUT / 2).await;
+    health.tick();
+    assert!(!health.is_alive());
+}
+
+#[tokio::test]
+async fn validates_the_credentials_of_both_providers() {
+    let cloudflare = MockCloudflare::start().await;
+    let linode = MockLinode::start().await;
+    let providers = Providers {
+        forward: Arc::new(cloudflare.provider()),
+        reverse: Some(Arc::new(linode.provider())),
+    };
+
+    providers.validate_credentials().await.unwrap();
+    assert_eq!(cloudflare.requests(), vec!["GET user/tokens/verify"]);
+    assert_eq!(linode.requests(), vec!["GET profile"]);
+
+    let providers = Providers {
+        forward: Arc::new(cloudflare.provider()),
+        reverse: Some(Arc::new(LinodeProvider::new(
+            Client::new("wrong-token").with_base_url(&linode.url()),
+        ))),
+    };
+    let error: Error = providers.validate_credentials().await.unwrap_err().into();
+    assert!(matches!(error, Error::Linode(error) if error.is_auth_failure()));
+}
+
+#[tokio::test(start_paused = true)]
+async fn standby_is_ready_and_alive_until_it_leads() {
+    let health = Health::new(LIVENESS_TIMEOUT);
+    health.credentials_validated();
+    health.standby();
+
+    tokio::time::advance(LIVENESS_TIMEOUT * 2).await;
+    assert!(health.is_ready());
+    assert!(health.is_alive());
+
+    health.leading();
+    assert!(!health.is_ready());
+    assert!(health.is_alive());
+    tokio::time::advance(LIVENESS_TIMEOUT).await;
+    assert!(!health.is_alive());
+}
@@ -0,0 +1,76 @@
+use k8s_openapi::api::coordination::v1::LeaseSpec;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::MicroTime;
+use k8s_openapi::chrono::{Duration as ChronoDuration, TimeZone, Utc};
+use node_dns::errors::Error;
+use node_dns::leader::{claim, LeaderElection};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+const LEASE_DURATION: Duration = Duration::from_secs(15);
+
+#[test]
+fn acquires_a_new_or_released_lease() {
+    let now = Utc.ymd(2021, 11, 20).and_hms(10, 30, 0);
+
+    let spec = claim(None, "node-dns-a", LEASE_DURATION, now).unwrap();
+    assert_eq!(spec.holder_identity.as_deref(), Some("node-dns-a"));
+    assert_eq!(spec.lease_duration_seconds, Some(15));
+    assert_eq!(spec.acquire_time, Some(MicroTime(now)));
+    assert_eq!(spec.lease_transitions, Some(0));
+
+    let released = LeaseSpec {
+        holder_identity: None,
+        ..spec
+    };
+    let spec = claim(Some(&released), "node-dns-b", LEASE_DURATION, now).unwrap();
+    assert_eq!(spec.holder_identity.as_deref(), Some("node-dns-b"));
+    assert_eq!(spec.lease_transitions, Some(1));
+}
+
+#[test]
+fn renews_its_own_lease() {
+    let acquired = Utc.ymd(2021, 11, 20).and_hms(10, 30, 0);
+    let now = acquired + ChronoDuration::seconds(60);
+    let held = claim(None, "node-dns-a", LEASE_DURATION, acquired).unwrap();
+
+    let spec = claim(Some(&held), "node-dns-a", LEASE_DURATION, now).unwrap();
+
+    assert_eq!(spec.acquire_time, Some(MicroTime(acquired)));
+    assert_eq!(spec.renew_time, Some(MicroTime(now)));
+    assert_eq!(spec.lease_transitions, Some(0));
+}
+
+#[test]
+fn takes_over_only_an_expired_lease() {
+    let renewed = Utc.ymd(2021, 11, 20).and_hms(10, 30, 0);
+    let held = claim(None, "node-dns-a", LEASE_DURATION, renewed).unwrap();
+
+    let now = renewed + ChronoDuration::seconds(14);
+    assert_eq!(claim(Some(&held), "node-dns-b", LEASE_DURATION, now), None);
+
+    let now = renewed + ChronoDuration::seconds(15);
+    let spec = claim(Some(&held), "node-dns-b", LEASE_DURATION, now).unwrap();
+    assert_eq!(spec.holder_identity.as_deref(), Some("node-dns-b"));
+    assert_eq!(spec.acquire_time, Some(MicroTime(now)));
+    assert_eq!(spec.lease_transitions, Some(1));
+}
+
+#[tokio::test(start_paused = true)]
+async fn loses_the_leadership_when_a_renewal_hangs() {
+    // Accepts the connections, but never answers
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut connections = vec![];
+        while let Ok((connection, _)) = listener.accept().await {
+            connections.push(connection);
+        }
+    });
+    let client = kube::Client::try_from(kube::Config::new(url.parse().unwrap())).unwrap();
+    let leader = LeaderElection::new(client, "default", "node-dns", "node-dns-0");
+
+    let error = tokio::time::timeout(Duration::from_secs(11), leader.hold())
+        .await
+        .unwrap();
+    assert!(matches!(error, Error::LeadershipLost(identity) if identity == "node-dns-0"));
+}
@@ -0,0 +1,234 @@
+mod common;
+
+use common::mock_linode::{MockLinode, RATE_LIMIT};
+use node_dns::config::LinodeConfig;
+use node_dns::dns;
+use node_dns::errors::Error;
+use node_dns::linode::{Client, Filter, LinodeError};
+use node_dns::provider::{DnsProvider, LinodeProvider, Providers};
+use node_dns::registry::Registry;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+const DOMAIN: &str = "k8s.example.com";
+
+#[tokio::test]
+async fn lists_span_multiple_pages() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    for i in 0..60 {
+        mock.add_record(domain_id, &format!("node-{}", i), "A", "192.0.2.10");
+    }
+
+    let records = mock
+        .client()
+        .with_page_size(25)
+        .get_domain_records(domain_id, &Filter::new())
+        .await
+        .unwrap();
+
+    assert_eq!(records.len(), 60);
+    let pages = mock.requests().iter().filter(|r| r.ends_with("/records")).count();
+    assert_eq!(pages, 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn retries_rate_limited_and_failed_requests() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.fail_next(429, Some(10));
+    mock.fail_next(503, None);
+    let client = mock.client();
+
+    let records = client.get_domain_records(domain_id, &Filter::new()).await.unwrap();
+
+    assert!(records.is_empty());
+    assert_eq!(mock.requests().len(), 3);
+    assert_eq!(client.rate_limit().unwrap().remaining, RATE_LIMIT as u64 - 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn gives_up_after_max_retries() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    for _ in 0..3 {
+        mock.fail_next(500, None);
+    }
+
+    let result = mock
+        .client()
+        .with_max_retries(2)
+        .get_domain_records(domain_id, &Filter::new())
+        .await;
+
+    assert!(result.is_err());
+    assert_eq!(mock.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn classifies_api_errors() {
+    let mock = MockLinode::start().await;
+
+    let error = Client::new("bad-token")
+        .with_base_url(&mock.url())
+        .get_domains()
+        .await
+        .unwrap_err();
+    assert!(matches!(error, LinodeError::Unauthorized(_)));
+    assert!(error.is_auth_failure());
+    assert_eq!(error.field_errors()[0].reason, "Invalid Token");
+
+    let error = mock.client().delete_domain_record(42, 1).await.unwrap_err();
+    assert!(matches!(error, LinodeError::NotFound(_)));
+    assert!(!error.is_retryable());
+
+    mock.fail_next(503, None);
+    let error = mock.client().with_max_retries(0).get_domains().await.unwrap_err();
+    assert!(matches!(error, LinodeError::Server { .. }));
+    assert!(error.is_retryable());
+}
+
+#[tokio::test]
+async fn filters_lists_on_server_side() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.add_record(domain_id, "node-1", "A", "192.0.2.10");
+    mock.add_record(domain_id, "node-1", "AAAA", "2001:db8::10");
+    mock.add_record(domain_id, "node-2", "A", "192.0.2.20");
+    let client = mock.client();
+
+    let records = client
+        .get_domain_records(domain_id, &Filter::new().name("node-1"))
+        .await
+        .unwrap();
+    assert_eq!(records.len(), 2);
+
+    let records = client
+        .get_domain_records(domain_id, &Filter::new().name("node-1").type_("A"))
+        .await
+        .unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].target, "192.0.2.10");
+}
+
+#[tokio::test]
+async fn updates_keep_the_other_fields_of_the_record() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    let record_id = mock.add_record(domain_id, "node-1", "A", "192.0.2.99");
+    mock.tag_record(domain_id, record_id, "kubernetes");
+    let provider = mock.provider();
+
+    let mut record = provider.list_records(DOMAIN, "node-1").await.unwrap().remove(0);
+    record.target = "192.0.2.10".to_string();
+    provider.update_record(DOMAIN, &record).await.unwrap();
+
+    let records = mock.records(domain_id);
+    assert_eq!(records[0].target, "192.0.2.10");
+    assert_eq!(records[0].tag.as_deref(), Some("kubernetes"));
+}
+
+#[tokio::test]
+async fn switches_only_to_a_valid_token() {
+    let mock = MockLinode::start().await;
+    mock.accept_token("old-token");
+    let provider = LinodeProvider::new(Client::new("old-token").with_base_url(&mock.url()));
+
+    let error: LinodeError = provider
+        .switch_token("unknown-token")
+        .await
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert!(error.is_auth_failure());
+    provider.validate_credentials().await.unwrap();
+
+    mock.accept_token("new-token");
+    provider.switch_token("new-token").await.unwrap();
+    mock.revoke_token("old-token");
+    provider.validate_credentials().await.unwrap();
+}
+
+#[tokio::test]
+async fn reloads_the_token_file() {
+    let mock = MockLinode::start().await;
+    mock.accept_token("old-token");
+    let token_file = std::env::temp_dir().join(format!("node-dns-linode-token-{}", std::process::id()));
+    std::fs::write(&token_file, "old-token\n").unwrap();
+    let config = LinodeConfig {
+        api_token_file: Some(token_file.to_str().unwrap().to_string()),
+        api_url: mock.url(),
+        ..LinodeConfig::default()
+    };
+    let provider = Arc::new(
+        LinodeProvider::from_config(&config)
+            .unwrap()
+            .with_token_file_poll_interval(Duration::from_millis(10)),
+    );
+    let watched = provider.clone();
+    tokio::spawn(async move { watched.watch_credentials().await });
+
+    // Rejected: the previous token stays in use
+    std::fs::write(&token_file, "unknown-token\n").unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    provider.validate_credentials().await.unwrap();
+
+    mock.accept_token("new-token");
+    std::fs::write(&token_file, "new-token\n").unwrap();
+    mock.revoke_token("old-token");
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while provider.validate_credentials().await.is_err() {
+        assert!(tokio::time::Instant::now() < deadline, "Still using the old token");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    std::fs::remove_file(&token_file).unwrap();
+}
+
+#[tokio::test]
+async fn updates_once_the_rejected_token_is_rotated() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.add_ip("192.0.2.10");
+    let resolvers = mock.resolvers(DOMAIN).await;
+    let token_file = std::env::temp_dir().join(format!("node-dns-rotated-token-{}", std::process::id()));
+    std::fs::write(&token_file, "expired-token\n").unwrap();
+    let config = LinodeConfig {
+        api_token_file: Some(token_file.to_str().unwrap().to_string()),
+        api_url: mock.url(),
+        ..LinodeConfig::default()
+    };
+    let provider = Arc::new(
+        LinodeProvider::from_config(&config)
+            .unwrap()
+            .with_token_file_poll_interval(Duration::from_millis(10)),
+    );
+    let providers = Providers {
+        forward: provider.clone(),
+        reverse: Some(provider.clone()),
+    };
+    let registry = Registry::default();
+    let ip_addresses: Vec<IpAddr> = vec!["192.0.2.10".parse().unwrap()];
+    let update = || dns::update(&providers, &registry, &resolvers, DOMAIN, "node-1", &ip_addresses);
+
+    // Requeued rather than given up on, as the token can still change
+    let error: Error = update().await.unwrap_err().into();
+    assert!(error.is_auth_failure());
+    assert!(providers.rotates_credentials());
+
+    let watched = providers.clone();
+    tokio::spawn(async move { watched.watch_credentials().await });
+    mock.accept_token("new-token");
+    std::fs::write(&token_file, "new-token\n").unwrap();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while update().await.is_err() {
+        assert!(tokio::time::Instant::now() < deadline, "Still using the rejected token");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(mock
+        .records(domain_id)
+        .iter()
+        .any(|r| r.name == "node-1" && r.target == "192.0.2.10"));
+    assert!(!mock.provider().rotates_credentials());
+    std::fs::remove_file(&token_file).unwrap();
+}
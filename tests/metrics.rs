@@ -0,0 +1,38 @@
+mod common;
+
+use common::mock_linode::MockLinode;
+use node_dns::linode::Filter;
+use node_dns::metrics::{self, LINODE_REQUESTS, RECONCILE_DURATION};
+use std::time::Duration;
+
+#[tokio::test(start_paused = true)]
+async fn counts_linode_requests_by_endpoint_and_status() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain("k8s.example.com");
+    mock.fail_next(503, None);
+    let labels = |status| ["domains/{id}/records", status];
+    let succeeded = LINODE_REQUESTS.get(&labels("200"));
+    let failed = LINODE_REQUESTS.get(&labels("503"));
+
+    mock.client()
+        .get_domain_records(domain_id, &Filter::new())
+        .await
+        .unwrap();
+
+    assert_eq!(LINODE_REQUESTS.get(&labels("200")), succeeded + 1);
+    assert_eq!(LINODE_REQUESTS.get(&labels("503")), failed + 1);
+}
+
+#[test]
+fn renders_the_text_exposition_format() {
+    RECONCILE_DURATION.observe(&["test"], Duration::from_millis(200));
+
+    let text = metrics::render();
+
+    assert!(text.contains("# TYPE node_dns_reconcile_duration_seconds histogram\n"));
+    assert!(text.contains("node_dns_reconcile_duration_seconds_bucket{outcome=\"test\",le=\"0.1\"} 0\n"));
+    assert!(text.contains("node_dns_reconcile_duration_seconds_bucket{outcome=\"test\",le=\"0.25\"} 1\n"));
+    assert!(text.contains("node_dns_reconcile_duration_seconds_bucket{outcome=\"test\",le=\"+Inf\"} 1\n"));
+    assert!(text.contains("node_dns_reconcile_duration_seconds_count{outcome=\"test\"} 1\n"));
+    assert!(text.contains("# TYPE node_dns_managed_nodes gauge\nnode_dns_managed_nodes 0\n"));
+}
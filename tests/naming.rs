@@ -0,0 +1,142 @@
+use k8s_openapi::api::core::v1::{Node, NodeAddress, NodeStatus};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use k8s_openapi::chrono::{TimeZone, Utc};
+use kube::api::ObjectMeta;
+use node_dns::naming::{pool_index, validate_label, NameError, NameTemplate, NAME_ANNOTATION, POOL_LABEL};
+use std::collections::BTreeMap;
+
+fn node(labels: &[(&str, &str)], annotations: &[(&str, &str)]) -> Node {
+    let map = |entries: &[(&str, &str)]| {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<BTreeMap<String, String>>()
+    };
+    Node {
+        metadata: ObjectMeta {
+            name: Some("lke1234-5678-abcdef".to_string()),
+            labels: Some(map(labels)),
+            annotations: Some(map(annotations)),
+            ..ObjectMeta::default()
+        },
+        ..Node::default()
+    }
+}
+
+#[test]
+fn renders_placeholders() {
+    let template: NameTemplate = "{region}-pool{label:lke.linode.com/pool-id}-{name}".parse().unwrap();
+    let node = node(
+        &[
+            ("topology.kubernetes.io/region", "us-east"),
+            ("lke.linode.com/pool-id", "5678"),
+        ],
+        &[],
+    );
+
+    assert_eq!(
+        template.render(&node, "ignored", &[]).unwrap(),
+        "us-east-pool5678-lke1234-5678-abcdef"
+    );
+    let template: NameTemplate = "{hostname}".parse().unwrap();
+    assert_eq!(template.render(&node, "Node-1", &[]).unwrap(), "node-1");
+}
+
+#[test]
+fn index_is_the_position_in_the_pool() {
+    let pool_node = |name: &str, pool: &str, created: i64| {
+        let mut node = node(&[(POOL_LABEL, pool)], &[]);
+        node.metadata.name = Some(name.to_string());
+        node.metadata.creation_timestamp = Some(Time(Utc.timestamp(created, 0)));
+        node
+    };
+    let nodes = vec![
+        pool_node("lke1-5678-c", "5678", 300),
+        pool_node("lke1-5678-a", "5678", 100),
+        pool_node("lke1-5679-a", "5679", 50),
+        pool_node("lke1-5678-b", "5678", 100),
+    ];
+    let template: NameTemplate = "pool{label:lke.linode.com/pool-id}-{index}".parse().unwrap();
+
+    let names: Vec<String> = nodes
+        .iter()
+        .map(|node| template.render(node, "ignored", &nodes).unwrap())
+        .collect();
+    assert_eq!(names, vec!["pool5678-2", "pool5678-0", "pool5679-0", "pool5678-1"]);
+    // Once the oldest node is gone
+    assert_eq!(pool_index(&nodes[0], &nodes[2..]), 1);
+}
+
+#[test]
+fn older_node_keeps_a_name() {
+    let named_node = |name: &str, created: i64| {
+        let mut node = node(&[], &[(NAME_ANNOTATION, "gateway")]);
+        node.metadata.name = Some(name.to_string());
+        node.metadata.creation_timestamp = Some(Time(Utc.timestamp(created, 0)));
+        node.status = Some(NodeStatus {
+            addresses: Some(vec![NodeAddress {
+                address: name.to_string(),
+                type_: "Hostname".to_string(),
+            }]),
+            ..NodeStatus::default()
+        });
+        node
+    };
+    let nodes = vec![named_node("node-2", 200), named_node("node-1", 100)];
+    let template: NameTemplate = "{hostname}".parse().unwrap();
+
+    assert_eq!(
+        template.render_unique(&nodes[1], "node-1", &nodes, |_| true).unwrap(),
+        "gateway"
+    );
+    assert_eq!(
+        template
+            .render_unique(&nodes[0], "node-2", &nodes, |_| true)
+            .unwrap_err(),
+        NameError::Taken("gateway".to_string(), "node-1".to_string())
+    );
+    // The older node gets no records
+    assert_eq!(
+        template
+            .render_unique(&nodes[0], "node-2", &nodes, |other| other.metadata.name.as_deref()
+                != Some("node-1"))
+            .unwrap(),
+        "gateway"
+    );
+}
+
+#[test]
+fn annotation_overrides_the_template() {
+    let template: NameTemplate = "{label:missing}".parse().unwrap();
+    let node = node(&[], &[(NAME_ANNOTATION, "gateway")]);
+
+    assert_eq!(template.render(&node, "node-1", &[]).unwrap(), "gateway");
+}
+
+#[test]
+fn rejects_invalid_templates_and_names() {
+    assert_eq!(
+        "{zone}".parse::<NameTemplate>().unwrap_err(),
+        NameError::UnknownPlaceholder("zone".to_string())
+    );
+    assert_eq!(
+        "node-{name".parse::<NameTemplate>().unwrap_err(),
+        NameError::UnclosedPlaceholder
+    );
+
+    let template: NameTemplate = "{label:lke.linode.com/pool-id}".parse().unwrap();
+    assert!(matches!(
+        template.render(&node(&[], &[]), "node-1", &[]),
+        Err(NameError::MissingField(_))
+    ));
+    let node = node(&[], &[(NAME_ANNOTATION, "node_1.example")]);
+    assert!(matches!(
+        template.render(&node, "node-1", &[]),
+        Err(NameError::InvalidLabel(..))
+    ));
+
+    assert!(validate_label("-node").is_err());
+    assert!(validate_label(&"a".repeat(64)).is_err());
+    assert!(validate_label("").is_err());
+    assert_eq!(validate_label(&"a".repeat(63)).unwrap(), "a".repeat(63));
+}
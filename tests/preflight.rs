@@ -0,0 +1,135 @@
+mod common;
+
+use common::mock_linode::MockLinode;
+use common::mock_resolver::{self, Answer};
+use node_dns::errors::Error;
+use node_dns::preflight;
+use serde_json::json;
+use std::str::FromStr;
+use std::sync::Arc;
+use trust_dns_resolver::proto::rr::{RData, RecordType};
+use trust_dns_resolver::{Name, TokioAsyncResolver};
+
+const DOMAIN: &str = "k8s.example.com";
+
+/// Resolver seeing the zone delegated to the given name servers
+async fn delegated_to(name_servers: &[&str]) -> TokioAsyncResolver {
+    let zone = Name::from_str(&format!("{}.", DOMAIN)).unwrap();
+    let name_servers: Vec<Name> = name_servers
+        .iter()
+        .map(|name| Name::from_str(&format!("{}.", name)).unwrap())
+        .collect();
+    let answer: Answer = Arc::new(move |name, record_type| {
+        if *name == zone && record_type == RecordType::NS {
+            name_servers.iter().cloned().map(RData::NS).collect()
+        } else {
+            vec![]
+        }
+    });
+    mock_resolver::answering(answer).await
+}
+
+fn failed(result: Result<(), Error>) -> Vec<String> {
+    match result {
+        Err(Error::PreflightFailed(problems)) => problems,
+        other => panic!("Expected failed preflight checks, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn passes_for_a_master_zone_delegated_to_linode() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    mock.restrict(json!({
+        "domain": [{ "id": domain_id, "label": DOMAIN, "permissions": "read_write" }],
+        "linode": [{ "id": 1, "label": "node-1", "permissions": "read_write" }],
+    }));
+    mock.set_scopes("domains:read_write ips:read_write linodes:read_only");
+    let resolver = delegated_to(&["NS1.linode.com", "ns2.linode.com"]).await;
+    let providers = mock.providers();
+
+    preflight::check(&providers, &[DOMAIN.to_string()], Some(&resolver))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn reports_all_the_problems_together() {
+    let mock = MockLinode::start().await;
+    mock.add_slave_domain(DOMAIN);
+    mock.restrict(json!({ "domain": [], "linode": [{ "id": 1, "label": "node-1", "permissions": "read_only" }] }));
+    let providers = mock.providers();
+    let zones = [DOMAIN.to_string(), "internal.example.com".to_string()];
+
+    let problems = failed(preflight::check(&providers, &zones, None).await);
+
+    assert_eq!(problems.len(), 3, "{:?}", problems);
+    assert!(problems[0].contains("is a slave zone"));
+    assert_eq!(problems[1], "Could not find domain internal.example.com at Linode");
+    assert!(problems[2].contains("no read_write grant on any Linode"));
+}
+
+#[tokio::test]
+async fn requires_the_read_write_scopes() {
+    let mock = MockLinode::start().await;
+    mock.add_domain(DOMAIN);
+    mock.set_scopes("domains:read_only,linodes:read_write");
+    let providers = mock.providers();
+
+    let problems = failed(preflight::check(&providers, &[DOMAIN.to_string()], None).await);
+
+    assert_eq!(problems.len(), 2, "{:?}", problems);
+    assert!(problems[0].contains("domains:read_write is required"));
+    assert!(problems[1].contains("ips:read_write is required"));
+}
+
+#[tokio::test]
+async fn requires_a_grant_on_the_domain_of_a_restricted_user() {
+    let mock = MockLinode::start().await;
+    let domain_id = mock.add_domain(DOMAIN);
+    let other_id = mock.add_domain("example.com");
+    mock.restrict(json!({
+        "domain": [
+            { "id": domain_id, "label": DOMAIN, "permissions": "read_only" },
+            { "id": other_id, "label": "example.com", "permissions": "read_write" },
+        ],
+        "linode": [{ "id": 1, "label": "node-1", "permissions": "read_write" }],
+    }));
+    let providers = mock.providers();
+
+    let problems = failed(preflight::check(&providers, &[DOMAIN.to_string()], None).await);
+
+    assert_eq!(
+        problems,
+        vec!["Linode user node-dns has no read_write grant on the domain k8s.example.com"]
+    );
+}
+
+#[tokio::test]
+async fn requires_the_delegation_to_linode() {
+    let mock = MockLinode::start().await;
+    mock.add_domain(DOMAIN);
+    let providers = mock.providers();
+
+    let resolver = delegated_to(&["ns1.linode.com", "ns1.example.net"]).await;
+    let problems = failed(preflight::check(&providers, &[DOMAIN.to_string()], Some(&resolver)).await);
+    assert_eq!(problems.len(), 1, "{:?}", problems);
+    assert!(problems[0].contains("\"ns1.example.net\""));
+
+    let resolver = delegated_to(&[]).await;
+    let problems = failed(preflight::check(&providers, &[DOMAIN.to_string()], Some(&resolver)).await);
+    assert_eq!(problems.len(), 1, "{:?}", problems);
+}
+
+#[tokio::test]
+async fn stops_at_invalid_credentials() {
+    let mock = MockLinode::start().await;
+    mock.add_domain(DOMAIN);
+    let providers = mock.providers();
+    mock.fail_next(401, None);
+
+    let result = preflight::check(&providers, &[DOMAIN.to_string()], None).await;
+
+    assert!(matches!(result, Err(Error::Linode(_))), "{:?}", result);
+    assert_eq!(mock.requests(), vec!["GET profile"]);
+}
@@ -0,0 +1,86 @@
+use k8s_openapi::api::core::v1::Node;
+use k8s_openapi::chrono::{TimeZone, Utc};
+use kube::api::ObjectMeta;
+use node_dns::reporting::{
+    annotations_patch, clear_annotations_patch, failed_patch, Status, ADDRESSES_ANNOTATION, FQDN_ANNOTATION,
+    LAST_VERIFIED_ANNOTATION, STATUS_ANNOTATION,
+};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+const FQDN: &str = "node-1.k8s.example.com";
+
+fn node(annotations: &[(&str, &str)]) -> Node {
+    Node {
+        metadata: ObjectMeta {
+            name: Some("node-1".to_string()),
+            annotations: Some(
+                annotations
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect::<BTreeMap<String, String>>(),
+            ),
+            ..ObjectMeta::default()
+        },
+        ..Node::default()
+    }
+}
+
+fn addresses() -> Vec<IpAddr> {
+    vec!["192.0.2.10".parse().unwrap(), "2001:db8::10".parse().unwrap()]
+}
+
+#[test]
+fn annotates_the_published_records() {
+    let now = Utc.ymd(2021, 11, 20).and_hms(10, 30, 0);
+
+    let patch = annotations_patch(&node(&[]), FQDN, &addresses(), Status::Pending, now).unwrap();
+    assert_eq!(
+        patch,
+        json!({ "metadata": { "annotations": {
+            FQDN_ANNOTATION: FQDN,
+            ADDRESSES_ANNOTATION: "192.0.2.10,2001:db8::10",
+            STATUS_ANNOTATION: "Pending",
+        } } })
+    );
+
+    let pending = node(&[
+        (FQDN_ANNOTATION, FQDN),
+        (ADDRESSES_ANNOTATION, "192.0.2.10,2001:db8::10"),
+        (STATUS_ANNOTATION, "Pending"),
+    ]);
+    assert_eq!(
+        annotations_patch(&pending, FQDN, &addresses(), Status::Pending, now),
+        None
+    );
+    let patch = annotations_patch(&pending, FQDN, &addresses(), Status::Verified, now).unwrap();
+    assert_eq!(
+        patch["metadata"]["annotations"][LAST_VERIFIED_ANNOTATION],
+        "2021-11-20T10:30:00Z"
+    );
+}
+
+#[test]
+fn failure_keeps_the_published_records() {
+    let verified = node(&[(FQDN_ANNOTATION, FQDN), (STATUS_ANNOTATION, "Verified")]);
+
+    assert_eq!(
+        failed_patch(&verified).unwrap(),
+        json!({ "metadata": { "annotations": { STATUS_ANNOTATION: "Failed" } } })
+    );
+    assert_eq!(failed_patch(&node(&[(STATUS_ANNOTATION, "Failed")])), None);
+}
+
+#[test]
+fn clears_only_annotated_nodes() {
+    assert_eq!(clear_annotations_patch(&node(&[("other", "value")])), None);
+
+    let patch = clear_annotations_patch(&node(&[(FQDN_ANNOTATION, FQDN)])).unwrap();
+    assert_eq!(patch["metadata"]["annotations"][FQDN_ANNOTATION], Value::Null);
+    assert_eq!(patch["metadata"]["annotations"][STATUS_ANNOTATION], Value::Null);
+    assert!(patch["metadata"]["annotations"]
+        .as_object()
+        .unwrap()
+        .contains_key(LAST_VERIFIED_ANNOTATION));
+}
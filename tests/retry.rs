@@ -0,0 +1,16 @@
+use node_dns::retry::{self, MAX_DELAY};
+use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+use std::time::Duration;
+
+#[test]
+fn waits_at_most_the_maximum_delay() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(retry::retry_after(&headers), None);
+
+    headers.insert(RETRY_AFTER, HeaderValue::from_static("2"));
+    assert_eq!(retry::retry_after(&headers), Some(Duration::from_secs(2)));
+    headers.insert(RETRY_AFTER, HeaderValue::from_static("86400"));
+    assert_eq!(retry::retry_after(&headers), Some(MAX_DELAY));
+
+    assert!((0..64).all(|attempt| retry::backoff(attempt) <= MAX_DELAY));
+}
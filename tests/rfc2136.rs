@@ -0,0 +1,153 @@
+mod common;
+
+use common::mock_dns::MockDns;
+use common::mock_resolver;
+use node_dns::dns::{self, Propagation};
+use node_dns::provider::{DnsProvider, Providers, Record, ReverseDnsProvider, Rfc2136Provider};
+use node_dns::registry::Registry;
+use node_dns::tsig;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+const DOMAIN: &str = "k8s.example.com";
+
+const REVERSE_ZONE: &str = "2.0.192.in-addr.arpa";
+
+fn provider(mock: &MockDns) -> Rfc2136Provider {
+    Rfc2136Provider::new(&mock.server(), Some(MockDns::key())).with_reverse_zones(&[REVERSE_ZONE])
+}
+
+#[tokio::test]
+async fn creates_lists_and_deletes_records() {
+    let mock = MockDns::start(Some(MockDns::key())).await;
+    mock.add_record("node-1.k8s.example.com", "TXT", "hand-made");
+    let provider = provider(&mock);
+
+    let record = Record::new("node-1", "A", "192.0.2.10", 300);
+    provider.create_record(DOMAIN, &record).await.unwrap();
+    assert_eq!(mock.records("node-1.k8s.example.com", "A"), vec!["192.0.2.10"]);
+
+    let mut records = provider.list_records(DOMAIN, "node-1").await.unwrap();
+    records.sort_by(|a, b| a.type_.cmp(&b.type_));
+    assert_eq!(
+        records,
+        vec![record.clone(), Record::new("node-1", "TXT", "hand-made", 300)]
+    );
+
+    provider.delete_record(DOMAIN, &record).await.unwrap();
+    assert!(mock.records("node-1.k8s.example.com", "A").is_empty());
+    assert_eq!(mock.records("node-1.k8s.example.com", "TXT"), vec!["hand-made"]);
+}
+
+#[tokio::test]
+async fn update_replaces_the_record_set() {
+    let mock = MockDns::start(Some(MockDns::key())).await;
+    mock.add_record("node-1.k8s.example.com", "A", "192.0.2.98");
+    mock.add_record("node-1.k8s.example.com", "A", "192.0.2.99");
+    let provider = provider(&mock);
+
+    let record = Record::new("node-1", "A", "192.0.2.10", 300);
+    provider.update_record(DOMAIN, &record).await.unwrap();
+
+    assert_eq!(mock.records("node-1.k8s.example.com", "A"), vec!["192.0.2.10"]);
+}
+
+#[tokio::test]
+async fn sets_ptr_in_configured_reverse_zones_only() {
+    let mock = MockDns::start(Some(MockDns::key())).await;
+    let provider = provider(&mock);
+
+    let ip: IpAddr = "192.0.2.10".parse().unwrap();
+    provider.set_ptr(ip, "node-1.k8s.example.com").await.unwrap();
+    assert_eq!(
+        mock.records("10.2.0.192.in-addr.arpa", "PTR"),
+        vec!["node-1.k8s.example.com."]
+    );
+
+    let ip: IpAddr = "198.51.100.10".parse().unwrap();
+    provider.set_ptr(ip, "node-2.k8s.example.com").await.unwrap();
+    assert!(mock.records("10.100.51.198.in-addr.arpa", "PTR").is_empty());
+}
+
+#[tokio::test]
+async fn resets_ptr_only_when_it_points_to_the_node() {
+    let mock = MockDns::start(Some(MockDns::key())).await;
+    let provider = provider(&mock);
+    let ip: IpAddr = "192.0.2.10".parse().unwrap();
+    provider.set_ptr(ip, "node-2.k8s.example.com").await.unwrap();
+
+    provider.reset_ptr(ip, "node-1.k8s.example.com").await.unwrap();
+    assert_eq!(
+        mock.records("10.2.0.192.in-addr.arpa", "PTR"),
+        vec!["node-2.k8s.example.com."]
+    );
+
+    provider.reset_ptr(ip, "node-2.k8s.example.com").await.unwrap();
+    assert!(mock.records("10.2.0.192.in-addr.arpa", "PTR").is_empty());
+}
+
+#[tokio::test]
+async fn unsigned_and_wrongly_signed_updates_are_refused() {
+    let mock = MockDns::start(Some(MockDns::key())).await;
+    let record = Record::new("node-1", "A", "192.0.2.10", 300);
+
+    let unsigned = Rfc2136Provider::new(&mock.server(), None);
+    assert!(unsigned.create_record(DOMAIN, &record).await.is_err());
+
+    let other_key = tsig::Key::new(
+        common::mock_dns::KEY_NAME,
+        tsig::Algorithm::HmacSha256,
+        "c2VjcmV0IHRoYXQgdGhlIHNlcnZlciBkb2VzIG5vdCBrbm93",
+    )
+    .unwrap();
+    let wrongly_signed = Rfc2136Provider::new(&mock.server(), Some(other_key));
+    let error = wrongly_signed.create_record(DOMAIN, &record).await.unwrap_err();
+    assert!(error.to_string().contains("Not authorized"), "{}", error);
+
+    assert!(mock.records("node-1.k8s.example.com", "A").is_empty());
+}
+
+#[tokio::test]
+async fn delete_removes_forward_and_spf_records() {
+    let mock = MockDns::start(Some(MockDns::key())).await;
+    mock.add_record("node-1.k8s.example.com", "A", "192.0.2.10");
+    mock.add_record("192.0.2.10._spf.k8s.example.com", "A", "192.0.2.10");
+    let owner = "heritage=node-dns,node-dns/owner=default";
+    mock.add_record("_node-dns.node-1.k8s.example.com", "TXT", owner);
+    mock.add_record("_node-dns.192.0.2.10._spf.k8s.example.com", "TXT", owner);
+    mock.add_record("node-2.k8s.example.com", "A", "192.0.2.20");
+    let ip: IpAddr = "192.0.2.10".parse().unwrap();
+
+    dns::delete(&provider(&mock), &Registry::default(), DOMAIN, "node-1", &[ip])
+        .await
+        .unwrap();
+
+    assert!(mock.records("node-1.k8s.example.com", "A").is_empty());
+    assert!(mock.records("192.0.2.10._spf.k8s.example.com", "A").is_empty());
+    assert!(mock.records("_node-dns.node-1.k8s.example.com", "TXT").is_empty());
+    assert_eq!(mock.records("node-2.k8s.example.com", "A"), vec!["192.0.2.20"]);
+}
+
+#[tokio::test]
+async fn update_publishes_forward_spf_and_reverse_records() {
+    let mock = MockDns::start(Some(MockDns::key())).await;
+    let provider = Arc::new(provider(&mock));
+    let providers = Providers {
+        forward: provider.clone(),
+        reverse: Some(provider),
+    };
+    let resolvers = mock_resolver::resolvers(mock.answer(), mock.answer()).await;
+    let ip: IpAddr = "192.0.2.10".parse().unwrap();
+
+    let outcome = dns::update(&providers, &Registry::default(), &resolvers, DOMAIN, "node-1", &[ip])
+        .await
+        .unwrap();
+
+    assert_eq!(outcome.propagation, Propagation::Verified);
+    assert_eq!(mock.records("node-1.k8s.example.com", "A"), vec!["192.0.2.10"]);
+    assert_eq!(mock.records("192.0.2.10._spf.k8s.example.com", "A"), vec!["192.0.2.10"]);
+    assert_eq!(
+        mock.records("10.2.0.192.in-addr.arpa", "PTR"),
+        vec!["node-1.k8s.example.com."]
+    );
+}
@@ -0,0 +1,63 @@
+use k8s_openapi::api::core::v1::Node;
+use kube::api::ObjectMeta;
+use node_dns::selection::{NodeSelector, SKIP_ANNOTATION};
+use std::collections::BTreeMap;
+
+fn node(labels: &[(&str, &str)], annotations: &[(&str, &str)]) -> Node {
+    let map = |entries: &[(&str, &str)]| {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<BTreeMap<String, String>>()
+    };
+    Node {
+        metadata: ObjectMeta {
+            labels: Some(map(labels)),
+            annotations: Some(map(annotations)),
+            ..ObjectMeta::default()
+        },
+        ..Node::default()
+    }
+}
+
+#[test]
+fn empty_selector_matches_every_node_not_skipped() {
+    let selector = NodeSelector::default();
+
+    assert!(selector.matches(&node(&[], &[])));
+    assert!(selector.matches(&node(&[], &[(SKIP_ANNOTATION, "false")])));
+    assert!(!selector.matches(&node(&[], &[(SKIP_ANNOTATION, "true")])));
+}
+
+#[test]
+fn matches_every_requirement() {
+    let selector: NodeSelector = "lke.linode.com/pool-id in (5678, 5679),role=mail,!spot,tier!=test,zone"
+        .parse()
+        .unwrap();
+    let labels = [
+        ("lke.linode.com/pool-id", "5679"),
+        ("role", "mail"),
+        ("tier", "prod"),
+        ("zone", "a"),
+    ];
+
+    assert!(selector.matches(&node(&labels, &[])));
+    assert!(!selector.matches(&node(&labels, &[(SKIP_ANNOTATION, "true")])));
+    assert!(!selector.matches(&node(&labels[1..], &[])));
+    assert!(!selector.matches(&node(&[labels[0], ("role", "web"), labels[2], labels[3]], &[])));
+    assert!(!selector.matches(&node(&[labels[0], labels[1], labels[2], labels[3], ("spot", "")], &[])));
+    assert!(!selector.matches(&node(&[labels[0], labels[1], ("tier", "test"), labels[3]], &[])));
+    assert!(!selector.matches(&node(&labels[..3], &[])));
+
+    let selector: NodeSelector = "role notin (web,batch)".parse().unwrap();
+    assert!(selector.matches(&node(&[("role", "mail")], &[])));
+    assert!(selector.matches(&node(&[], &[])));
+    assert!(!selector.matches(&node(&[("role", "batch")], &[])));
+}
+
+#[test]
+fn rejects_invalid_selectors() {
+    assert!("role in web".parse::<NodeSelector>().is_err());
+    assert!("=mail".parse::<NodeSelector>().is_err());
+    assert!("role=mail,,zone".parse::<NodeSelector>().is_err());
+}
@@ -0,0 +1,153 @@
+use k8s_openapi::api::core::v1::Node;
+use k8s_openapi::chrono::{TimeZone, Utc};
+use kube::api::ObjectMeta;
+use node_dns::reporting::clear_annotations_patch;
+use node_dns::state::{forget_patch, AppliedState, STATE_ANNOTATION};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+fn node(annotations: &[(&str, &str)]) -> Node {
+    Node {
+        metadata: ObjectMeta {
+            name: Some("node-1".to_string()),
+            annotations: Some(
+                annotations
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect::<BTreeMap<String, String>>(),
+            ),
+            ..ObjectMeta::default()
+        },
+        ..Node::default()
+    }
+}
+
+fn addresses() -> Vec<IpAddr> {
+    vec!["192.0.2.10".parse().unwrap(), "2001:db8::10".parse().unwrap()]
+}
+
+fn internal_addresses() -> Vec<IpAddr> {
+    vec!["10.0.0.10".parse().unwrap()]
+}
+
+fn applied_state() -> AppliedState {
+    AppliedState {
+        fqdn: "node-1.k8s.example.com".to_string(),
+        host_name: "node-1".to_string(),
+        internal_fqdn: Some("node-1.internal.k8s.example.com".to_string()),
+        provider: "linode".to_string(),
+        reverse_provider: "linode".to_string(),
+        ip_addresses: addresses(),
+        internal_ip_addresses: internal_addresses(),
+        applied_at: Utc.ymd(2021, 11, 20).and_hms(10, 30, 0),
+    }
+}
+
+#[test]
+fn reads_back_the_saved_state() {
+    let patch = applied_state().patch();
+    let annotation = patch["metadata"]["annotations"][STATE_ANNOTATION].as_str().unwrap();
+    assert_eq!(
+        serde_json::from_str::<Value>(annotation).unwrap(),
+        json!({
+            "fqdn": "node-1.k8s.example.com",
+            "hostName": "node-1",
+            "internalFqdn": "node-1.internal.k8s.example.com",
+            "provider": "linode",
+            "reverseProvider": "linode",
+            "ipAddresses": ["192.0.2.10", "2001:db8::10"],
+            "internalIpAddresses": ["10.0.0.10"],
+            "appliedAt": "2021-11-20T10:30:00Z",
+        })
+    );
+
+    let state = AppliedState::from_node(&node(&[(STATE_ANNOTATION, annotation)])).unwrap();
+    assert_eq!(state, applied_state());
+}
+
+#[test]
+fn matches_only_the_same_names_addresses_and_providers() {
+    let state = applied_state();
+
+    assert!(state.matches(&AppliedState {
+        applied_at: Utc::now(),
+        ..applied_state()
+    }));
+    let changes = [
+        AppliedState {
+            fqdn: "node-1.k8s.example.org".to_string(),
+            ..applied_state()
+        },
+        AppliedState {
+            host_name: "node-2".to_string(),
+            ..applied_state()
+        },
+        AppliedState {
+            internal_fqdn: None,
+            ..applied_state()
+        },
+        AppliedState {
+            internal_fqdn: Some("node-1.k8s.example.internal".to_string()),
+            ..applied_state()
+        },
+        AppliedState {
+            provider: "cloudflare".to_string(),
+            ..applied_state()
+        },
+        AppliedState {
+            reverse_provider: "none".to_string(),
+            ..applied_state()
+        },
+        AppliedState {
+            ip_addresses: addresses()[..1].to_vec(),
+            ..applied_state()
+        },
+        AppliedState {
+            internal_ip_addresses: vec![],
+            ..applied_state()
+        },
+    ];
+    for changed in changes.iter() {
+        assert!(!state.matches(changed), "{:?}", changed);
+    }
+}
+
+#[test]
+fn reads_the_state_of_earlier_versions() {
+    let annotation = json!({
+        "fqdn": "node-1.k8s.example.com",
+        "hostName": "node-1",
+        "ipAddresses": ["192.0.2.10", "2001:db8::10"],
+        "appliedAt": "2021-11-20T10:30:00Z",
+    })
+    .to_string();
+    let state = AppliedState::from_node(&node(&[(STATE_ANNOTATION, &annotation)])).unwrap();
+
+    // Verified again, the providers are unknown
+    assert_eq!(state.provider, "");
+    assert!(!state.matches(&applied_state()));
+}
+
+#[test]
+fn ignores_a_missing_or_unreadable_state() {
+    assert_eq!(AppliedState::from_node(&node(&[])), None);
+    assert_eq!(AppliedState::from_node(&node(&[(STATE_ANNOTATION, "not json")])), None);
+    assert_eq!(
+        AppliedState::from_node(&node(&[(STATE_ANNOTATION, r#"{"hostName":"node-1"}"#)])),
+        None
+    );
+}
+
+#[test]
+fn forgets_the_state() {
+    assert_eq!(
+        forget_patch(),
+        json!({ "metadata": { "annotations": { STATE_ANNOTATION: null } } })
+    );
+
+    let state = applied_state().patch();
+    let annotation = state["metadata"]["annotations"][STATE_ANNOTATION].as_str().unwrap();
+    let patch = clear_annotations_patch(&node(&[(STATE_ANNOTATION, annotation)])).unwrap();
+    assert_eq!(patch["metadata"]["annotations"][STATE_ANNOTATION], Value::Null);
+}
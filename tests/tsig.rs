@@ -0,0 +1,73 @@
+//! Signatures checked against messages encoded and signed byte by byte as RFC 8945 describes, independently of
+//! `tsig.rs`: an update of `node-1.example.com` to 192.0.2.10, and the answer of the server.
+
+use node_dns::tsig::{Algorithm, Key};
+
+const SECRET: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+const TIME_SIGNED: u64 = 1_700_000_000;
+
+const REQUEST: &[&str] = &[
+    "123428000001000000010000076578616d706c6503636f6d0000060001066e6f64652d31076578616d706c6503636f6d",
+    "00000100010000012c0004c000020a",
+];
+
+const SIGNED_REQUEST: &[&str] = &[
+    "123428000001000000010001076578616d706c6503636f6d0000060001066e6f64652d31076578616d706c6503636f6d",
+    "00000100010000012c0004c000020a086e6f64652d646e730000fa00ff00000000003d0b686d61632d73686132353600",
+    "00006553f100012c002038648d27babf90c54e4b715086379fd007488817d18c811286494741d6a041bc123400000000",
+];
+
+const REQUEST_MAC: &[&str] = &["38648d27babf90c54e4b715086379fd007488817d18c811286494741d6a041bc"];
+
+const SIGNED_RESPONSE: &[&str] = &[
+    "1234a8000001000000000001076578616d706c6503636f6d0000060001086e6f64652d646e730000fa00ff0000000000",
+    "3d0b686d61632d7368613235360000006553f100012c0020989003d561e0438b4374b8fb6bee9e267a8857ab78cce3e4",
+    "1ca1f10f9cee8660123400000000",
+];
+
+const REQUEST_MAC_SHA512: &[&str] = &[
+    "4368359a7ee18bc118d840c1e2e05f28caacfbf9fc6de16ba64bf9da9f33a035843a79c01797437f729a8bb75d8fd0e0",
+    "af3308f94ecd4c3d31c8bb556c81164e",
+];
+
+fn bytes(hex: &[&str]) -> Vec<u8> {
+    let hex = hex.concat();
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn signs_as_the_rfc_describes() {
+    // The key name is signed in its canonical, lowercase form
+    let key = Key::new("Node-DNS", Algorithm::HmacSha256, SECRET).unwrap();
+
+    let (signed, mac) = key.sign_at(&bytes(REQUEST), None, TIME_SIGNED).unwrap();
+
+    assert_eq!(mac, bytes(REQUEST_MAC));
+    assert_eq!(signed, bytes(SIGNED_REQUEST));
+
+    let key = Key::new("node-dns", Algorithm::HmacSha512, SECRET).unwrap();
+    let (_, mac) = key.sign_at(&bytes(REQUEST), None, TIME_SIGNED).unwrap();
+    assert_eq!(mac, bytes(REQUEST_MAC_SHA512));
+}
+
+#[test]
+fn verifies_the_response_with_the_request_mac() {
+    let key = Key::new("node-dns", Algorithm::HmacSha256, SECRET).unwrap();
+    let request_mac = bytes(REQUEST_MAC);
+    let response = bytes(SIGNED_RESPONSE);
+
+    let (unsigned, _) = key.verify_at(&response, Some(&request_mac), TIME_SIGNED + 10).unwrap();
+    assert_eq!(&unsigned[10..12], &[0, 0]);
+
+    // The response signature covers the request one
+    assert!(key.verify_at(&response, None, TIME_SIGNED).is_err());
+    // Signed more than the fudge of 300 seconds ago
+    assert!(key.verify_at(&response, Some(&request_mac), TIME_SIGNED + 301).is_err());
+    let mut tampered = response.clone();
+    tampered[3] = 0x05;
+    assert!(key.verify_at(&tampered, Some(&request_mac), TIME_SIGNED).is_err());
+}